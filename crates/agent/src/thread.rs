@@ -569,6 +569,21 @@ pub struct ToolCallAuthorization {
     pub response: oneshot::Sender<acp::PermissionOptionId>,
 }
 
+/// How a tool call is treated before it runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToolCallGate {
+    /// Run the tool, asking for confirmation only if the tool itself does
+    Allow,
+    /// Ask the user to confirm this call before running the tool, even if tool
+    /// actions are always allowed
+    Confirm,
+    /// Don't run the tool, reporting an error to the model instead
+    Deny,
+}
+
+/// Decides how each tool call of the given kind is treated before it runs
+pub type ToolGate = Rc<dyn Fn(acp::ToolKind, &App) -> ToolCallGate>;
+
 #[derive(Debug, thiserror::Error)]
 enum CompletionError {
     #[error("max tokens")]
@@ -614,6 +629,8 @@ pub struct Thread {
     pub(crate) action_log: Entity<ActionLog>,
     /// Tracks the last time files were read by the agent, to detect external modifications
     pub(crate) file_read_times: HashMap<PathBuf, fs::MTime>,
+    /// Checked for every tool call before it runs, whether or not the tool asks for confirmation
+    tool_gate: Option<ToolGate>,
 }
 
 impl Thread {
@@ -673,6 +690,7 @@ impl Thread {
             project,
             action_log,
             file_read_times: HashMap::default(),
+            tool_gate: None,
         }
     }
 
@@ -869,6 +887,7 @@ impl Thread {
             prompt_capabilities_tx,
             prompt_capabilities_rx,
             file_read_times: HashMap::default(),
+            tool_gate: None,
         }
     }
 
@@ -1025,6 +1044,10 @@ impl Thread {
         self.tools.remove(name).is_some()
     }
 
+    pub fn set_tool_gate(&mut self, gate: impl Fn(acp::ToolKind, &App) -> ToolCallGate + 'static) {
+        self.tool_gate = Some(Rc::new(gate));
+    }
+
     pub fn profile(&self) -> &AgentProfileId {
         &self.profile_id
     }
@@ -1550,7 +1573,7 @@ impl Thread {
             event_stream.send_tool_call(
                 &tool_use.id,
                 &tool_use.name,
-                title,
+                title.clone(),
                 kind,
                 tool_use.input.clone(),
             );
@@ -1561,7 +1584,7 @@ impl Thread {
             event_stream.update_tool_call_fields(
                 &tool_use.id,
                 acp::ToolCallUpdateFields {
-                    title: Some(title.into()),
+                    title: Some(title.clone().into()),
                     kind: Some(kind),
                     raw_input: Some(tool_use.input.clone()),
                     ..Default::default()
@@ -1584,6 +1607,24 @@ impl Thread {
             }));
         };
 
+        let gate = self
+            .tool_gate
+            .as_ref()
+            .map_or(ToolCallGate::Allow, |gate| gate(kind, cx));
+        if gate == ToolCallGate::Deny {
+            let content = format!(
+                "The {} tool isn't permitted in the current mode",
+                tool_use.name
+            );
+            return Some(Task::ready(LanguageModelToolResult {
+                content: LanguageModelToolResultContent::Text(Arc::from(content)),
+                tool_use_id: tool_use.id,
+                tool_name: tool_use.name,
+                is_error: true,
+                output: None,
+            }));
+        }
+
         let fs = self.project.read(cx).fs().clone();
         let tool_event_stream =
             ToolCallEventStream::new(tool_use.id.clone(), event_stream.clone(), Some(fs));
@@ -1592,7 +1633,19 @@ impl Thread {
             ..Default::default()
         });
         let supports_images = self.model().is_some_and(|model| model.supports_images());
-        let tool_result = tool.run(tool_use.input, tool_event_stream, cx);
+        let tool_result = if gate == ToolCallGate::Confirm {
+            let confirmation = tool_event_stream.confirm(title, cx);
+            // The tool needn't ask again once the user has confirmed the call
+            let tool_event_stream = tool_event_stream.into_authorized();
+            let input = tool_use.input;
+            cx.spawn(async move |_, cx| {
+                confirmation.await?;
+                cx.update(|cx| tool.run(input, tool_event_stream, cx))?
+                    .await
+            })
+        } else {
+            tool.run(tool_use.input, tool_event_stream, cx)
+        };
         log::debug!("Running tool {}", tool_use.name);
         Some(cx.foreground_executor().spawn(async move {
             let tool_result = tool_result.await.and_then(|output| {
@@ -2437,6 +2490,8 @@ pub struct ToolCallEventStream {
     tool_use_id: LanguageModelToolUseId,
     stream: ThreadEventStream,
     fs: Option<Arc<dyn Fs>>,
+    /// Whether the user already confirmed this call, so `authorize` needn't ask
+    authorized: bool,
 }
 
 impl ToolCallEventStream {
@@ -2458,6 +2513,14 @@ impl ToolCallEventStream {
             tool_use_id,
             stream,
             fs,
+            authorized: false,
+        }
+    }
+
+    fn into_authorized(self) -> Self {
+        Self {
+            authorized: true,
+            ..self
         }
     }
 
@@ -2480,10 +2543,54 @@ impl ToolCallEventStream {
     }
 
     pub fn authorize(&self, title: impl Into<String>, cx: &mut App) -> Task<Result<()>> {
-        if agent_settings::AgentSettings::get_global(cx).always_allow_tool_actions {
+        if self.authorized
+            || agent_settings::AgentSettings::get_global(cx).always_allow_tool_actions
+        {
             return Task::ready(Ok(()));
         }
 
+        self.request_authorization(title, true, cx)
+    }
+
+    /// Ask the user to confirm this call, even if tool actions are always allowed.
+    ///
+    /// "Always Allow" isn't offered, since the next call must be confirmed too.
+    pub fn confirm(&self, title: impl Into<String>, cx: &mut App) -> Task<Result<()>> {
+        self.request_authorization(title, false, cx)
+    }
+
+    fn request_authorization(
+        &self,
+        title: impl Into<String>,
+        offer_always_allow: bool,
+        cx: &mut App,
+    ) -> Task<Result<()>> {
+        let mut options = vec![
+            acp::PermissionOption {
+                id: acp::PermissionOptionId("allow".into()),
+                name: "Allow".into(),
+                kind: acp::PermissionOptionKind::AllowOnce,
+                meta: None,
+            },
+            acp::PermissionOption {
+                id: acp::PermissionOptionId("deny".into()),
+                name: "Deny".into(),
+                kind: acp::PermissionOptionKind::RejectOnce,
+                meta: None,
+            },
+        ];
+        if offer_always_allow {
+            options.insert(
+                0,
+                acp::PermissionOption {
+                    id: acp::PermissionOptionId("always_allow".into()),
+                    name: "Always Allow".into(),
+                    kind: acp::PermissionOptionKind::AllowAlways,
+                    meta: None,
+                },
+            );
+        }
+
         let (response_tx, response_rx) = oneshot::channel();
         self.stream
             .0
@@ -2497,26 +2604,7 @@ impl ToolCallEventStream {
                             ..Default::default()
                         },
                     },
-                    options: vec![
-                        acp::PermissionOption {
-                            id: acp::PermissionOptionId("always_allow".into()),
-                            name: "Always Allow".into(),
-                            kind: acp::PermissionOptionKind::AllowAlways,
-                            meta: None,
-                        },
-                        acp::PermissionOption {
-                            id: acp::PermissionOptionId("allow".into()),
-                            name: "Allow".into(),
-                            kind: acp::PermissionOptionKind::AllowOnce,
-                            meta: None,
-                        },
-                        acp::PermissionOption {
-                            id: acp::PermissionOptionId("deny".into()),
-                            name: "Deny".into(),
-                            kind: acp::PermissionOptionKind::RejectOnce,
-                            meta: None,
-                        },
-                    ],
+                    options,
                     response: response_tx,
                 },
             )))
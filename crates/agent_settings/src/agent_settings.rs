@@ -2,16 +2,19 @@ mod agent_profile;
 
 use std::sync::Arc;
 
-use collections::IndexMap;
+use collections::{HashMap, IndexMap};
 use gpui::{App, Pixels, px};
 use language_model::LanguageModel;
 use project::DisableAiSettings;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{
-    DefaultAgentView, DockPosition, LanguageModelParameters, LanguageModelSelection,
-    NotifyWhenAgentWaiting, RegisterSetting, Settings,
+    CodeTemplateContent, DefaultAgentView, DockPosition, LanguageModelParameters,
+    LanguageModelSelection, NotifyWhenAgentWaiting, RegisterSetting, Settings,
+    VisualIndicatorSettingsContent,
 };
+use util::ResultExt as _;
+use util::paths::{PathMatcher, PathStyle};
 
 pub use crate::agent_profile::*;
 
@@ -45,6 +48,15 @@ pub struct AgentSettings {
     pub expand_terminal_card: bool,
     pub use_modifier_to_send: bool,
     pub message_editor_min_lines: usize,
+    pub quick_edit_response_reserve: u32,
+    pub quick_edit_max_retries: u32,
+    pub quick_edit_prompt_history_size: usize,
+    pub quick_edit_context_lines: u32,
+    pub sensitive_paths: PathMatcher,
+    pub code_templates: Vec<CodeTemplateContent>,
+    pub visual_indicators: VisualIndicatorSettingsContent,
+    pub default_mode: Option<String>,
+    pub language_default_modes: HashMap<String, String>,
 }
 
 impl AgentSettings {
@@ -177,6 +189,20 @@ impl Settings for AgentSettings {
             expand_terminal_card: agent.expand_terminal_card.unwrap(),
             use_modifier_to_send: agent.use_modifier_to_send.unwrap(),
             message_editor_min_lines: agent.message_editor_min_lines.unwrap(),
+            quick_edit_response_reserve: agent.quick_edit_response_reserve.unwrap(),
+            quick_edit_max_retries: agent.quick_edit_max_retries.unwrap(),
+            quick_edit_prompt_history_size: agent.quick_edit_prompt_history_size.unwrap(),
+            quick_edit_context_lines: agent.quick_edit_context_lines.unwrap(),
+            sensitive_paths: PathMatcher::new(
+                agent.sensitive_path_patterns.unwrap(),
+                PathStyle::local(),
+            )
+            .log_err()
+            .unwrap_or_default(),
+            code_templates: agent.code_templates.unwrap_or_default(),
+            visual_indicators: agent.visual_indicators.unwrap_or_default(),
+            default_mode: agent.default_mode,
+            language_default_modes: agent.language_default_modes.unwrap_or_default(),
         }
     }
 }
@@ -39,7 +39,7 @@ use prompt_store::{PromptId, PromptStore};
 use rope::Point;
 use settings::{NotifyWhenAgentWaiting, Settings as _, SettingsStore};
 use std::cell::RefCell;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 use std::{collections::BTreeMap, rc::Rc, time::Duration};
@@ -61,6 +61,7 @@ use crate::acp::ModeSelector;
 use crate::acp::entry_view_state::{EntryViewEvent, ViewEvent};
 use crate::acp::message_editor::{MessageEditor, MessageEditorEvent};
 use crate::agent_diff::AgentDiff;
+use crate::agent_modes::{ToolPermission, tool_for_kind};
 use crate::profile_selector::{ProfileProvider, ProfileSelector};
 
 use crate::ui::{
@@ -68,9 +69,9 @@ use crate::ui::{
     UsageCallout,
 };
 use crate::{
-    AgentDiffPane, AgentPanel, AllowAlways, AllowOnce, ContinueThread, ContinueWithBurnMode,
-    CycleModeSelector, ExpandMessageEditor, Follow, KeepAll, NewThread, OpenAgentDiff, OpenHistory,
-    RejectAll, RejectOnce, ToggleBurnMode, ToggleProfileSelector,
+    AgentDiffPane, AgentMode, AgentPanel, AllowAlways, AllowOnce, ContinueThread,
+    ContinueWithBurnMode, CycleModeSelector, ExpandMessageEditor, Follow, KeepAll, NewThread,
+    OpenAgentDiff, OpenHistory, RejectAll, RejectOnce, ToggleBurnMode, ToggleProfileSelector,
 };
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -674,6 +675,7 @@ impl AcpThreadView {
                                 )
                             })
                         });
+                        this.gate_native_tool_calls(cx);
 
                         this.message_editor.focus_handle(cx).focus(window);
 
@@ -1037,7 +1039,7 @@ impl AcpThreadView {
         .detach();
     }
 
-    fn send(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+    pub(crate) fn send(&mut self, window: &mut Window, cx: &mut Context<Self>) {
         let Some(thread) = self.thread() else { return };
 
         if self.is_loading_contents {
@@ -1418,13 +1420,22 @@ impl AcpThreadView {
                 self.list_state.splice(range.clone(), 0);
             }
             AcpThreadEvent::ToolAuthorizationRequired => {
-                self.notify_with_sound("Waiting for tool confirmation", IconName::Info, window, cx);
+                self.reject_tool_calls_denied_by_mode(thread, cx);
+                if thread.read(cx).first_tool_awaiting_confirmation().is_some() {
+                    self.notify_with_sound(
+                        "Waiting for tool confirmation",
+                        IconName::Info,
+                        window,
+                        cx,
+                    );
+                }
             }
             AcpThreadEvent::Retry(retry) => {
                 self.thread_retry_status = Some(retry.clone());
             }
             AcpThreadEvent::Stopped => {
                 self.thread_retry_status.take();
+                self.end_request_mode(cx);
                 let used_tools = thread.read(cx).used_tools_since_last_user_message();
                 self.notify_with_sound(
                     if used_tools {
@@ -1447,6 +1458,7 @@ impl AcpThreadView {
             }
             AcpThreadEvent::Error => {
                 self.thread_retry_status.take();
+                self.end_request_mode(cx);
                 self.notify_with_sound(
                     "Agent stopped due to an error",
                     IconName::Warning,
@@ -1916,6 +1928,15 @@ impl AcpThreadView {
         let Some(thread) = self.thread() else {
             return;
         };
+        // Always allowing a tool the mode wants confirmed every time, such as a command
+        // run while a sensitive file is open, would skip confirming the next call too
+        let (option_id, option_kind) = match option_kind {
+            acp::PermissionOptionKind::AllowAlways => self
+                .allow_once_option(thread.read(cx), &tool_call_id, cx)
+                .map(|option_id| (option_id, acp::PermissionOptionKind::AllowOnce))
+                .unwrap_or((option_id, option_kind)),
+            _ => (option_id, option_kind),
+        };
 
         telemetry::event!(
             "Agent Tool Call Authorized",
@@ -1937,6 +1958,157 @@ impl AcpThreadView {
         cx.notify();
     }
 
+    /// The mode the agent panel runs the current request in, which tool calls are gated on
+    fn agent_mode(workspace: &WeakEntity<Workspace>, cx: &App) -> AgentMode {
+        workspace
+            .upgrade()
+            .and_then(|workspace| workspace.read(cx).panel::<AgentPanel>(cx))
+            .map(|panel| panel.read(cx).effective_mode().clone())
+            .unwrap_or_default()
+    }
+
+    /// Go back to the mode selected in the agent panel once a request sent in another
+    /// mode is done
+    fn end_request_mode(&self, cx: &mut App) {
+        let Some(panel) = self
+            .workspace
+            .upgrade()
+            .and_then(|workspace| workspace.read(cx).panel::<AgentPanel>(cx))
+        else {
+            return;
+        };
+        panel.update(cx, |panel, cx| panel.end_request_mode(cx));
+    }
+
+    /// Path of the file in the active editor, which sensitive path patterns are matched against
+    fn active_file_path(workspace: &WeakEntity<Workspace>, cx: &App) -> Option<PathBuf> {
+        let workspace = workspace.upgrade()?;
+        let editor = workspace.read(cx).active_item_as::<Editor>(cx)?;
+        let buffer = editor.read(cx).buffer().read(cx).as_singleton()?;
+        let file = buffer.read(cx).file()?;
+        Some(file.full_path(cx))
+    }
+
+    /// Get the option allowing a tool call just once, if the agent mode asks to confirm
+    /// its tool every time
+    fn allow_once_option(
+        &self,
+        thread: &AcpThread,
+        tool_call_id: &acp::ToolCallId,
+        cx: &App,
+    ) -> Option<acp::PermissionOptionId> {
+        let tool_call = thread.entries().iter().find_map(|entry| match entry {
+            AgentThreadEntry::ToolCall(tool_call) if &tool_call.id == tool_call_id => {
+                Some(tool_call)
+            }
+            _ => None,
+        })?;
+        let ToolCallStatus::WaitingForConfirmation { options, .. } = &tool_call.status else {
+            return None;
+        };
+        let permission = Self::agent_mode(&self.workspace, cx).tool_permission_for_path(
+            tool_for_kind(tool_call.kind)?,
+            Self::active_file_path(&self.workspace, cx).as_deref(),
+            &AgentSettings::get_global(cx).sensitive_paths,
+        );
+        if permission != ToolPermission::AskFirst {
+            return None;
+        }
+        options
+            .iter()
+            .find(|option| option.kind == acp::PermissionOptionKind::AllowOnce)
+            .map(|option| option.id.clone())
+    }
+
+    /// Gate every tool call the native agent runs on the agent mode, including calls
+    /// that run without confirmation, and confirm the calls the mode asks about first
+    /// even when tool actions are always allowed
+    fn gate_native_tool_calls(&self, cx: &mut App) {
+        let Some(thread) = self.as_native_thread(cx) else {
+            return;
+        };
+        let workspace = self.workspace.clone();
+        thread.update(cx, |thread, _cx| {
+            thread.set_tool_gate(move |kind, cx| {
+                let Some(tool) = tool_for_kind(kind) else {
+                    return agent::ToolCallGate::Allow;
+                };
+                let mode = Self::agent_mode(&workspace, cx);
+                if !mode.permits_tool(tool) {
+                    return agent::ToolCallGate::Deny;
+                }
+                let permission = mode.tool_permission_for_path(
+                    tool,
+                    Self::active_file_path(&workspace, cx).as_deref(),
+                    &AgentSettings::get_global(cx).sensitive_paths,
+                );
+                if permission == ToolPermission::AskFirst {
+                    agent::ToolCallGate::Confirm
+                } else {
+                    agent::ToolCallGate::Allow
+                }
+            });
+        });
+    }
+
+    /// Explain why "Always Allow" isn't offered for a tool call the agent mode confirms
+    /// every time, or `None` if the mode doesn't
+    fn always_allow_withheld_reason(&self, kind: acp::ToolKind, cx: &App) -> Option<SharedString> {
+        let tool = tool_for_kind(kind)?;
+        let mode = Self::agent_mode(&self.workspace, cx);
+        if mode.tool_permission(tool) == ToolPermission::AskFirst {
+            return Some(
+                format!(
+                    "{} mode confirms every tool call, so it can't always be allowed",
+                    mode.display_name()
+                )
+                .into(),
+            );
+        }
+        let permission = mode.tool_permission_for_path(
+            tool,
+            Self::active_file_path(&self.workspace, cx).as_deref(),
+            &AgentSettings::get_global(cx).sensitive_paths,
+        );
+        (permission == ToolPermission::AskFirst).then(|| {
+            "Commands are confirmed every time while a sensitive file is open, so they can't always be allowed".into()
+        })
+    }
+
+    /// Reject every tool call awaiting confirmation whose tool the agent mode doesn't
+    /// permit, such as edits in Ask mode
+    fn reject_tool_calls_denied_by_mode(&self, thread: &Entity<AcpThread>, cx: &mut App) {
+        let mode = Self::agent_mode(&self.workspace, cx);
+        let denied_tool_calls = thread
+            .read(cx)
+            .entries()
+            .iter()
+            .filter_map(|entry| {
+                let AgentThreadEntry::ToolCall(tool_call) = entry else {
+                    return None;
+                };
+                let ToolCallStatus::WaitingForConfirmation { options, .. } = &tool_call.status
+                else {
+                    return None;
+                };
+                let tool = tool_for_kind(tool_call.kind)?;
+                if mode.permits_tool(tool) {
+                    return None;
+                }
+                let option = options
+                    .iter()
+                    .find(|option| option.kind == acp::PermissionOptionKind::RejectOnce)?;
+                Some((tool_call.id.clone(), option.id.clone(), option.kind))
+            })
+            .collect::<Vec<_>>();
+
+        thread.update(cx, |thread, cx| {
+            for (tool_call_id, option_id, option_kind) in denied_tool_calls {
+                thread.authorize_tool_call(tool_call_id, option_id, option_kind, cx);
+            }
+        });
+    }
+
     fn restore_checkpoint(&mut self, message_id: &UserMessageId, cx: &mut Context<Self>) {
         let Some(thread) = self.thread() else {
             return;
@@ -2809,6 +2981,11 @@ impl AcpThreadView {
                 .is_some_and(|call| call.id == tool_call_id)
         });
         let mut seen_kinds: ArrayVec<acp::PermissionOptionKind, 3> = ArrayVec::new();
+        let always_allow_withheld_reason = self.always_allow_withheld_reason(kind, cx);
+        let withhold_always_allow = always_allow_withheld_reason.is_some();
+        let options = options.iter().filter(move |option| {
+            !withhold_always_allow || option.kind != acp::PermissionOptionKind::AllowAlways
+        });
 
         div()
             .p_1()
@@ -2823,7 +3000,14 @@ impl AcpThreadView {
                 }
             })
             .gap_0p5()
-            .children(options.iter().map(move |option| {
+            .when_some(always_allow_withheld_reason, |this, reason| {
+                this.child(
+                    Label::new(reason)
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                )
+            })
+            .children(options.map(move |option| {
                 let option_id = SharedString::from(option.id.0.clone());
                 Button::new((option_id, entry_ix), option.name.clone())
                     .map(|this| {
@@ -5344,6 +5528,16 @@ impl AcpThreadView {
         })
     }
 
+    /// Inserts text into the message editor or the message being edited, if
+    /// any, and focuses it without sending.
+    pub(crate) fn insert_text(&self, text: &str, window: &mut Window, cx: &mut Context<Self>) {
+        let message_editor = self.active_editor(cx);
+        message_editor.update(cx, |message_editor, cx| {
+            message_editor.insert_text(text, window, cx);
+        });
+        window.focus(&message_editor.focus_handle(cx));
+    }
+
     /// Inserts the selected text into the message editor or the message being
     /// edited, if any.
     pub(crate) fn insert_selections(&self, window: &mut Window, cx: &mut Context<Self>) {
@@ -4,63 +4,1065 @@
 /// allowing users to quickly access quick edit, send-to-agent, and other
 /// agent features from right-click menus in the editor.
 
-use gpui::{App, Context, Window};
-use workspace::Workspace;
+use std::any::TypeId;
+use std::ops::Range;
+use std::str::FromStr as _;
 
-/// Context menu item for asking the agent about selected code
-pub struct AskAgentAboutThis;
+use editor::{AgentContextMenuRenderer, Editor};
+use gpui::{Action, App, Context, DispatchPhase, Entity, Focusable as _, Window};
+use language::{DiagnosticSeverity, Point};
+use multi_buffer::{MultiBufferOffset, MultiBufferSnapshot};
+use ui::{ContextMenu, ContextMenuEntry, IconName, IconPosition};
+use util::ResultExt as _;
+use workspace::{Toast, Workspace, notifications::NotificationId};
 
-/// Context menu item for quick editing with the agent
-pub struct QuickEditWithAgent;
+use crate::code_templates::{
+    CodeTemplatePicker, DocCommentStyle, generate_docstring_prompt, generate_tests_template,
+};
+use crate::quick_edit::{
+    ContextInfo, FormatStyle, PreparedAction, QuickEditScope, build_agent_prompt,
+    format_agent_prompt, open_quick_edit_input, prepare_quick_edit, resolve_target_range,
+    selection_or_line_range, selection_or_symbol_range, selection_text_and_context,
+    start_quick_edit,
+};
+use crate::{AgentMode, AgentPanel, SendFileToAgent};
 
-/// Context menu item for generating code from a template
-pub struct GenerateFromTemplate;
+pub use zed_actions::agent::{
+    AskAgentAboutThis, ExplainSelection, FixDiagnosticsHere, GenerateDocstring,
+    GenerateFromTemplate, GenerateTestsForSelection, QuickEditWithAgent, SummarizeSelection,
+};
 
-/// Register agent context menu handlers
-pub fn init(_cx: &mut App) {
-    // Context menus will be registered when workspace items are added
-    // This is a placeholder for future integration with editor context menus
+/// Register agent context menu handlers.
+///
+/// The entries themselves live in the editor's context menu, which shows them
+/// as enabled only when the corresponding action is available for the focused editor.
+pub fn init(cx: &mut App) {
+    editor::set_agent_context_menu_renderer(AgentModeMenuRenderer, cx);
+
+    cx.observe_new(|editor: &mut Editor, _, _| {
+        editor
+            .register_action_renderer(|editor, window, cx| {
+                let Some(workspace) = editor.workspace() else {
+                    return;
+                };
+                let has_cursor_line =
+                    has_actionable_selection(editor, SelectionRequirement::CursorLine, cx);
+                let has_selection =
+                    has_actionable_selection(editor, SelectionRequirement::NonEmpty, cx);
+                // Whether the diagnostics fall within the target range is only
+                // resolved once the action is dispatched
+                let has_diagnostics = editor.buffer().read(cx).read(cx).has_diagnostics();
+
+                register_menu_action::<AskAgentAboutThis>(
+                    &workspace,
+                    has_cursor_line,
+                    handle_ask_agent_about_this,
+                    window,
+                );
+                register_menu_action::<ExplainSelection>(
+                    &workspace,
+                    has_cursor_line,
+                    handle_explain_selection,
+                    window,
+                );
+                register_menu_action::<SummarizeSelection>(
+                    &workspace,
+                    has_cursor_line,
+                    handle_summarize_selection,
+                    window,
+                );
+                register_menu_action::<QuickEditWithAgent>(
+                    &workspace,
+                    has_selection,
+                    handle_quick_edit_with_agent,
+                    window,
+                );
+                register_menu_action::<GenerateFromTemplate>(
+                    &workspace,
+                    has_selection,
+                    handle_generate_from_template,
+                    window,
+                );
+                register_menu_action::<GenerateTestsForSelection>(
+                    &workspace,
+                    has_cursor_line,
+                    handle_generate_tests_for_selection,
+                    window,
+                );
+                register_menu_action::<GenerateDocstring>(
+                    &workspace,
+                    has_cursor_line,
+                    handle_generate_docstring,
+                    window,
+                );
+                register_menu_action::<FixDiagnosticsHere>(
+                    &workspace,
+                    has_diagnostics,
+                    handle_fix_diagnostics_here,
+                    window,
+                );
+            })
+            .detach();
+    })
+    .detach();
+}
+
+/// An entry of the agent mode section in the editor's context menu
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AgentModeMenuItem {
+    pub mode: AgentMode,
+    pub label: String,
+    pub icon: IconName,
+    pub selected: bool,
+}
+
+/// List the modes selectable from the context menu: the built-in modes in
+/// display order followed by `custom_modes`, with `active_mode` selected
+pub(crate) fn agent_mode_menu_items(
+    active_mode: &AgentMode,
+    custom_modes: &[AgentMode],
+) -> Vec<AgentModeMenuItem> {
+    AgentMode::all()
+        .iter()
+        .chain(custom_modes)
+        .map(|mode| {
+            let label = match mode.shortcut_hint() {
+                Some(shortcut_hint) => format!("{} ({shortcut_hint})", mode.display_name()),
+                None => mode.display_name().to_string(),
+            };
+            AgentModeMenuItem {
+                mode: mode.clone(),
+                label,
+                icon: IconName::from_str(mode.icon()).unwrap_or(IconName::Sparkle),
+                selected: mode == active_mode,
+            }
+        })
+        .collect()
+}
+
+/// Adds the agent mode section to the editor's context menu, so the mode for the
+/// next agent action can be picked without opening the agent panel
+struct AgentModeMenuRenderer;
+
+impl AgentContextMenuRenderer for AgentModeMenuRenderer {
+    fn render_entries(&self, menu: ContextMenu, window: &mut Window, cx: &mut App) -> ContextMenu {
+        let Some(panel) = window
+            .root::<Workspace>()
+            .flatten()
+            .and_then(|workspace| workspace.read(cx).panel::<AgentPanel>(cx))
+        else {
+            return menu;
+        };
+
+        let active_mode = panel.read(cx).active_mode().clone();
+        // Keep an active custom mode listed, since custom modes aren't stored with the panel.
+        let custom_modes = match &active_mode {
+            AgentMode::Custom { .. } => vec![active_mode.clone()],
+            _ => Vec::new(),
+        };
+
+        agent_mode_menu_items(&active_mode, &custom_modes)
+            .into_iter()
+            .fold(menu.separator().header("Agent Mode"), |menu, item| {
+                let panel = panel.downgrade();
+                let mode = item.mode;
+                menu.item(
+                    ContextMenuEntry::new(item.label)
+                        .icon(item.icon)
+                        .toggleable(IconPosition::End, item.selected)
+                        .handler(move |_, cx| {
+                            panel
+                                .update(cx, |panel, cx| panel.set_active_mode(mode.clone(), cx))
+                                .log_err();
+                        }),
+                )
+            })
+    }
+}
+
+/// What a context menu entry needs from the editor's selection to be enabled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SelectionRequirement {
+    /// Some text must be selected
+    NonEmpty,
+    /// An empty cursor is enough, as long as the buffer has content to fall back to
+    CursorLine,
+}
+
+/// Whether the editor's newest selection satisfies `requirement`.
+///
+/// This runs on every render, so it only checks the selection and buffer state; the
+/// range an action targets is resolved when the action runs.
+pub(crate) fn has_actionable_selection(
+    editor: &Editor,
+    requirement: SelectionRequirement,
+    cx: &mut App,
+) -> bool {
+    match requirement {
+        SelectionRequirement::NonEmpty => {
+            editor.has_non_empty_selection(&editor.display_snapshot(cx))
+        }
+        SelectionRequirement::CursorLine => editor.buffer().read(cx).len(cx).0 > 0,
+    }
+}
+
+/// Route a context menu action to its workspace handler while `enabled` is true
+pub(crate) fn register_menu_action<A: Action>(
+    workspace: &Entity<Workspace>,
+    enabled: bool,
+    handler: fn(&mut Workspace, &mut Window, &mut Context<Workspace>),
+    window: &mut Window,
+) {
+    let workspace = workspace.downgrade();
+    window.on_action_when(enabled, TypeId::of::<A>(), move |_, phase, window, cx| {
+        if phase != DispatchPhase::Bubble {
+            return;
+        }
+        workspace
+            .update(cx, |workspace, cx| handler(workspace, window, cx))
+            .log_err();
+    });
+}
+
+const ASK_AGENT_INSTRUCTION: &str = "Explain this code:";
+
+/// Build the composer text for asking the agent about a piece of code
+pub(crate) fn ask_agent_prompt(context: &ContextInfo, code: &str) -> String {
+    format_agent_prompt(ASK_AGENT_INSTRUCTION, context, code)
+}
+
+/// Prepare the composer text for asking the agent about the editor's target range
+pub(crate) fn prepare_ask_agent_about_this(
+    editor: &Editor,
+    cx: &mut App,
+) -> Option<PreparedAction> {
+    let range = resolve_target_range(editor, cx)?;
+    let prompt = build_agent_prompt(editor, range.clone(), ASK_AGENT_INSTRUCTION, cx)?;
+    Some(PreparedAction {
+        range,
+        prompt: Some(prompt),
+    })
 }
 
 /// Handle "Ask Agent About This" action from context menu
 pub(crate) fn handle_ask_agent_about_this(
-    _workspace: &mut Workspace,
-    _window: &mut Window,
-    _cx: &mut Context<Workspace>,
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prompt) = editor
+        .update(cx, |editor, cx| prepare_ask_agent_about_this(editor, cx))
+        .and_then(|prepared| prepared.prompt)
+    else {
+        return;
+    };
+
+    insert_into_agent_composer(workspace, prompt, window, cx);
+}
+
+/// Most lines of a file sent by `SendFileToAgent`, beyond which the middle is omitted
+const SEND_FILE_MAX_LINES: usize = 500;
+
+/// Prepare the composer text for sending the editor's whole buffer to the agent.
+///
+/// The selection is ignored. Returns `None` when the buffer is empty.
+pub(crate) fn prepare_send_file_to_agent(editor: &Editor, cx: &mut App) -> Option<PreparedAction> {
+    let len = editor.buffer().read(cx).snapshot(cx).len().0;
+    if len == 0 {
+        return None;
+    }
+    let range = 0..len;
+    let (code, mut context) = selection_text_and_context(editor, range.clone(), cx)?;
+    context.start_line = 0;
+    context.end_line = code.lines().count().saturating_sub(1) as u32;
+    context.start_column = 0;
+    context.end_column = 0;
+    let prompt = context.format_for_agent_with_options(
+        &code,
+        FormatStyle::default(),
+        Some(SEND_FILE_MAX_LINES),
+    );
+    Some(PreparedAction {
+        range,
+        prompt: Some(prompt),
+    })
+}
+
+/// Handle `SendFileToAgent` by seeding the agent composer with the active file
+pub(crate) fn handle_send_file_to_agent(
+    workspace: &mut Workspace,
+    _: &SendFileToAgent,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prompt) = editor
+        .update(cx, |editor, cx| prepare_send_file_to_agent(editor, cx))
+        .and_then(|prepared| prepared.prompt)
+    else {
+        return;
+    };
+
+    insert_into_agent_composer(workspace, prompt, window, cx);
+}
+
+/// A prompt to send to the agent along with the mode it must run in
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AgentRequest {
+    pub mode: AgentMode,
+    pub prompt: String,
+}
+
+const EXPLAIN_INSTRUCTION: &str = "Explain what this code does:";
+
+/// Build the request for explaining code, given a prompt built from `EXPLAIN_INSTRUCTION`.
+///
+/// Explanations always run in Ask mode, whatever mode is active, so that no
+/// tool can modify files.
+pub(crate) fn explain_request(prompt: String) -> AgentRequest {
+    AgentRequest {
+        mode: AgentMode::Ask,
+        prompt,
+    }
+}
+
+/// Prepare the prompt explaining the editor's selection or enclosing symbol
+pub(crate) fn prepare_explain_selection(editor: &Editor, cx: &mut App) -> Option<PreparedAction> {
+    let range = selection_or_symbol_range(editor, cx)?;
+    let prompt = build_agent_prompt(editor, range.clone(), EXPLAIN_INSTRUCTION, cx)?;
+    Some(PreparedAction {
+        range,
+        prompt: Some(prompt),
+    })
+}
+
+/// Handle "Explain This" action from context menu
+pub(crate) fn handle_explain_selection(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prompt) = editor
+        .update(cx, |editor, cx| prepare_explain_selection(editor, cx))
+        .and_then(|prepared| prepared.prompt)
+    else {
+        return;
+    };
+
+    send_agent_request(workspace, explain_request(prompt), window, cx);
+}
+
+const SUMMARIZE_INSTRUCTION: &str =
+    "Summarize what this code does in a few sentences. Be concise and mention the main symbols:";
+
+/// Most lines sent by `SummarizeSelection`, beyond which the middle is omitted
+const SUMMARIZE_MAX_LINES: usize = 500;
+
+/// Build the request for summarizing code, given a prompt built from `SUMMARIZE_INSTRUCTION`.
+///
+/// Like explanations, summaries run in Ask mode so the answer lands in the
+/// panel and the buffer is left untouched.
+pub(crate) fn summarize_request(prompt: String) -> AgentRequest {
+    AgentRequest {
+        mode: AgentMode::Ask,
+        prompt,
+    }
+}
+
+/// Prepare the prompt summarizing the editor's selection, or the whole buffer
+/// when nothing is selected.
+///
+/// Long code is truncated to `SUMMARIZE_MAX_LINES`. Returns `None` when the
+/// buffer is empty.
+pub(crate) fn prepare_summarize_selection(editor: &Editor, cx: &mut App) -> Option<PreparedAction> {
+    let range = if editor.has_non_empty_selection(&editor.display_snapshot(cx)) {
+        selection_or_line_range(editor, cx)
+    } else {
+        QuickEditScope::WholeFile.resolve_range(editor, cx)?
+    };
+    if range.is_empty() {
+        return None;
+    }
+    let (code, context) = selection_text_and_context(editor, range.clone(), cx)?;
+    let prompt = format!(
+        "{SUMMARIZE_INSTRUCTION}\n\n{}",
+        context.format_for_agent_with_options(
+            &code,
+            FormatStyle::default(),
+            Some(SUMMARIZE_MAX_LINES),
+        )
+    );
+    Some(PreparedAction {
+        range,
+        prompt: Some(prompt),
+    })
+}
+
+/// Handle "Summarize This" action from context menu
+pub(crate) fn handle_summarize_selection(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prompt) = editor
+        .update(cx, |editor, cx| prepare_summarize_selection(editor, cx))
+        .and_then(|prepared| prepared.prompt)
+    else {
+        return;
+    };
+
+    send_agent_request(workspace, summarize_request(prompt), window, cx);
+}
+
+/// Open the agent panel and send the request's prompt in the request's mode, leaving
+/// the mode the user picked active for later requests
+fn send_agent_request(
+    workspace: &mut Workspace,
+    request: AgentRequest,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(panel) = workspace.panel::<AgentPanel>(cx) else {
+        return;
+    };
+
+    if !panel.focus_handle(cx).contains_focused(window, cx) {
+        workspace.toggle_panel_focus::<AgentPanel>(window, cx);
+    }
+
+    panel.update(cx, |_, cx| {
+        // Wait until the workspace is no longer being updated.
+        cx.defer_in(window, move |panel, window, cx| {
+            panel.send_prompt_in_mode(&request.prompt, request.mode, window, cx);
+        });
+    });
+}
+
+/// A diagnostic reported within the code the agent is asked to fix
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DiagnosticInfo {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// Zero-based row on which the diagnostic starts
+    pub row: u32,
+}
+
+impl DiagnosticInfo {
+    fn severity_label(&self) -> &'static str {
+        match self.severity {
+            DiagnosticSeverity::ERROR => "error",
+            DiagnosticSeverity::WARNING => "warning",
+            DiagnosticSeverity::INFORMATION => "info",
+            DiagnosticSeverity::HINT => "hint",
+            _ => "diagnostic",
+        }
+    }
+}
+
+/// Collect the diagnostics overlapping `range`, in buffer order
+pub(crate) fn diagnostics_in_range(
+    buffer: &MultiBufferSnapshot,
+    range: Range<usize>,
+) -> Vec<DiagnosticInfo> {
+    buffer
+        .diagnostics_in_range::<MultiBufferOffset>(
+            MultiBufferOffset(range.start)..MultiBufferOffset(range.end),
+        )
+        .map(|entry| DiagnosticInfo {
+            severity: entry.diagnostic.severity,
+            message: entry.diagnostic.message.clone(),
+            row: buffer.offset_to_point(entry.range.start).row,
+        })
+        .collect()
+}
+
+/// Build the quick edit instruction for fixing `diagnostics`.
+///
+/// The instruction prefills the single-line quick edit input, so each diagnostic is
+/// reduced to the first line of its message.
+pub(crate) fn fix_diagnostics_instruction(diagnostics: &[DiagnosticInfo]) -> String {
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            format!(
+                "line {} ({}): {}",
+                diagnostic.row + 1,
+                diagnostic.severity_label(),
+                diagnostic.message.lines().next().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("Fix these errors: {}", diagnostics.join("; "))
+}
+
+/// Prepare a quick edit fixing the diagnostics in the editor's target range, prefilled
+/// with an instruction listing them for the user to review before sending.
+///
+/// Returns `None` when the range has no diagnostics.
+pub(crate) fn prepare_fix_diagnostics_here(
+    editor: &Editor,
+    cx: &mut App,
+) -> Option<PreparedAction> {
+    let range = resolve_target_range(editor, cx)?;
+    let buffer = editor.buffer().read(cx).snapshot(cx);
+    let diagnostics = diagnostics_in_range(&buffer, range.clone());
+    if diagnostics.is_empty() {
+        return None;
+    }
+    Some(PreparedAction {
+        range,
+        prompt: Some(fix_diagnostics_instruction(&diagnostics)),
+    })
+}
+
+/// Handle "Fix Diagnostics Here" action from context menu
+pub(crate) fn handle_fix_diagnostics_here(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prepared) = editor.update(cx, |editor, cx| prepare_fix_diagnostics_here(editor, cx))
+    else {
+        struct NoDiagnosticsToFixToast;
+        workspace.show_toast(
+            Toast::new(
+                NotificationId::unique::<NoDiagnosticsToFixToast>(),
+                "There are no diagnostics to fix here.",
+            )
+            .autohide(),
+            cx,
+        );
+        return;
+    };
+
+    open_quick_edit_input(
+        workspace,
+        editor,
+        prepared.range,
+        prepared.prompt,
+        window,
+        cx,
+    );
+}
+
+/// Send a prepared quick edit whose prompt already holds the code and its context,
+/// so that it isn't wrapped around the selection a second time
+fn send_prepared_quick_edit(
+    workspace: &mut Workspace,
+    editor: Entity<Editor>,
+    prepared: PreparedAction,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(prompt) = prepared.prompt else {
+        return;
+    };
+    start_quick_edit(
+        workspace,
+        editor,
+        prepared.range,
+        prompt.clone(),
+        Some(prompt),
+        window,
+        cx,
+    )
+    .log_err();
+}
+
+/// Open the agent panel and seed its composer with `text`, without sending it
+fn insert_into_agent_composer(
+    workspace: &mut Workspace,
+    text: String,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
 ) {
-    // TODO: Implement context menu handler
-    // This will open the agent panel and add the selected code to the message editor
+    let Some(panel) = workspace.panel::<AgentPanel>(cx) else {
+        return;
+    };
+
+    if !panel.focus_handle(cx).contains_focused(window, cx) {
+        workspace.toggle_panel_focus::<AgentPanel>(window, cx);
+    }
+
+    panel.update(cx, |_, cx| {
+        // Wait until the workspace is no longer being updated.
+        cx.defer_in(window, move |panel, window, cx| {
+            panel.insert_into_composer(&text, window, cx);
+        });
+    });
 }
 
 /// Handle "Quick Edit With Agent" action from context menu
 pub(crate) fn handle_quick_edit_with_agent(
-    _workspace: &mut Workspace,
-    _window: &mut Window,
-    _cx: &mut Context<Workspace>,
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
 ) {
-    // TODO: Implement context menu handler
-    // This will open quick edit mode with the selected code
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prepared) = editor.update(cx, |editor, cx| {
+        prepare_quick_edit(editor, None, QuickEditScope::Selection, cx)
+    }) else {
+        return;
+    };
+    open_quick_edit_input(
+        workspace,
+        editor,
+        prepared.range,
+        prepared.prompt,
+        window,
+        cx,
+    );
 }
 
 /// Handle "Generate From Template" action from context menu
 pub(crate) fn handle_generate_from_template(
-    _workspace: &mut Workspace,
-    _window: &mut Window,
-    _cx: &mut Context<Workspace>,
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some((range, code, context)) = editor.update(cx, |editor, cx| {
+        let range = resolve_target_range(editor, cx)?;
+        let (code, context) = selection_text_and_context(editor, range.clone(), cx)?;
+        Some((range, code, context))
+    }) else {
+        return;
+    };
+
+    CodeTemplatePicker::toggle(workspace, editor, range, code, context, window, cx);
+}
+
+/// Prepare a quick edit generating tests for the editor's selection or enclosing symbol,
+/// inserted below it
+pub(crate) fn prepare_generate_tests_for_selection(
+    editor: &Editor,
+    cx: &mut App,
+) -> Option<PreparedAction> {
+    let range = selection_or_symbol_range(editor, cx)?;
+    let (code, context) = selection_text_and_context(editor, range.clone(), cx)?;
+    let template = generate_tests_template(&context.infer_language());
+    let prompt = template.build_prompt(&context, &code);
+    let buffer = editor.buffer().read(cx).snapshot(cx);
+    Some(PreparedAction {
+        range: template.insertion.target_range(&buffer, range),
+        prompt: Some(prompt),
+    })
+}
+
+/// Handle "Generate Tests" action from context menu
+pub(crate) fn handle_generate_tests_for_selection(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prepared) = editor.update(cx, |editor, cx| {
+        prepare_generate_tests_for_selection(editor, cx)
+    }) else {
+        return;
+    };
+
+    send_prepared_quick_edit(workspace, editor, prepared, window, cx);
+}
+
+/// Prepare a quick edit generating a doc comment for the editor's selection or
+/// enclosing symbol.
+///
+/// The range is empty and sits where the doc comment belongs, so the generated
+/// comment is inserted instead of replacing the symbol.
+pub(crate) fn prepare_generate_docstring(editor: &Editor, cx: &mut App) -> Option<PreparedAction> {
+    let range = selection_or_symbol_range(editor, cx)?;
+    let (code, context) = selection_text_and_context(editor, range, cx)?;
+    let style = DocCommentStyle::for_language(&context.infer_language());
+    let insertion_row = style.map_or(context.start_line, |style| {
+        style.insertion_row(context.start_line)
+    });
+
+    let buffer = editor.buffer().read(cx).snapshot(cx);
+    let insertion_row = insertion_row.min(buffer.max_point().row);
+    let insertion_offset = buffer.point_to_offset(Point::new(insertion_row, 0)).0;
+    Some(PreparedAction {
+        range: insertion_offset..insertion_offset,
+        prompt: Some(generate_docstring_prompt(style, &context, &code)),
+    })
+}
+
+/// Handle "Generate Doc Comment" action from context menu
+pub(crate) fn handle_generate_docstring(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
 ) {
-    // TODO: Implement code generation from template
-    // This will show a menu of code generation templates
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prepared) = editor.update(cx, |editor, cx| prepare_generate_docstring(editor, cx))
+    else {
+        return;
+    };
+
+    send_prepared_quick_edit(workspace, editor, prepared, window, cx);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::quick_edit::tests::build_test_editor;
+    use crate::quick_edit_input::QuickEditInput;
+    use editor::SelectionEffects;
+    use gpui::{TestAppContext, VisualTestContext};
+    use language::{Diagnostic, DiagnosticEntry, DiagnosticSet, LanguageServerId, PointUtf16};
+
     #[test]
     fn test_context_menu_items_exist() {
         // Verify that context menu items are defined
         let _ask_agent = AskAgentAboutThis;
         let _quick_edit = QuickEditWithAgent;
         let _generate = GenerateFromTemplate;
+        let _fix_diagnostics = FixDiagnosticsHere;
+        let _explain = ExplainSelection;
+        let _summarize = SummarizeSelection;
+        let _generate_tests = GenerateTestsForSelection;
+        let _generate_docstring = GenerateDocstring;
+    }
+
+    #[test]
+    fn test_ask_agent_prompt() {
+        let context = ContextInfo {
+            file_path: Some("src/lib.rs".to_string()),
+            relative_path: None,
+            start_line: 2,
+            end_line: 4,
+            start_column: 0,
+            end_column: 0,
+            language: None,
+        };
+        let code = "fn answer() -> u32 {\n    42\n}";
+        let prompt = ask_agent_prompt(&context, code);
+
+        assert!(prompt.starts_with("Explain this code:\n\n"));
+        assert!(prompt.contains("src/lib.rs"));
+        assert!(prompt.contains("Lines 3-5"));
+        assert!(prompt.contains("```rust"));
+        assert!(prompt.contains(code));
+    }
+
+    #[test]
+    fn test_agent_mode_menu_items() {
+        let items = agent_mode_menu_items(&AgentMode::Plan, &[]);
+        let modes = items
+            .iter()
+            .map(|item| item.mode.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(modes, AgentMode::all());
+
+        let selected = items
+            .iter()
+            .filter(|item| item.selected)
+            .map(|item| item.mode.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(selected, [AgentMode::Plan]);
+
+        for item in &items {
+            assert!(item.label.starts_with(item.mode.display_name()));
+            if let Some(shortcut_hint) = item.mode.shortcut_hint() {
+                assert!(item.label.contains(shortcut_hint));
+            }
+        }
+
+        let custom = AgentMode::custom("Reviewer", ["read_file"], "Reviews code");
+        let items = agent_mode_menu_items(&custom, std::slice::from_ref(&custom));
+        assert_eq!(items.len(), AgentMode::all().len() + 1);
+        assert!(items.last().is_some_and(|item| item.selected));
+        assert_eq!(items.last().map(|item| item.icon), Some(IconName::Sparkle));
+    }
+
+    #[test]
+    fn test_explain_request_forces_ask_mode() {
+        let context = ContextInfo {
+            file_path: Some("src/lib.rs".to_string()),
+            relative_path: None,
+            start_line: 0,
+            end_line: 2,
+            start_column: 0,
+            end_column: 1,
+            language: None,
+        };
+        let code = "fn answer() -> u32 {\n    42\n}";
+        let request = explain_request(format_agent_prompt(EXPLAIN_INSTRUCTION, &context, code));
+
+        assert_eq!(request.mode, AgentMode::Ask);
+        assert!(!request.mode.permits_tool("edit_file"));
+        assert!(
+            request
+                .prompt
+                .starts_with("Explain what this code does:\n\n")
+        );
+        assert!(request.prompt.contains("src/lib.rs"));
+        assert!(request.prompt.contains("```rust"));
+        assert!(request.prompt.contains(code));
+    }
+
+    #[gpui::test]
+    async fn test_summarize_selection(cx: &mut TestAppContext) {
+        let text = "fn main() {\n    let a = 1;\n}\n";
+        let (_workspace, editor, mut cx) = build_test_editor(text, cx).await;
+        let summarize = |range: Range<usize>, cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                    selections.select_ranges([
+                        MultiBufferOffset(range.start)..MultiBufferOffset(range.end)
+                    ]);
+                });
+                prepare_summarize_selection(editor, cx)
+            })
+        };
+
+        let prepared = summarize(16..26, &mut cx).unwrap();
+        assert_eq!(prepared.range, 16..26);
+        let request = summarize_request(prepared.prompt.unwrap());
+        assert_eq!(request.mode, AgentMode::Ask);
+        assert!(!request.mode.permits_tool("edit_file"));
+        assert!(request.prompt.starts_with(SUMMARIZE_INSTRUCTION));
+        assert!(request.prompt.contains("Line 2"));
+        assert!(request.prompt.contains("```rust"));
+        assert!(request.prompt.contains("let a = 1;"));
+        assert!(!request.prompt.contains("fn main"));
+
+        // Without a selection the whole file is summarized
+        let prepared = summarize(2..2, &mut cx).unwrap();
+        assert_eq!(prepared.range, 0..text.len());
+        assert!(prepared.prompt.unwrap().contains(text));
+    }
+
+    #[gpui::test]
+    async fn test_summarize_selection_truncates_large_files(cx: &mut TestAppContext) {
+        let text = (0..SUMMARIZE_MAX_LINES * 2)
+            .map(|row| format!("let line_{row} = {row};\n"))
+            .collect::<String>();
+        let (_workspace, editor, mut cx) = build_test_editor(&text, cx).await;
+
+        let prompt = editor
+            .update(&mut cx, |editor, cx| {
+                prepare_summarize_selection(editor, cx)
+            })
+            .and_then(|prepared| prepared.prompt)
+            .unwrap();
+        assert!(prompt.contains("let line_0 = 0;"));
+        assert!(prompt.contains(&format!("let line_{0} = {0};", SUMMARIZE_MAX_LINES * 2 - 1)));
+        assert!(!prompt.contains(&format!("let line_{0} = {0};", SUMMARIZE_MAX_LINES)));
+    }
+
+    #[test]
+    fn test_fix_diagnostics_prompt() {
+        let context = ContextInfo {
+            file_path: Some("src/lib.rs".to_string()),
+            relative_path: None,
+            start_line: 2,
+            end_line: 2,
+            start_column: 0,
+            end_column: 0,
+            language: None,
+        };
+        let code = "let count: u32 = \"three\";";
+        let diagnostics = [
+            DiagnosticInfo {
+                severity: DiagnosticSeverity::ERROR,
+                message: "mismatched types\nexpected `u32`, found `&str`".to_string(),
+                row: 2,
+            },
+            DiagnosticInfo {
+                severity: DiagnosticSeverity::WARNING,
+                message: "unused variable: `count`".to_string(),
+                row: 2,
+            },
+        ];
+        let instruction = fix_diagnostics_instruction(&diagnostics);
+        assert_eq!(
+            instruction,
+            "Fix these errors: line 3 (error): mismatched types; line 3 (warning): unused variable: `count`"
+        );
+        let prompt = format_agent_prompt(&instruction, &context, code);
+
+        assert!(prompt.starts_with(&format!("{instruction}\n\n")));
+        assert!(prompt.contains("src/lib.rs"));
+        assert!(prompt.contains("```rust"));
+        assert!(prompt.contains(code));
+    }
+
+    #[gpui::test]
+    async fn test_has_actionable_selection(cx: &mut TestAppContext) {
+        let (_workspace, editor, mut cx) =
+            build_test_editor("let a = 1;\n\nlet b = 2;\n", cx).await;
+
+        let select = |range: std::ops::Range<usize>, cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                    selections.select_ranges([
+                        MultiBufferOffset(range.start)..MultiBufferOffset(range.end)
+                    ]);
+                });
+                (
+                    has_actionable_selection(editor, SelectionRequirement::NonEmpty, cx),
+                    has_actionable_selection(editor, SelectionRequirement::CursorLine, cx),
+                )
+            })
+        };
+
+        // A non-empty selection satisfies both requirements
+        assert_eq!(select(4..9, &mut cx), (true, true));
+        // An empty cursor falls back to its line when that line has content
+        assert_eq!(select(2..2, &mut cx), (false, true));
+        // An empty cursor on a blank line falls back to the whole buffer
+        assert_eq!(select(11..11, &mut cx), (false, true));
+    }
+
+    #[gpui::test]
+    async fn test_prepare_actions(cx: &mut TestAppContext) {
+        let (_workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                selections.select_ranges([MultiBufferOffset(16)..MultiBufferOffset(26)]);
+            });
+        });
+        let prepare = |prepare: fn(&Editor, &mut App) -> Option<PreparedAction>,
+                       cx: &mut VisualTestContext| {
+            editor.update(cx, |editor, cx| prepare(editor, cx))
+        };
+
+        let ask = prepare(prepare_ask_agent_about_this, &mut cx).unwrap();
+        assert_eq!(ask.range, 16..26);
+        let prompt = ask.prompt.unwrap();
+        assert!(prompt.starts_with(ASK_AGENT_INSTRUCTION));
+        assert!(prompt.contains("let a = 1;"));
+
+        let explain = prepare(prepare_explain_selection, &mut cx).unwrap();
+        assert_eq!(explain.range, 16..26);
+        assert!(explain.prompt.unwrap().starts_with(EXPLAIN_INSTRUCTION));
+
+        // Tests are inserted below the selected line
+        let tests = prepare(prepare_generate_tests_for_selection, &mut cx).unwrap();
+        assert_eq!(tests.range, 27..27);
+        assert!(tests.prompt.unwrap().contains("let a = 1;"));
+
+        // Doc comments are inserted at the start of the selected line
+        let docstring = prepare(prepare_generate_docstring, &mut cx).unwrap();
+        assert_eq!(docstring.range, 12..12);
+        assert!(docstring.prompt.unwrap().contains("let a = 1;"));
+
+        // The whole file is sent regardless of the selection
+        let file = prepare(prepare_send_file_to_agent, &mut cx).unwrap();
+        assert_eq!(file.range, 0..29);
+        let prompt = file.prompt.unwrap();
+        assert!(prompt.contains("Lines 1-3"));
+        assert!(prompt.contains("fn main() {\n    let a = 1;\n}\n"));
+
+        // Without diagnostics there is nothing to fix
+        assert_eq!(prepare(prepare_fix_diagnostics_here, &mut cx), None);
+
+        // Preparing leaves the buffer untouched
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let a = 1;\n}\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_init_registers_actions(cx: &mut TestAppContext) {
+        cx.update(init);
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a: u32 = \"one\";\n}\n", cx).await;
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            workspace.add_item_to_active_pane(Box::new(editor.clone()), None, true, window, cx);
+        });
+        let select = |range: std::ops::Range<usize>, cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                    selections.select_ranges([
+                        MultiBufferOffset(range.start)..MultiBufferOffset(range.end)
+                    ]);
+                });
+            });
+            cx.run_until_parked();
+        };
+        let is_available = |action: &dyn Action, cx: &mut VisualTestContext| {
+            cx.update(|window, cx| window.is_action_available(action, cx))
+        };
+        let has_quick_edit_input = |cx: &mut VisualTestContext| {
+            workspace.update(cx, |workspace, cx| {
+                workspace.active_modal::<QuickEditInput>(cx).is_some()
+            })
+        };
+
+        // An empty cursor is enough to ask about its line, but not to quick edit
+        select(16..16, &mut cx);
+        assert!(is_available(&ExplainSelection, &mut cx));
+        assert!(is_available(&GenerateDocstring, &mut cx));
+        assert!(!is_available(&QuickEditWithAgent, &mut cx));
+        assert!(!is_available(&GenerateFromTemplate, &mut cx));
+
+        // Without diagnostics there is nothing to fix, and dispatching does nothing
+        select(16..35, &mut cx);
+        assert!(is_available(&QuickEditWithAgent, &mut cx));
+        assert!(!is_available(&FixDiagnosticsHere, &mut cx));
+        cx.dispatch_action(FixDiagnosticsHere);
+        cx.run_until_parked();
+        assert!(!has_quick_edit_input(&mut cx));
+
+        editor.update(&mut cx, |editor, cx| {
+            let buffer = editor.buffer().read(cx).as_singleton().unwrap();
+            buffer.update(cx, |buffer, cx| {
+                let diagnostics = DiagnosticSet::new(
+                    [DiagnosticEntry {
+                        range: PointUtf16::new(1, 17)..PointUtf16::new(1, 22),
+                        diagnostic: Diagnostic {
+                            severity: DiagnosticSeverity::ERROR,
+                            group_id: 1,
+                            is_primary: true,
+                            message: "mismatched types".into(),
+                            ..Default::default()
+                        },
+                    }],
+                    buffer,
+                );
+                buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+            });
+        });
+        cx.run_until_parked();
+
+        // Diagnostics outside the selection enable the entry, but dispatching it finds
+        // nothing to fix within the selection
+        select(0..11, &mut cx);
+        assert!(is_available(&FixDiagnosticsHere, &mut cx));
+        cx.dispatch_action(FixDiagnosticsHere);
+        cx.run_until_parked();
+        assert!(!has_quick_edit_input(&mut cx));
+
+        select(16..35, &mut cx);
+        cx.dispatch_action(FixDiagnosticsHere);
+        cx.run_until_parked();
+        assert!(has_quick_edit_input(&mut cx));
     }
 }
@@ -3,51 +3,336 @@
 /// This module defines and manages different agent modes for optimal UX:
 /// - Write: Full tool access for comprehensive edits
 /// - Ask: Read-only mode for questions and analysis
+/// - Plan: Read-only mode that proposes an ordered edit plan for approval
 /// - Quick Edit: Scoped edits focused on specific code ranges
 /// - Manual: User-controlled mode similar to Cursor's approach
 
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use agent_client_protocol as acp;
+use agent_settings::AgentSettings;
+use anyhow::{Result, anyhow};
+use collections::HashMap;
+use db::kvp::KEY_VALUE_STORE;
+use editor::Editor;
+use gpui::App;
+use language::Buffer;
+use language_model::LanguageModel;
 use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+use util::ResultExt as _;
+use util::paths::PathMatcher;
+use workspace::WorkspaceId;
+
+/// Every tool any built-in mode knows about, in display order
+const ALL_TOOLS: &[&str] = &[
+    "read_file",
+    "list_files",
+    "search_files",
+    "get_file_outline",
+    "propose_plan",
+    "suggest_edit",
+    "edit_file",
+    "write_file",
+    "run_command",
+];
+
+/// Tools that can modify files or run arbitrary commands
+const MUTATING_TOOLS: &[&str] = &["write_file", "edit_file", "run_command"];
+
+/// Get the tool that an agent tool call of the given kind is gated as by modes.
+///
+/// Kinds no mode restricts, such as thinking or fetching, map to `None`.
+pub fn tool_for_kind(kind: acp::ToolKind) -> Option<&'static str> {
+    match kind {
+        acp::ToolKind::Read => Some("read_file"),
+        acp::ToolKind::Search => Some("search_files"),
+        acp::ToolKind::Edit => Some("edit_file"),
+        acp::ToolKind::Delete | acp::ToolKind::Move => Some("write_file"),
+        acp::ToolKind::Execute => Some("run_command"),
+        acp::ToolKind::Think
+        | acp::ToolKind::Fetch
+        | acp::ToolKind::SwitchMode
+        | acp::ToolKind::Other => None,
+    }
+}
+
+/// Key prefix under which mode usage is persisted, followed by the workspace id
+const AGENT_MODE_USAGE_KEY: &str = "agent_mode_usage";
+
+/// Key prefix under which the active mode is persisted, followed by the workspace id
+const ACTIVE_AGENT_MODE_KEY: &str = "active_agent_mode";
+
+/// Factor applied to every usage count each time a mode is selected, so that old
+/// habits fade and counts stay bounded
+const MODE_USAGE_DECAY: f32 = 0.9;
+
+/// How a mode treats a request to run a tool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToolPermission {
+    /// The tool is never run in this mode
+    Denied,
+    /// The tool runs only after the user confirms it
+    AskFirst,
+    /// The tool runs without confirmation
+    Allowed,
+}
+
+/// A change to a file that the agent wants to make
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditAction {
+    /// Create a new file
+    Create,
+    /// Change an existing file
+    Modify {
+        /// Whether the change stays within the text the user selected
+        within_selection: bool,
+    },
+    /// Delete a file
+    Delete,
+}
+
+/// Features of a language model that modes may depend on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// Whether the model can call tools
+    pub supports_tools: bool,
+}
+
+impl ModelCapabilities {
+    pub fn for_model(model: &dyn LanguageModel) -> Self {
+        Self {
+            supports_tools: model.supports_tools(),
+        }
+    }
+}
+
+/// Everything shown for a mode in a picker or settings UI, gathered by `AgentMode::info`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AgentModeInfo {
+    pub display_name: String,
+    pub description: String,
+    pub enabled_tools: Vec<String>,
+    pub use_cases: Vec<String>,
+    /// Keyboard shortcut of the mode on the current platform, if it has one
+    pub shortcut_hint: Option<String>,
+    /// Name of the icon, as used by `ui::IconName`
+    pub icon: String,
+}
 
 /// Different agent modes available
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum AgentMode {
     /// Full write access with all tools enabled
     Write,
     /// Read-only mode for asking questions
     Ask,
+    /// Read-only mode that produces an edit plan without applying it
+    Plan,
     /// Scoped edits for quick modifications
     QuickEdit,
     /// Manual mode where user controls all actions
     Manual,
+    /// User-defined mode with its own tool set
+    Custom {
+        name: String,
+        tools: Vec<String>,
+        description: String,
+        /// Guidance appended to the system prompt while this mode is active
+        #[serde(default)]
+        system_prompt: String,
+    },
 }
 
 impl AgentMode {
+    /// Get the built-in modes in display order
+    pub fn all() -> &'static [AgentMode] {
+        const ALL: &[AgentMode] = &[
+            AgentMode::Write,
+            AgentMode::Ask,
+            AgentMode::Plan,
+            AgentMode::QuickEdit,
+            AgentMode::Manual,
+        ];
+        ALL
+    }
+
+    /// Get the next built-in mode, wrapping around at the end.
+    /// Custom modes are not part of the cycle, so they advance to the first built-in mode.
+    pub fn next(&self) -> AgentMode {
+        let all = Self::all();
+        match all.iter().position(|mode| mode == self) {
+            Some(ix) => all[(ix + 1) % all.len()].clone(),
+            None => all[0].clone(),
+        }
+    }
+
+    /// Get the previous built-in mode, wrapping around at the start.
+    /// Custom modes step back to the last built-in mode.
+    pub fn previous(&self) -> AgentMode {
+        let all = Self::all();
+        match all.iter().position(|mode| mode == self) {
+            Some(ix) => all[(ix + all.len() - 1) % all.len()].clone(),
+            None => all[all.len() - 1].clone(),
+        }
+    }
+
+    /// Create a user-defined mode with the given tool set
+    pub fn custom(
+        name: impl Into<String>,
+        tools: impl IntoIterator<Item = impl Into<String>>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self::Custom {
+            name: name.into(),
+            tools: tools.into_iter().map(Into::into).collect(),
+            description: description.into(),
+            system_prompt: String::new(),
+        }
+    }
+
+    /// Set the system prompt guidance of a custom mode. Built-in modes are returned unchanged.
+    pub fn with_system_prompt_fragment(mut self, fragment: impl Into<String>) -> Self {
+        if let Self::Custom { system_prompt, .. } = &mut self {
+            *system_prompt = fragment.into();
+        }
+        self
+    }
+
     /// Get display name for the mode
-    pub fn display_name(&self) -> &'static str {
+    pub fn display_name(&self) -> &str {
         match self {
             Self::Write => "Write",
             Self::Ask => "Ask",
+            Self::Plan => "Plan",
             Self::QuickEdit => "Quick Edit",
             Self::Manual => "Manual",
+            Self::Custom { name, .. } => name,
+        }
+    }
+
+    /// Get the guidance this mode contributes to the system prompt.
+    ///
+    /// Callers append it to the system prompt for the active mode.
+    pub fn system_prompt_fragment(&self) -> &str {
+        match self {
+            Self::Write => {
+                "You may read, create and edit files and run commands to complete the request."
+            }
+            Self::Ask => {
+                "You are in read-only mode: do not modify files or run commands. \
+                 Answer questions and explain code using only read-only tools."
+            }
+            Self::Plan => {
+                "Do not modify files. Propose an ordered list of the edits you intend to make \
+                 and wait for the user to approve the plan."
+            }
+            Self::QuickEdit => {
+                "Make the smallest change that satisfies the request, \
+                 limited to the selected code."
+            }
+            Self::Manual => {
+                "Suggest edits for the user to review instead of applying them yourself."
+            }
+            Self::Custom { system_prompt, .. } => system_prompt,
         }
     }
 
     /// Get description for the mode
-    pub fn description(&self) -> &'static str {
+    pub fn description(&self) -> &str {
         match self {
             Self::Write => {
                 "Full access to tools for comprehensive edits, refactoring, and code generation"
             }
             Self::Ask => "Read-only mode for analyzing code, answering questions, and understanding",
+            Self::Plan => {
+                "Outputs an ordered list of intended edits for your approval before anything is written"
+            }
             Self::QuickEdit => {
                 "Focused mode for making quick, scoped edits to selected code ranges"
             }
             Self::Manual => "User controls all actions - agent suggests, you decide what to apply",
+            Self::Custom { description, .. } => description,
+        }
+    }
+
+    /// Get which tools are enabled in this mode, i.e. every tool that isn't denied
+    pub fn enabled_tools(&self) -> Vec<&str> {
+        self.available_tools()
+            .into_iter()
+            .filter(|tool| self.tool_permission(tool) != ToolPermission::Denied)
+            .collect()
+    }
+
+    /// Get how this mode treats a request to run the given tool.
+    ///
+    /// Manual mode confirms every tool.
+    pub fn tool_permission(&self, tool_name: &str) -> ToolPermission {
+        // Ask mode must stay read-only even if its tool list is ever extended.
+        if *self == Self::Ask && MUTATING_TOOLS.contains(&tool_name) {
+            return ToolPermission::Denied;
+        }
+        if !self.available_tools().contains(&tool_name) {
+            return ToolPermission::Denied;
+        }
+        match self {
+            Self::Manual => ToolPermission::AskFirst,
+            _ => ToolPermission::Allowed,
+        }
+    }
+
+    /// Get how this mode treats a request to run the given tool while `active_path`
+    /// is being edited.
+    ///
+    /// `run_command` is confirmed first whenever the path is sensitive, such as a
+    /// secrets file or lockfile, even in modes that otherwise allow it. The agent panel
+    /// matches the active editor's file and confirms such calls every time, even when
+    /// tool actions are always allowed.
+    pub fn tool_permission_for_path(
+        &self,
+        tool_name: &str,
+        active_path: Option<&Path>,
+        sensitive_paths: &PathMatcher,
+    ) -> ToolPermission {
+        let permission = self.tool_permission(tool_name);
+        let is_sensitive = active_path.is_some_and(|path| sensitive_paths.is_match(path));
+        if tool_name == "run_command" && permission == ToolPermission::Allowed && is_sensitive {
+            ToolPermission::AskFirst
+        } else {
+            permission
+        }
+    }
+
+    /// Whether the user must confirm `action` before it's applied in this mode.
+    ///
+    /// Manual mode confirms every write, Write mode only deletes, and QuickEdit only
+    /// changes outside the selection. Read-only modes confirm every write they're
+    /// somehow asked to make, while custom modes follow Write mode.
+    pub fn requires_confirmation(&self, action: EditAction) -> bool {
+        match self {
+            Self::Manual | Self::Ask | Self::Plan => true,
+            Self::QuickEdit => matches!(
+                action,
+                EditAction::Modify {
+                    within_selection: false
+                }
+            ),
+            Self::Write | Self::Custom { .. } => matches!(action, EditAction::Delete),
         }
     }
 
-    /// Get which tools are enabled in this mode
-    pub fn enabled_tools(&self) -> Vec<&'static str> {
+    /// Get the permission of every known tool in this mode, including denied ones
+    pub fn tool_permissions(&self) -> Vec<(&'static str, ToolPermission)> {
+        ALL_TOOLS
+            .iter()
+            .map(|tool| (*tool, self.tool_permission(tool)))
+            .collect()
+    }
+
+    fn available_tools(&self) -> Vec<&str> {
         match self {
             Self::Write => vec![
                 "read_file",
@@ -63,6 +348,13 @@ impl AgentMode {
                 "list_files",
                 "get_file_outline",
             ],
+            Self::Plan => vec![
+                "read_file",
+                "search_files",
+                "list_files",
+                "get_file_outline",
+                "propose_plan",
+            ],
             Self::QuickEdit => vec![
                 "read_file",
                 "edit_file",
@@ -73,6 +365,46 @@ impl AgentMode {
                 "suggest_edit",
                 "search_files",
             ],
+            Self::Custom { tools, .. } => tools.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Get the most restrictive built-in mode that enables all of the given tools.
+    ///
+    /// Falls back to `Write` when no built-in mode enables every requested tool.
+    pub fn minimal_for_tools(tools: &[&str]) -> AgentMode {
+        const BY_RESTRICTIVENESS: [AgentMode; 4] = [
+            AgentMode::Ask,
+            AgentMode::Plan,
+            AgentMode::Manual,
+            AgentMode::QuickEdit,
+        ];
+        BY_RESTRICTIVENESS
+            .into_iter()
+            .find(|mode| {
+                let enabled_tools = mode.enabled_tools();
+                tools.iter().all(|tool| enabled_tools.contains(tool))
+            })
+            .unwrap_or(AgentMode::Write)
+    }
+
+    /// Whether a tool may be executed in this mode, possibly after confirmation.
+    ///
+    /// The agent panel checks this before the native agent runs any tool call, and
+    /// rejects calls other agents ask to confirm, so this is the check to change rather
+    /// than `enabled_tools`.
+    pub fn permits_tool(&self, tool_name: &str) -> bool {
+        self.tool_permission(tool_name) != ToolPermission::Denied
+    }
+
+    /// Whether a model with the given capabilities can run this mode.
+    ///
+    /// Ask mode works with any chat model, while every other mode relies on tools.
+    pub fn is_supported_by(&self, model_capabilities: &ModelCapabilities) -> bool {
+        match self {
+            Self::Ask => true,
+            Self::Custom { tools, .. } if tools.is_empty() => true,
+            _ => model_capabilities.supports_tools,
         }
     }
 
@@ -91,6 +423,11 @@ impl AgentMode {
                 "Performance analysis",
                 "Documentation generation",
             ],
+            Self::Plan => vec![
+                "Reviewing large changes before execution",
+                "Multi-file refactoring",
+                "Agreeing on an approach up front",
+            ],
             Self::QuickEdit => vec![
                 "Quick bug fixes",
                 "Small refactoring",
@@ -102,16 +439,117 @@ impl AgentMode {
                 "Critical code changes",
                 "Learning from agent suggestions",
             ],
+            Self::Custom { .. } => Vec::new(),
+        }
+    }
+
+    /// Get the sampling temperature suggested for this mode.
+    ///
+    /// This is only a suggestion: an explicitly configured temperature should take precedence.
+    pub fn suggested_temperature(&self) -> Option<f32> {
+        match self {
+            Self::Write => Some(0.5),
+            Self::Ask => Some(0.7),
+            Self::Plan => Some(0.4),
+            Self::QuickEdit => Some(0.1),
+            Self::Manual => Some(0.3),
+            Self::Custom { .. } => None,
+        }
+    }
+
+    /// Get the response token limit suggested for this mode.
+    ///
+    /// Quick edits are scoped to a selection, so their responses are capped.
+    pub fn suggested_max_tokens(&self) -> Option<u32> {
+        match self {
+            Self::QuickEdit => Some(4096),
+            Self::Write | Self::Ask | Self::Plan | Self::Manual | Self::Custom { .. } => None,
+        }
+    }
+
+    /// Get the name of the icon representing this mode, as used by `ui::IconName`
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::Write => "pencil",
+            Self::Ask => "circle_help",
+            Self::Plan => "list_todo",
+            Self::QuickEdit => "bolt_filled",
+            Self::Manual => "user_round_pen",
+            Self::Custom { .. } => "sparkle",
         }
     }
 
-    /// Get keyboard shortcut hint for this mode
+    /// Get keyboard shortcut hint for this mode on the current platform.
+    ///
+    /// These mirror the default keymaps; user rebindings are not reflected.
     pub fn shortcut_hint(&self) -> Option<&'static str> {
         match self {
-            Self::QuickEdit => Some("cmd-k or ctrl-alt-k"),
+            Self::QuickEdit => {
+                if cfg!(target_os = "macos") {
+                    Some("cmd-k")
+                } else {
+                    Some("ctrl-alt-k")
+                }
+            }
             _ => None,
         }
     }
+
+    /// Get all of this mode's display metadata at once
+    pub fn info(&self) -> AgentModeInfo {
+        AgentModeInfo {
+            display_name: self.display_name().to_string(),
+            description: self.description().to_string(),
+            enabled_tools: self
+                .enabled_tools()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            use_cases: self.use_cases().into_iter().map(str::to_string).collect(),
+            shortcut_hint: self.shortcut_hint().map(str::to_string),
+            icon: self.icon().to_string(),
+        }
+    }
+}
+
+impl AgentMode {
+    /// Restore the mode that was last active in a workspace, or `None` when nothing was stored.
+    ///
+    /// Falls back to the default mode when the stored value can't be parsed, or it names
+    /// a custom mode that is no longer among `custom_modes`.
+    pub fn restore_active(workspace_id: WorkspaceId, custom_modes: &[AgentMode]) -> Option<Self> {
+        KEY_VALUE_STORE
+            .read_kvp(&workspace_db_key(ACTIVE_AGENT_MODE_KEY, workspace_id))
+            .log_err()
+            .flatten()
+            .map(|value| Self::from_persisted(&value, custom_modes))
+    }
+
+    /// Persist this mode as the active one for a workspace
+    pub async fn save_active(&self, workspace_id: WorkspaceId) -> Result<()> {
+        let value = serde_json::to_string(self)?;
+        KEY_VALUE_STORE
+            .write_kvp(workspace_db_key(ACTIVE_AGENT_MODE_KEY, workspace_id), value)
+            .await
+    }
+
+    fn from_persisted(value: &str, custom_modes: &[AgentMode]) -> Self {
+        match serde_json::from_str::<AgentMode>(value) {
+            // Use the current definition of the custom mode, as its tools may have changed.
+            Ok(AgentMode::Custom { name, .. }) => custom_modes
+                .iter()
+                .find(|mode| {
+                    matches!(mode, AgentMode::Custom { .. }) && mode.display_name() == name
+                })
+                .cloned()
+                .unwrap_or_default(),
+            Ok(mode) => mode,
+            Err(error) => {
+                log::warn!("failed to parse persisted agent mode {value:?}: {error}");
+                Self::default()
+            }
+        }
+    }
 }
 
 impl Default for AgentMode {
@@ -120,6 +558,201 @@ impl Default for AgentMode {
     }
 }
 
+impl fmt::Display for AgentMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write => write!(f, "write"),
+            Self::Ask => write!(f, "ask"),
+            Self::Plan => write!(f, "plan"),
+            Self::QuickEdit => write!(f, "quick-edit"),
+            Self::Manual => write!(f, "manual"),
+            Self::Custom { name, .. } => write!(f, "{name}"),
+        }
+    }
+}
+
+impl FromStr for AgentMode {
+    type Err = anyhow::Error;
+
+    /// Parse a built-in mode by its stable name. Custom modes can't be
+    /// reconstructed from a name alone, so they are rejected here.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "write" => Ok(Self::Write),
+            "ask" => Ok(Self::Ask),
+            "plan" => Ok(Self::Plan),
+            "quick-edit" => Ok(Self::QuickEdit),
+            "manual" => Ok(Self::Manual),
+            _ => Err(anyhow!("unknown agent mode: {s:?}")),
+        }
+    }
+}
+
+/// Modes the agent starts in when opened for a file, by the file's language
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LanguageModeDefaults {
+    /// Mode for languages without an entry in `by_language`
+    fallback: AgentMode,
+    /// Modes keyed by lowercased language name
+    by_language: HashMap<String, AgentMode>,
+}
+
+impl LanguageModeDefaults {
+    pub fn new(
+        fallback: AgentMode,
+        by_language: impl IntoIterator<Item = (String, AgentMode)>,
+    ) -> Self {
+        Self {
+            fallback,
+            by_language: by_language
+                .into_iter()
+                .map(|(language, mode)| (language.to_lowercase(), mode))
+                .collect(),
+        }
+    }
+
+    /// Get the defaults configured in the agent settings
+    pub fn get(cx: &App) -> Self {
+        Self::from_settings(AgentSettings::get_global(cx))
+    }
+
+    /// Resolve the `default_mode` and `language_default_modes` settings, skipping
+    /// mode names that fail to parse
+    pub fn from_settings(settings: &AgentSettings) -> Self {
+        let parse = |mode: &str| {
+            mode.parse::<AgentMode>()
+                .map_err(|error| log::warn!("ignoring agent mode setting: {error}"))
+                .ok()
+        };
+        let fallback = settings
+            .default_mode
+            .as_deref()
+            .and_then(parse)
+            .unwrap_or_default();
+        let by_language = settings
+            .language_default_modes
+            .iter()
+            .filter_map(|(language, mode)| Some((language.clone(), parse(mode)?)));
+        Self::new(fallback, by_language)
+    }
+
+    /// The mode to start in for files whose language has no default of its own
+    pub fn fallback(&self) -> &AgentMode {
+        &self.fallback
+    }
+
+    /// The mode to start in for a file in `language`, matched case-insensitively
+    pub fn default_mode_for_language(&self, language: &str) -> AgentMode {
+        self.by_language
+            .get(&language.to_lowercase())
+            .unwrap_or(&self.fallback)
+            .clone()
+    }
+
+    /// The mode to start in for a buffer, based on its language
+    pub fn default_mode_for_buffer(&self, buffer: &Buffer) -> AgentMode {
+        match buffer.language() {
+            Some(language) => self.default_mode_for_language(language.name().as_ref()),
+            None => self.fallback.clone(),
+        }
+    }
+
+    /// The mode to start in for an editor, based on the language of the buffer its
+    /// newest selection is in
+    pub fn default_mode_for_editor(&self, editor: &Editor, cx: &App) -> AgentMode {
+        match editor.active_excerpt(cx) {
+            Some((_, buffer, _)) => self.default_mode_for_buffer(buffer.read(cx)),
+            None => self.fallback.clone(),
+        }
+    }
+}
+
+/// How often each mode has been selected, with older selections decayed
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModeUsage {
+    entries: Vec<ModeUsageEntry>,
+    /// Number of selections recorded so far, used to order them by recency
+    selections: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ModeUsageEntry {
+    mode: AgentMode,
+    /// Decayed number of times the mode was selected
+    score: f32,
+    /// Value of `selections` when the mode was last selected
+    last_selected: u64,
+}
+
+impl ModeUsage {
+    /// Record that `mode` was selected
+    pub fn record(&mut self, mode: &AgentMode) {
+        self.selections += 1;
+        for entry in &mut self.entries {
+            entry.score *= MODE_USAGE_DECAY;
+        }
+
+        if let Some(entry) = self.entries.iter_mut().find(|entry| &entry.mode == mode) {
+            entry.score += 1.0;
+            entry.last_selected = self.selections;
+        } else {
+            self.entries.push(ModeUsageEntry {
+                mode: mode.clone(),
+                score: 1.0,
+                last_selected: self.selections,
+            });
+        }
+    }
+
+    /// Decayed selection count of every mode that has been used
+    pub fn usage_counts(&self) -> HashMap<AgentMode, u32> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.mode.clone(), entry.score.round() as u32))
+            .collect()
+    }
+
+    /// The mode with the highest usage count, preferring the most recently
+    /// selected one on ties. Falls back to the default mode when nothing was recorded.
+    pub fn most_used_mode(&self) -> AgentMode {
+        self.entries
+            .iter()
+            .max_by_key(|entry| (entry.score.round() as u32, entry.last_selected))
+            .map(|entry| entry.mode.clone())
+            .unwrap_or_default()
+    }
+
+    /// The most recently selected mode, if any
+    pub fn last_used_mode(&self) -> Option<AgentMode> {
+        self.entries
+            .iter()
+            .max_by_key(|entry| entry.last_selected)
+            .map(|entry| entry.mode.clone())
+    }
+
+    /// Read the usage persisted for a workspace
+    pub fn load(workspace_id: WorkspaceId) -> Result<Option<Self>> {
+        let Some(value) =
+            KEY_VALUE_STORE.read_kvp(&workspace_db_key(AGENT_MODE_USAGE_KEY, workspace_id))?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_str(&value)?))
+    }
+
+    /// Persist the usage for a workspace
+    pub async fn save(&self, workspace_id: WorkspaceId) -> Result<()> {
+        let value = serde_json::to_string(self)?;
+        KEY_VALUE_STORE
+            .write_kvp(workspace_db_key(AGENT_MODE_USAGE_KEY, workspace_id), value)
+            .await
+    }
+}
+
+fn workspace_db_key(prefix: &str, workspace_id: WorkspaceId) -> String {
+    format!("{prefix}-{}", i64::from(workspace_id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,6 +761,7 @@ mod tests {
     fn test_mode_display_names() {
         assert_eq!(AgentMode::Write.display_name(), "Write");
         assert_eq!(AgentMode::Ask.display_name(), "Ask");
+        assert_eq!(AgentMode::Plan.display_name(), "Plan");
         assert_eq!(AgentMode::QuickEdit.display_name(), "Quick Edit");
         assert_eq!(AgentMode::Manual.display_name(), "Manual");
     }
@@ -147,6 +781,276 @@ mod tests {
         assert!(tools.contains(&"read_file"));
     }
 
+    #[test]
+    fn test_ask_mode_denies_mutating_tools() {
+        for tool in ["write_file", "edit_file", "run_command"] {
+            assert!(!AgentMode::Ask.permits_tool(tool));
+        }
+        assert!(AgentMode::Ask.permits_tool("read_file"));
+        assert!(!AgentMode::Ask.permits_tool("unknown_tool"));
+    }
+
+    #[test]
+    fn test_write_mode_permits_tools() {
+        for tool in ["write_file", "edit_file", "run_command", "read_file"] {
+            assert!(AgentMode::Write.permits_tool(tool));
+        }
+    }
+
+    #[test]
+    fn test_tool_kinds_gated_by_ask_mode() {
+        let permits_kind =
+            |kind| tool_for_kind(kind).is_none_or(|tool| AgentMode::Ask.permits_tool(tool));
+        for kind in [
+            acp::ToolKind::Edit,
+            acp::ToolKind::Delete,
+            acp::ToolKind::Move,
+            acp::ToolKind::Execute,
+        ] {
+            assert!(!permits_kind(kind));
+        }
+        for kind in [
+            acp::ToolKind::Read,
+            acp::ToolKind::Search,
+            acp::ToolKind::Think,
+            acp::ToolKind::Fetch,
+        ] {
+            assert!(permits_kind(kind));
+        }
+    }
+
+    #[test]
+    fn test_tool_permissions() {
+        use ToolPermission::{Allowed, AskFirst, Denied};
+
+        let cases = [
+            (AgentMode::Write, Allowed, Allowed),
+            (AgentMode::Ask, Denied, Denied),
+            (AgentMode::Plan, Denied, Denied),
+            (AgentMode::QuickEdit, Denied, Denied),
+            (AgentMode::Manual, Denied, Denied),
+        ];
+        for (mode, run_command, write_file) in cases {
+            assert_eq!(mode.tool_permission("run_command"), run_command, "{mode:?}");
+            assert_eq!(mode.tool_permission("write_file"), write_file, "{mode:?}");
+        }
+
+        assert_eq!(
+            AgentMode::Write.tool_permission("edit_file"),
+            ToolPermission::Allowed
+        );
+        assert_eq!(
+            AgentMode::QuickEdit.tool_permission("edit_file"),
+            ToolPermission::Allowed
+        );
+        assert_eq!(
+            AgentMode::Manual.tool_permission("suggest_edit"),
+            ToolPermission::AskFirst
+        );
+        assert_eq!(
+            AgentMode::Manual.tool_permission("read_file"),
+            ToolPermission::AskFirst
+        );
+
+        let custom = AgentMode::custom("Runner", ["run_command"], "Runs commands");
+        assert_eq!(
+            custom.tool_permission("run_command"),
+            ToolPermission::Allowed
+        );
+        assert_eq!(custom.tool_permission("write_file"), ToolPermission::Denied);
+    }
+
+    #[test]
+    fn test_requires_confirmation() {
+        let modify_selection = EditAction::Modify {
+            within_selection: true,
+        };
+        let modify_elsewhere = EditAction::Modify {
+            within_selection: false,
+        };
+        let cases = [
+            (AgentMode::Manual, [true, true, true, true]),
+            (AgentMode::Write, [false, false, false, true]),
+            (AgentMode::QuickEdit, [false, false, true, false]),
+            (AgentMode::Ask, [true, true, true, true]),
+            (AgentMode::Plan, [true, true, true, true]),
+            (
+                AgentMode::custom("Runner", ["run_command"], "Runs commands"),
+                [false, false, false, true],
+            ),
+        ];
+        for (mode, expected) in cases {
+            let actual = [
+                EditAction::Create,
+                modify_selection,
+                modify_elsewhere,
+                EditAction::Delete,
+            ]
+            .map(|action| mode.requires_confirmation(action));
+            assert_eq!(actual, expected, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_run_command_guard_for_sensitive_paths() {
+        use util::paths::PathStyle;
+
+        let sensitive_paths =
+            PathMatcher::new(["**/.env", "**/.env.*"], PathStyle::local()).unwrap();
+        let permission = |mode: &AgentMode, tool, path: Option<&str>| {
+            mode.tool_permission_for_path(tool, path.map(Path::new), &sensitive_paths)
+        };
+
+        let write = AgentMode::Write;
+        assert_eq!(
+            permission(&write, "run_command", Some("project/src/main.rs")),
+            ToolPermission::Allowed
+        );
+        assert_eq!(
+            permission(&write, "run_command", None),
+            ToolPermission::Allowed
+        );
+        assert_eq!(
+            permission(&write, "run_command", Some("project/.env")),
+            ToolPermission::AskFirst
+        );
+        assert_eq!(
+            permission(&write, "run_command", Some("project/.env.local")),
+            ToolPermission::AskFirst
+        );
+        // Only commands are guarded, and other modes keep their permissions
+        assert_eq!(
+            permission(&write, "edit_file", Some("project/.env")),
+            ToolPermission::Allowed
+        );
+        assert_eq!(
+            permission(&AgentMode::Ask, "run_command", Some("project/.env")),
+            ToolPermission::Denied
+        );
+    }
+
+    #[test]
+    fn test_mode_info_matches_getters() {
+        let mode = AgentMode::Write;
+        let info = mode.info();
+        assert_eq!(info.display_name, mode.display_name());
+        assert_eq!(info.description, mode.description());
+        assert_eq!(info.enabled_tools, mode.enabled_tools());
+        assert_eq!(info.use_cases, mode.use_cases());
+        assert_eq!(info.shortcut_hint.as_deref(), mode.shortcut_hint());
+        assert_eq!(info.icon, mode.icon());
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["display_name"], "Write");
+        assert_eq!(serde_json::from_value::<AgentModeInfo>(json).unwrap(), info);
+    }
+
+    #[test]
+    fn test_tool_permissions_list_every_tool() {
+        let permissions = AgentMode::Ask.tool_permissions();
+        assert_eq!(permissions.len(), ALL_TOOLS.len());
+        assert!(permissions.contains(&("write_file", ToolPermission::Denied)));
+        assert!(permissions.contains(&("read_file", ToolPermission::Allowed)));
+
+        for mode in AgentMode::all() {
+            for tool in mode.available_tools() {
+                assert!(
+                    ALL_TOOLS.contains(&tool),
+                    "{mode:?} uses unknown tool {tool}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_enabled_tools_exclude_denied() {
+        for mode in AgentMode::all() {
+            for tool in mode.enabled_tools() {
+                assert_ne!(mode.tool_permission(tool), ToolPermission::Denied);
+            }
+        }
+        assert!(AgentMode::Write.enabled_tools().contains(&"run_command"));
+        assert!(AgentMode::Manual.enabled_tools().contains(&"suggest_edit"));
+    }
+
+    #[test]
+    fn test_minimal_mode_for_tools() {
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["read_file", "search_files"]),
+            AgentMode::Ask
+        );
+        assert_eq!(AgentMode::minimal_for_tools(&[]), AgentMode::Ask);
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["read_file", "propose_plan"]),
+            AgentMode::Plan
+        );
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["suggest_edit"]),
+            AgentMode::Manual
+        );
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["read_file", "edit_file"]),
+            AgentMode::QuickEdit
+        );
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["edit_file", "list_files"]),
+            AgentMode::Write
+        );
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["read_file", "run_command"]),
+            AgentMode::Write
+        );
+        assert_eq!(
+            AgentMode::minimal_for_tools(&["unknown_tool"]),
+            AgentMode::Write
+        );
+    }
+
+    #[test]
+    fn test_mode_support_by_model_capabilities() {
+        let tools_capable = ModelCapabilities {
+            supports_tools: true,
+        };
+        let chat_only = ModelCapabilities {
+            supports_tools: false,
+        };
+
+        for mode in AgentMode::all() {
+            assert!(mode.is_supported_by(&tools_capable), "{mode}");
+        }
+        assert!(AgentMode::Ask.is_supported_by(&chat_only));
+        for mode in [
+            AgentMode::Write,
+            AgentMode::Plan,
+            AgentMode::QuickEdit,
+            AgentMode::Manual,
+        ] {
+            assert!(!mode.is_supported_by(&chat_only), "{mode}");
+        }
+
+        let chat_custom = AgentMode::custom("Chat", Vec::<String>::new(), "Plain conversation");
+        assert!(chat_custom.is_supported_by(&chat_only));
+        let tool_custom = AgentMode::custom("Reader", ["read_file"], "Reads files");
+        assert!(!tool_custom.is_supported_by(&chat_only));
+        assert!(tool_custom.is_supported_by(&tools_capable));
+    }
+
+    #[test]
+    fn test_plan_mode_tools() {
+        let tools = AgentMode::Plan.enabled_tools();
+        assert!(tools.contains(&"propose_plan"));
+        assert!(tools.contains(&"read_file"));
+        assert!(!tools.contains(&"write_file"));
+        assert!(!tools.contains(&"edit_file"));
+        assert!(!tools.contains(&"run_command"));
+        assert!(
+            AgentMode::Plan
+                .use_cases()
+                .iter()
+                .any(|use_case| use_case.to_lowercase().contains("before execution"))
+        );
+    }
+
     #[test]
     fn test_quick_edit_focused() {
         let tools = AgentMode::QuickEdit.enabled_tools();
@@ -158,12 +1062,259 @@ mod tests {
     fn test_mode_descriptions_not_empty() {
         assert!(!AgentMode::Write.description().is_empty());
         assert!(!AgentMode::Ask.description().is_empty());
+        assert!(!AgentMode::Plan.description().is_empty());
         assert!(!AgentMode::QuickEdit.description().is_empty());
         assert!(!AgentMode::Manual.description().is_empty());
     }
 
+    #[test]
+    fn test_custom_mode() {
+        let mode = AgentMode::custom(
+            "Review",
+            ["read_file", "search_files", "get_file_outline"],
+            "Review changes without editing",
+        );
+        assert_eq!(mode.display_name(), "Review");
+        assert_eq!(mode.description(), "Review changes without editing");
+        assert_eq!(
+            mode.enabled_tools(),
+            vec!["read_file", "search_files", "get_file_outline"]
+        );
+        assert!(mode.use_cases().is_empty());
+        assert_eq!(mode.shortcut_hint(), None);
+    }
+
+    #[test]
+    fn test_custom_mode_serde_round_trip() {
+        let mode = AgentMode::custom("Refactor", ["read_file", "edit_file"], "Refactor code");
+        let json = serde_json::to_string(&mode).unwrap();
+        let parsed: AgentMode = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, mode);
+    }
+
+    #[test]
+    fn test_mode_string_round_trip() {
+        for mode in [
+            AgentMode::Write,
+            AgentMode::Ask,
+            AgentMode::Plan,
+            AgentMode::QuickEdit,
+            AgentMode::Manual,
+        ] {
+            let name = mode.to_string();
+            assert_eq!(name.parse::<AgentMode>().unwrap(), mode);
+
+            let json = serde_json::to_string(&mode).unwrap();
+            assert_eq!(json, format!("\"{name}\""));
+            assert_eq!(serde_json::from_str::<AgentMode>(&json).unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("Quick-Edit".parse::<AgentMode>().unwrap(), AgentMode::QuickEdit);
+        assert_eq!("ASK".parse::<AgentMode>().unwrap(), AgentMode::Ask);
+        assert!("QuickEdit".parse::<AgentMode>().is_err());
+        assert!("unknown".parse::<AgentMode>().is_err());
+    }
+
+    #[test]
+    fn test_all_modes_order() {
+        assert_eq!(
+            AgentMode::all(),
+            &[
+                AgentMode::Write,
+                AgentMode::Ask,
+                AgentMode::Plan,
+                AgentMode::QuickEdit,
+                AgentMode::Manual,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mode_cycling() {
+        assert_eq!(AgentMode::Write.next(), AgentMode::Ask);
+        assert_eq!(AgentMode::Manual.next(), AgentMode::Write);
+        assert_eq!(AgentMode::Write.previous(), AgentMode::Manual);
+        assert_eq!(AgentMode::Ask.previous(), AgentMode::Write);
+
+        let custom = AgentMode::custom("Review", ["read_file"], "Review code");
+        assert_eq!(custom.next(), AgentMode::Write);
+    }
+
+    #[test]
+    fn test_mode_icons() {
+        for mode in AgentMode::all() {
+            assert!(!mode.icon().is_empty());
+            assert!(ui::IconName::from_str(mode.icon()).is_ok());
+        }
+        let custom = AgentMode::custom("Review", ["read_file"], "Review code");
+        assert!(ui::IconName::from_str(custom.icon()).is_ok());
+    }
+
+    #[test]
+    fn test_shortcut_hints() {
+        let hint = AgentMode::QuickEdit.shortcut_hint().unwrap();
+        assert!(!hint.is_empty());
+        assert!(!hint.contains(" or "));
+        for mode in AgentMode::all() {
+            if *mode != AgentMode::QuickEdit {
+                assert_eq!(mode.shortcut_hint(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_suggested_model_parameters() {
+        let quick_edit = AgentMode::QuickEdit.suggested_temperature().unwrap();
+        let ask = AgentMode::Ask.suggested_temperature().unwrap();
+        let write = AgentMode::Write.suggested_temperature().unwrap();
+        assert!(quick_edit < ask);
+        assert!(quick_edit < write);
+        assert!(AgentMode::QuickEdit.suggested_max_tokens().is_some());
+
+        let custom = AgentMode::custom("Review", ["read_file"], "Review code");
+        assert_eq!(custom.suggested_temperature(), None);
+        assert_eq!(custom.suggested_max_tokens(), None);
+    }
+
     #[test]
     fn test_default_mode() {
         assert_eq!(AgentMode::default(), AgentMode::Write);
     }
+
+    #[test]
+    fn test_mode_usage_counts() {
+        let mut usage = ModeUsage::default();
+        assert!(usage.usage_counts().is_empty());
+        assert_eq!(usage.most_used_mode(), AgentMode::default());
+
+        usage.record(&AgentMode::Plan);
+        usage.record(&AgentMode::Plan);
+        usage.record(&AgentMode::Plan);
+        usage.record(&AgentMode::Ask);
+
+        let counts = usage.usage_counts();
+        assert_eq!(counts.get(&AgentMode::Plan), Some(&2));
+        assert_eq!(counts.get(&AgentMode::Ask), Some(&1));
+        assert_eq!(counts.get(&AgentMode::Write), None);
+        assert_eq!(usage.most_used_mode(), AgentMode::Plan);
+        assert_eq!(usage.last_used_mode(), Some(AgentMode::Ask));
+    }
+
+    #[test]
+    fn test_mode_usage_ties_prefer_recent() {
+        let mut usage = ModeUsage::default();
+        usage.record(&AgentMode::Write);
+        usage.record(&AgentMode::Ask);
+        usage.record(&AgentMode::Write);
+        usage.record(&AgentMode::Ask);
+
+        let counts = usage.usage_counts();
+        assert_eq!(counts.get(&AgentMode::Write), counts.get(&AgentMode::Ask));
+        assert_eq!(usage.most_used_mode(), AgentMode::Ask);
+    }
+
+    #[test]
+    fn test_mode_usage_decays() {
+        let mut usage = ModeUsage::default();
+        for _ in 0..20 {
+            usage.record(&AgentMode::Write);
+        }
+        for _ in 0..10 {
+            usage.record(&AgentMode::Ask);
+        }
+
+        // Counts stay bounded, and recent habits take over from old ones
+        let counts = usage.usage_counts();
+        assert!(counts[&AgentMode::Write] < 10);
+        assert!(counts[&AgentMode::Ask] <= 10);
+        assert_eq!(usage.most_used_mode(), AgentMode::Ask);
+    }
+
+    #[gpui::test]
+    async fn test_save_and_restore_active_mode() {
+        let workspace_id = WorkspaceId::default();
+        AgentMode::Plan.save_active(workspace_id).await.unwrap();
+        assert_eq!(
+            AgentMode::restore_active(workspace_id, &[]),
+            Some(AgentMode::Plan)
+        );
+
+        let custom = AgentMode::custom("Reviewer", ["read_file"], "Reviews code");
+        custom.save_active(workspace_id).await.unwrap();
+        assert_eq!(
+            AgentMode::restore_active(workspace_id, &[custom.clone()]),
+            Some(custom)
+        );
+    }
+
+    #[test]
+    fn test_system_prompt_fragments() {
+        assert!(
+            AgentMode::Ask
+                .system_prompt_fragment()
+                .contains("read-only")
+        );
+        assert!(
+            AgentMode::QuickEdit
+                .system_prompt_fragment()
+                .contains("smallest change")
+        );
+        for mode in AgentMode::all() {
+            assert!(!mode.system_prompt_fragment().is_empty(), "{mode:?}");
+        }
+
+        let custom = AgentMode::custom("Reviewer", ["read_file"], "Reviews code");
+        assert_eq!(custom.system_prompt_fragment(), "");
+        let custom = custom.with_system_prompt_fragment("Focus on correctness.");
+        assert_eq!(custom.system_prompt_fragment(), "Focus on correctness.");
+        assert_eq!(
+            AgentMode::Ask
+                .with_system_prompt_fragment("ignored")
+                .system_prompt_fragment(),
+            AgentMode::Ask.system_prompt_fragment()
+        );
+    }
+
+    #[test]
+    fn test_default_mode_for_language() {
+        let defaults = LanguageModeDefaults::new(
+            AgentMode::Write,
+            [
+                ("SQL".to_string(), AgentMode::Ask),
+                ("yaml".to_string(), AgentMode::Ask),
+            ],
+        );
+
+        assert_eq!(defaults.default_mode_for_language("SQL"), AgentMode::Ask);
+        assert_eq!(defaults.default_mode_for_language("sql"), AgentMode::Ask);
+        assert_eq!(defaults.default_mode_for_language("YAML"), AgentMode::Ask);
+        assert_eq!(defaults.default_mode_for_language("Rust"), AgentMode::Write);
+
+        let defaults = LanguageModeDefaults::new(AgentMode::Plan, []);
+        assert_eq!(defaults.default_mode_for_language("Rust"), AgentMode::Plan);
+    }
+
+    #[test]
+    fn test_restore_falls_back_to_default() {
+        assert_eq!(
+            AgentMode::from_persisted("\"not-a-mode\"", &[]),
+            AgentMode::Write
+        );
+        assert_eq!(AgentMode::from_persisted("", &[]), AgentMode::Write);
+
+        // A custom mode that has since been removed
+        let removed = AgentMode::custom("Removed", Vec::<String>::new(), "");
+        let persisted = serde_json::to_string(&removed).unwrap();
+        assert_eq!(AgentMode::from_persisted(&persisted, &[]), AgentMode::Write);
+
+        // A custom mode that still exists picks up its current definition
+        let updated = AgentMode::custom("Removed", ["read_file"], "");
+        assert_eq!(
+            AgentMode::from_persisted(&persisted, &[updated.clone()]),
+            updated
+        );
+    }
 }
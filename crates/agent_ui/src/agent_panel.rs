@@ -14,7 +14,8 @@ use settings::{
 
 use zed_actions::agent::{OpenClaudeCodeOnboardingModal, ReauthenticateAgent};
 
-use crate::ManageProfiles;
+use crate::agent_modes::{LanguageModeDefaults, ModeUsage};
+use crate::agent_telemetry::agent_mode_changed_event;
 use crate::ui::{AcpOnboardingModal, ClaudeCodeOnboardingModal};
 use crate::{
     AddContextServer, AgentDiffPane, Follow, InlineAssistant, NewTextThread, NewThread,
@@ -26,6 +27,7 @@ use crate::{
     text_thread_editor::{AgentPanelDelegate, TextThreadEditor, make_lsp_adapter_delegate},
     ui::{AgentOnboardingModal, EndTrialUpsell},
 };
+use crate::{AgentMode, ManageProfiles};
 use crate::{
     ExpandMessageEditor,
     acp::{AcpThreadHistory, ThreadHistoryEvent},
@@ -441,6 +443,12 @@ pub struct AgentPanel {
     pending_serialization: Option<Task<Result<()>>>,
     onboarding: Entity<AgentPanelOnboarding>,
     selected_agent: AgentType,
+    active_mode: AgentMode,
+    /// Mode the request sent by `send_prompt_in_mode` runs in instead of the active
+    /// mode, until its turn ends
+    request_mode: Option<AgentMode>,
+    /// How often each mode was selected in this workspace
+    mode_usage: ModeUsage,
 }
 
 impl AgentPanel {
@@ -461,6 +469,57 @@ impl AgentPanel {
         }));
     }
 
+    /// The mode the agent works in for this workspace
+    pub fn active_mode(&self) -> &AgentMode {
+        &self.active_mode
+    }
+
+    /// The mode tool calls are gated on: the mode the current request was sent in, if
+    /// any, or else the active mode
+    pub fn effective_mode(&self) -> &AgentMode {
+        self.request_mode.as_ref().unwrap_or(&self.active_mode)
+    }
+
+    /// Stop overriding the active mode once the request sent by `send_prompt_in_mode`
+    /// is done
+    pub(crate) fn end_request_mode(&mut self, cx: &mut Context<Self>) {
+        if self.request_mode.take().is_some() {
+            cx.notify();
+        }
+    }
+
+    /// How often each mode was selected in this workspace
+    pub fn mode_usage(&self) -> &ModeUsage {
+        &self.mode_usage
+    }
+
+    /// Switch the agent mode, persisting it and its usage for the workspace so they're
+    /// restored next session
+    pub fn set_active_mode(&mut self, mode: AgentMode, cx: &mut Context<Self>) {
+        if self.active_mode == mode {
+            return;
+        }
+        telemetry::send_event(agent_mode_changed_event(&self.active_mode, &mode));
+        self.active_mode = mode.clone();
+        self.mode_usage.record(&mode);
+        cx.notify();
+
+        let Some(workspace_id) = self
+            .workspace
+            .read_with(cx, |workspace, _| workspace.database_id())
+            .ok()
+            .flatten()
+        else {
+            return;
+        };
+        let mode_usage = self.mode_usage.clone();
+        cx.background_spawn(async move {
+            mode.save_active(workspace_id).await?;
+            mode_usage.save(workspace_id).await
+        })
+        .detach_and_log_err(cx);
+    }
+
     pub fn load(
         workspace: WeakEntity<Workspace>,
         prompt_builder: Arc<PromptBuilder>,
@@ -483,6 +542,21 @@ impl AgentPanel {
                 None
             };
 
+            let workspace_id = workspace.read_with(cx, |workspace, _| workspace.database_id())?;
+            let (restored_mode, mode_usage) = match workspace_id {
+                Some(workspace_id) => {
+                    cx.background_spawn(async move {
+                        let mode_usage = ModeUsage::load(workspace_id)
+                            .log_err()
+                            .flatten()
+                            .unwrap_or_default();
+                        (AgentMode::restore_active(workspace_id, &[]), mode_usage)
+                    })
+                    .await
+                }
+                None => (None, ModeUsage::default()),
+            };
+
             let slash_commands = Arc::new(SlashCommandWorkingSet::default());
             let text_thread_store = workspace
                 .update(cx, |workspace, cx| {
@@ -500,7 +574,18 @@ impl AgentPanel {
                 let panel =
                     cx.new(|cx| Self::new(workspace, text_thread_store, prompt_store, window, cx));
 
+                // Without a mode from a previous session, start in the default one for
+                // the language of the file being edited
+                let active_mode = restored_mode.unwrap_or_else(|| {
+                    let defaults = LanguageModeDefaults::get(cx);
+                    workspace
+                        .active_item_as::<Editor>(cx)
+                        .map(|editor| defaults.default_mode_for_editor(editor.read(cx), cx))
+                        .unwrap_or_else(|| defaults.fallback().clone())
+                });
                 panel.as_mut(cx).loading = true;
+                panel.as_mut(cx).active_mode = active_mode;
+                panel.as_mut(cx).mode_usage = mode_usage;
                 if let Some(serialized_panel) = serialized_panel {
                     panel.update(cx, |panel, cx| {
                         panel.width = serialized_panel.width.map(|w| w.round());
@@ -690,6 +775,9 @@ impl AgentPanel {
             acp_history,
             history_store,
             selected_agent: AgentType::default(),
+            active_mode: AgentMode::default(),
+            request_mode: None,
+            mode_usage: ModeUsage::default(),
             loading: false,
         };
 
@@ -750,6 +838,56 @@ impl AgentPanel {
         }
     }
 
+    /// Inserts text into the composer of the active thread and focuses it,
+    /// leaving it to the user to send.
+    pub(crate) fn insert_into_composer(
+        &mut self,
+        text: &str,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(thread_view) = self.active_thread_view() {
+            thread_view.update(cx, |thread_view, cx| {
+                thread_view.insert_text(text, window, cx);
+            });
+        } else if let Some(text_thread_editor) = self.active_text_thread_editor() {
+            let editor = text_thread_editor.read(cx).editor().clone();
+            editor.update(cx, |editor, cx| {
+                editor.insert(text, window, cx);
+            });
+            window.focus(&editor.focus_handle(cx));
+        }
+    }
+
+    /// Inserts text into the composer of the active thread and sends it.
+    ///
+    /// Text threads can't be sent on the user's behalf, so the text is only inserted.
+    pub(crate) fn send_prompt(&mut self, text: &str, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(thread_view) = self.active_thread_view() {
+            thread_view.update(cx, |thread_view, cx| {
+                thread_view.insert_text(text, window, cx);
+                thread_view.send(window, cx);
+            });
+        } else {
+            self.insert_into_composer(text, window, cx);
+        }
+    }
+
+    /// Like `send_prompt`, but the request runs in `mode` without switching the active
+    /// mode, which is neither changed for later requests nor persisted
+    pub(crate) fn send_prompt_in_mode(
+        &mut self,
+        text: &str,
+        mode: AgentMode,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.active_thread_view().is_some() {
+            self.request_mode = Some(mode);
+        }
+        self.send_prompt(text, window, cx);
+    }
+
     fn new_thread(&mut self, _action: &NewThread, window: &mut Window, cx: &mut Context<Self>) {
         self.new_agent_thread(AgentType::NativeAgent, window, cx);
     }
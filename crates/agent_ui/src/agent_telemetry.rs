@@ -0,0 +1,129 @@
+/// Agent Telemetry
+///
+/// This module builds the telemetry events reported for quick edits, agent mode
+/// changes and code template use. Events only carry coarse, non-identifying
+/// properties: never code, file contents, paths or user-chosen names.
+
+use std::collections::HashMap;
+
+use crate::agent_modes::AgentMode;
+use crate::code_templates::{builtin_templates, conversion_templates};
+
+/// Reported in place of names the user chose, such as custom modes and templates
+const CUSTOM_NAME: &str = "custom";
+
+/// Bucket a selection's line count so events don't reveal its exact size
+pub fn selection_size_bucket(line_count: u32) -> &'static str {
+    match line_count {
+        0..=1 => "1",
+        2..=10 => "2-10",
+        11..=50 => "11-50",
+        51..=200 => "51-200",
+        _ => "200+",
+    }
+}
+
+/// Event for a quick edit submitted on a selection of `line_count` lines
+pub fn quick_edit_submitted_event(line_count: u32, language: Option<&str>) -> telemetry::Event {
+    event(
+        "Agent Quick Edit Submitted",
+        [
+            ("selection_size", selection_size_bucket(line_count).into()),
+            ("language", language.into()),
+        ],
+    )
+}
+
+/// Event for switching the agent panel from one mode to another
+pub fn agent_mode_changed_event(from: &AgentMode, to: &AgentMode) -> telemetry::Event {
+    event(
+        "Agent Mode Changed",
+        [("from", mode_id(from).into()), ("to", mode_id(to).into())],
+    )
+}
+
+/// Event for running the code template named `template_name`
+pub fn code_template_used_event(template_name: &str) -> telemetry::Event {
+    let template = builtin_templates()
+        .into_iter()
+        .chain(conversion_templates())
+        .find(|template| template.name == template_name)
+        .map_or_else(|| CUSTOM_NAME.to_string(), |template| template.name);
+    event("Agent Code Template Used", [("template", template.into())])
+}
+
+fn mode_id(mode: &AgentMode) -> String {
+    match mode {
+        AgentMode::Custom { .. } => CUSTOM_NAME.to_string(),
+        mode => mode.to_string(),
+    }
+}
+
+fn event<const N: usize>(
+    event_type: &str,
+    properties: [(&str, serde_json::Value); N],
+) -> telemetry::Event {
+    telemetry::Event {
+        event_type: event_type.to_string(),
+        event_properties: HashMap::from_iter(
+            properties
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property_keys(event: &telemetry::Event) -> Vec<&str> {
+        let mut keys = event
+            .event_properties
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn test_selection_size_bucket() {
+        assert_eq!(selection_size_bucket(0), "1");
+        assert_eq!(selection_size_bucket(1), "1");
+        assert_eq!(selection_size_bucket(10), "2-10");
+        assert_eq!(selection_size_bucket(11), "11-50");
+        assert_eq!(selection_size_bucket(200), "51-200");
+        assert_eq!(selection_size_bucket(201), "200+");
+    }
+
+    #[test]
+    fn test_events_only_contain_allowed_fields() {
+        let event = quick_edit_submitted_event(42, Some("Rust"));
+        assert_eq!(event.event_type, "Agent Quick Edit Submitted");
+        assert_eq!(property_keys(&event), ["language", "selection_size"]);
+        assert_eq!(event.event_properties["selection_size"], "11-50");
+        assert_eq!(event.event_properties["language"], "Rust");
+        assert!(quick_edit_submitted_event(1, None).event_properties["language"].is_null());
+
+        let custom_mode = AgentMode::custom(
+            "Refactor the billing module",
+            ["read_file"],
+            "Private description",
+        );
+        let event = agent_mode_changed_event(&AgentMode::Ask, &custom_mode);
+        assert_eq!(event.event_type, "Agent Mode Changed");
+        assert_eq!(property_keys(&event), ["from", "to"]);
+        assert_eq!(event.event_properties["from"], "ask");
+        assert_eq!(event.event_properties["to"], "custom");
+
+        let event = code_template_used_event("Add unit tests");
+        assert_eq!(event.event_type, "Agent Code Template Used");
+        assert_eq!(property_keys(&event), ["template"]);
+        assert_eq!(event.event_properties["template"], "Add unit tests");
+        assert_eq!(
+            code_template_used_event("Rewrite /home/me/secret.rs").event_properties["template"],
+            "custom"
+        );
+    }
+}
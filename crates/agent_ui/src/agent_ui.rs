@@ -5,7 +5,9 @@ mod agent_diff;
 mod agent_model_selector;
 mod agent_modes;
 mod agent_panel;
+mod agent_telemetry;
 mod buffer_codegen;
+mod code_templates;
 mod completion_provider;
 mod context;
 mod context_server_configuration;
@@ -15,6 +17,7 @@ mod language_model_selector;
 mod mention_set;
 mod profile_selector;
 mod quick_edit;
+mod quick_edit_input;
 mod slash_command;
 mod slash_command_picker;
 mod terminal_codegen;
@@ -50,10 +53,12 @@ use std::any::TypeId;
 
 use crate::agent_configuration::{ConfigureContextServerModal, ManageProfilesModal};
 pub use crate::agent_panel::{AgentPanel, ConcreteAssistantPanelDelegate};
-pub use crate::agent_modes::AgentMode;
+pub use crate::agent_modes::{
+    AgentMode, AgentModeInfo, EditAction, ModelCapabilities, ToolPermission,
+};
 pub use crate::inline_assistant::InlineAssistant;
-pub use crate::quick_edit::QuickEdit;
-pub use crate::visual_indicators::{TokenInfo, SelectionInfo};
+pub use crate::quick_edit::{QuickEdit, QuickEditError, QuickEditScope, open_quick_edit};
+pub use crate::visual_indicators::{SelectionInfo, SelectionStatsCache, TokenBreakdown, TokenInfo};
 pub use agent_diff::{AgentDiffPane, AgentDiffToolbar};
 pub use text_thread_editor::{AgentPanelDelegate, TextThreadEditor};
 use zed_actions;
@@ -131,8 +136,18 @@ actions!(
         ToggleBurnMode,
         /// Send selected code to the agent panel in the main thread.
         SendSelectionToAgent,
+        /// Send the whole active file to the agent panel.
+        SendFileToAgent,
         /// Open quick edit UI for current selection.
         OpenQuickEditForSelection,
+        /// Keep the quick edit proposed in the editor.
+        AcceptQuickEdit,
+        /// Discard the quick edit proposed in the editor, restoring the original text.
+        RejectQuickEdit,
+        /// Show or hide the full prompt in the quick edit input, to edit it before it's sent.
+        ToggleQuickEditFullPrompt,
+        /// Re-run the most recent quick edit prompt on the current selection.
+        RepeatLastQuickEdit,
     ]
 );
 
@@ -246,6 +261,7 @@ pub fn init(
     assistant_slash_command::init(cx);
     agent_panel::init(cx);
     agent_context_menu::init(cx);
+    quick_edit::init(cx);
     context_server_configuration::init(language_registry.clone(), fs.clone(), cx);
     TextThreadEditor::init(cx);
 
@@ -267,6 +283,7 @@ pub fn init(
     cx.observe_new(|workspace: &mut Workspace, _window, cx| {
         workspace.register_action(quick_edit::handle_quick_edit);
         workspace.register_action(quick_edit::handle_send_selection_to_agent);
+        workspace.register_action(agent_context_menu::handle_send_file_to_agent);
     })
     .detach();
 
@@ -490,6 +507,15 @@ mod tests {
             expand_terminal_card: true,
             use_modifier_to_send: true,
             message_editor_min_lines: 1,
+            quick_edit_response_reserve: 4096,
+            quick_edit_max_retries: 2,
+            quick_edit_prompt_history_size: 20,
+            quick_edit_context_lines: 10,
+            sensitive_paths: Default::default(),
+            code_templates: Vec::new(),
+            visual_indicators: Default::default(),
+            default_mode: None,
+            language_default_modes: Default::default(),
         };
 
         cx.update(|cx| {
@@ -0,0 +1,1153 @@
+/// Code Generation Templates
+///
+/// This module provides reusable prompt templates behind the
+/// "Generate From Template" context menu entry. A template combines a fixed
+/// instruction with the selected code and is sent through quick edit.
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use agent_settings::AgentSettings;
+use editor::Editor;
+use fuzzy::{StringMatchCandidate, match_strings};
+use gpui::{
+    App, BackgroundExecutor, Context, DismissEvent, Entity, EventEmitter, FocusHandle, Focusable,
+    Render, Task, WeakEntity, Window,
+};
+use language::Point;
+use multi_buffer::{MultiBufferOffset, MultiBufferRow, MultiBufferSnapshot};
+use picker::{Picker, PickerDelegate};
+use settings::{CodeTemplateContent, CodeTemplateInsertion, Settings as _};
+use ui::{ListItem, ListItemSpacing, prelude::*};
+use util::ResultExt as _;
+use workspace::{ModalView, Workspace};
+
+use crate::agent_modes::{AgentMode, EditAction};
+use crate::agent_telemetry::code_template_used_event;
+use crate::quick_edit::{ContextInfo, active_agent_mode, format_agent_prompt, start_quick_edit};
+
+/// A reusable instruction for generating or transforming code
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeTemplate {
+    pub name: String,
+    pub description: String,
+    /// Instruction sent to the agent ahead of the selected code
+    pub prompt_body: String,
+    /// Language the template applies to, as returned by `ContextInfo::infer_language`.
+    /// `None` means the template applies to any language.
+    pub target_language: Option<String>,
+    /// Where the generated code goes relative to the selection
+    pub insertion: InsertionMode,
+}
+
+/// Where generated code is written relative to the selection it was generated from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InsertionMode {
+    /// Replace the selected code
+    #[default]
+    ReplaceSelection,
+    /// Insert on new lines above the selection's first line
+    InsertAbove,
+    /// Insert on new lines below the selection's last line
+    InsertBelow,
+    /// Insert at the end of the file
+    AppendToFile,
+}
+
+impl InsertionMode {
+    /// The kind of edit writing generated code this way makes, to check against the agent mode
+    pub fn edit_action(self) -> EditAction {
+        match self {
+            Self::ReplaceSelection => EditAction::Modify {
+                within_selection: true,
+            },
+            Self::InsertAbove | Self::InsertBelow | Self::AppendToFile => EditAction::Create,
+        }
+    }
+
+    /// Get the offset range the generated code is written to, for a `selection` of `buffer`.
+    ///
+    /// Every mode but `ReplaceSelection` yields an empty range, so that nothing is replaced.
+    pub fn target_range(
+        self,
+        buffer: &MultiBufferSnapshot,
+        selection: Range<usize>,
+    ) -> Range<usize> {
+        let offset = match self {
+            Self::ReplaceSelection => return selection,
+            Self::InsertAbove => {
+                let start_row = buffer
+                    .offset_to_point(MultiBufferOffset(selection.start))
+                    .row;
+                buffer.point_to_offset(Point::new(start_row, 0))
+            }
+            Self::InsertBelow => {
+                let end = buffer.offset_to_point(MultiBufferOffset(selection.end));
+                // A selection of whole lines ends at the start of the next line
+                let end_row = if end.column == 0 && selection.end > selection.start {
+                    end.row.saturating_sub(1)
+                } else {
+                    end.row
+                };
+                if end_row < buffer.max_point().row {
+                    buffer.point_to_offset(Point::new(end_row + 1, 0))
+                } else {
+                    buffer.point_to_offset(Point::new(
+                        end_row,
+                        buffer.line_len(MultiBufferRow(end_row)),
+                    ))
+                }
+            }
+            Self::AppendToFile => buffer.len(),
+        };
+        offset.0..offset.0
+    }
+}
+
+impl CodeTemplate {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        prompt_body: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            prompt_body: prompt_body.into(),
+            target_language: None,
+            insertion: InsertionMode::default(),
+        }
+    }
+
+    /// Write the generated code as `insertion` describes instead of replacing the selection
+    pub fn with_insertion(mut self, insertion: InsertionMode) -> Self {
+        self.insertion = insertion;
+        self
+    }
+
+    /// Restrict this template to a single language
+    pub fn with_target_language(mut self, language: impl Into<String>) -> Self {
+        self.target_language = Some(language.into());
+        self
+    }
+
+    /// Whether this template is relevant for code in `language`
+    pub fn applies_to(&self, language: &str) -> bool {
+        match &self.target_language {
+            Some(target) => target.eq_ignore_ascii_case(language),
+            None => true,
+        }
+    }
+
+    /// Build the quick edit prompt for applying this template to `code`
+    pub fn build_prompt(&self, context: &ContextInfo, code: &str) -> String {
+        format_agent_prompt(&self.prompt_body, context, code)
+    }
+}
+
+/// Get the templates that ship with Zed
+pub fn builtin_templates() -> Vec<CodeTemplate> {
+    vec![
+        CodeTemplate::new(
+            "Add unit tests",
+            "Write unit tests covering the selected code",
+            "Write unit tests for the following code. Cover the main behavior and important edge cases, and follow the testing conventions of the language.",
+        )
+        .with_insertion(InsertionMode::InsertBelow),
+        CodeTemplate::new(
+            "Add docstring",
+            "Document the selected code",
+            "Add documentation comments to the following code using the idiomatic doc comment style for the language. Do not change the code itself.",
+        )
+        .with_insertion(InsertionMode::InsertAbove),
+        CodeTemplate::new(
+            "Convert to async",
+            "Rewrite the selected code to be asynchronous",
+            "Convert the following code to use async/await, updating signatures and call sites within the selection as needed.",
+        ),
+        CodeTemplate::new(
+            "Add error handling",
+            "Handle failure cases in the selected code",
+            "Add error handling to the following code. Propagate errors where the caller should handle them and avoid panicking.",
+        ),
+    ]
+}
+
+/// Appended to every conversion so that its result can replace the selection as is
+const CONVERSION_OUTPUT_INSTRUCTION: &str = "Return only the converted code, without explanations or Markdown fences, so that it can replace the selection as is.";
+
+/// Conversions between idioms of a language, as `(name, description, instruction, languages)`
+const CONVERSIONS: &[(&str, &str, &str, &[&str])] = &[
+    (
+        "Convert callbacks to promises",
+        "Rewrite callback-based code to use promises",
+        "Convert the following callback-based code to return and consume promises.",
+        &["javascript", "typescript"],
+    ),
+    (
+        "Convert promise chains to async/await",
+        "Rewrite .then() chains with async/await",
+        "Convert the promise chains in the following code to async/await, keeping error handling equivalent.",
+        &["javascript", "typescript"],
+    ),
+    (
+        "Convert var to let/const",
+        "Replace var declarations with block-scoped ones",
+        "Replace every `var` declaration in the following code with `const`, or with `let` when the variable is reassigned.",
+        &["javascript", "typescript"],
+    ),
+    (
+        "Convert loops to iterators",
+        "Rewrite loops as iterator chains",
+        "Convert the loops in the following code to iterator chains where that keeps the code readable.",
+        &["rust"],
+    ),
+    (
+        "Convert unwrap to ?",
+        "Propagate errors instead of panicking",
+        "Replace `unwrap` and `expect` calls in the following code with the `?` operator, adjusting the return type if needed.",
+        &["rust"],
+    ),
+    (
+        "Convert to f-strings",
+        "Rewrite string formatting with f-strings",
+        "Convert `%` formatting and `str.format` calls in the following code to f-strings.",
+        &["python"],
+    ),
+];
+
+/// Get the "convert selection" templates, one per conversion and target language.
+///
+/// Conversions replace the selection with the converted code.
+pub fn conversion_templates() -> Vec<CodeTemplate> {
+    CONVERSIONS
+        .iter()
+        .flat_map(|(name, description, instruction, languages)| {
+            languages.iter().map(move |language| {
+                CodeTemplate::new(
+                    *name,
+                    *description,
+                    format!("{instruction} {CONVERSION_OUTPUT_INSTRUCTION}"),
+                )
+                .with_target_language(*language)
+            })
+        })
+        .collect()
+}
+
+impl From<CodeTemplateInsertion> for InsertionMode {
+    fn from(insertion: CodeTemplateInsertion) -> Self {
+        match insertion {
+            CodeTemplateInsertion::ReplaceSelection => Self::ReplaceSelection,
+            CodeTemplateInsertion::InsertAbove => Self::InsertAbove,
+            CodeTemplateInsertion::InsertBelow => Self::InsertBelow,
+            CodeTemplateInsertion::AppendToFile => Self::AppendToFile,
+        }
+    }
+}
+
+/// Build templates from the user's `agent.code_templates` entries.
+///
+/// Entries with an empty name or prompt body are skipped so that one mistake doesn't
+/// hide every other template.
+pub fn load_user_templates(entries: &[CodeTemplateContent]) -> Vec<CodeTemplate> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            if entry.name.trim().is_empty() || entry.prompt_body.trim().is_empty() {
+                log::warn!("skipping code template with an empty name or prompt body");
+                return None;
+            }
+            Some(CodeTemplate {
+                name: entry.name.clone(),
+                description: entry.description.clone(),
+                prompt_body: entry.prompt_body.clone(),
+                target_language: entry.target_language.clone(),
+                insertion: entry.insertion.unwrap_or_default().into(),
+            })
+        })
+        .collect()
+}
+
+/// Merge user templates into the built-in ones.
+///
+/// A user template replaces a built-in template with the same name in place,
+/// and any other user templates are appended after the built-ins.
+pub fn merge_templates(
+    mut templates: Vec<CodeTemplate>,
+    user_templates: Vec<CodeTemplate>,
+) -> Vec<CodeTemplate> {
+    for user_template in user_templates {
+        match templates
+            .iter_mut()
+            .find(|template| template.name.eq_ignore_ascii_case(&user_template.name))
+        {
+            Some(existing) => *existing = user_template,
+            None => templates.push(user_template),
+        }
+    }
+    templates
+}
+
+/// A test framework and where its tests conventionally live
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestFramework {
+    /// Short identifier of the framework or the command that runs it, e.g. `cargo test`
+    pub id: &'static str,
+    pub name: &'static str,
+    pub location: &'static str,
+}
+
+/// The conventional test framework of each language, keyed by `ContextInfo::infer_language`
+const TEST_FRAMEWORKS: &[(&str, TestFramework)] = &[
+    (
+        "rust",
+        TestFramework {
+            id: "cargo test",
+            name: "Rust's built-in #[test] harness",
+            location: "a #[cfg(test)] mod tests at the bottom of the same file",
+        },
+    ),
+    (
+        "python",
+        TestFramework {
+            id: "pytest",
+            name: "pytest",
+            location: "a test_<module>.py file in the tests directory",
+        },
+    ),
+    (
+        "typescript",
+        TestFramework {
+            id: "jest",
+            name: "Jest",
+            location: "a <module>.test.ts file next to the source file",
+        },
+    ),
+    (
+        "javascript",
+        TestFramework {
+            id: "jest",
+            name: "Jest",
+            location: "a <module>.test.js file next to the source file",
+        },
+    ),
+    (
+        "go",
+        TestFramework {
+            id: "go test",
+            name: "Go's testing package",
+            location: "a <file>_test.go file in the same package",
+        },
+    ),
+    (
+        "java",
+        TestFramework {
+            id: "junit",
+            name: "JUnit 5",
+            location: "the matching test class under src/test/java",
+        },
+    ),
+    (
+        "cpp",
+        TestFramework {
+            id: "googletest",
+            name: "GoogleTest",
+            location: "a test file in the tests directory",
+        },
+    ),
+];
+
+/// Get the conventional test framework for `language`, as returned by `ContextInfo::infer_language`
+pub fn test_framework_for_language(language: &str) -> Option<TestFramework> {
+    TEST_FRAMEWORKS
+        .iter()
+        .find(|(framework_language, _)| *framework_language == language)
+        .map(|(_, framework)| *framework)
+}
+
+/// Get the short identifier of the conventional test framework for `language`,
+/// such as `cargo test` or `pytest`
+pub fn test_framework_for(language: &str) -> Option<&'static str> {
+    test_framework_for_language(language).map(|framework| framework.id)
+}
+
+/// Get the "Generate tests" template for code in `language`, targeting its
+/// conventional test framework when one is known
+pub fn generate_tests_template(language: &str) -> CodeTemplate {
+    let prompt_body = match test_framework_for_language(language) {
+        Some(framework) => format!(
+            "Write tests for the following code using {}. Cover the main behavior and important edge cases, and place the tests in {}.",
+            framework.name, framework.location
+        ),
+        None => "Write appropriate tests for the following code. Cover the main behavior and important edge cases, and place the tests where the project's conventions expect them.".to_string(),
+    };
+    CodeTemplate::new(
+        "Generate tests",
+        "Write tests for the selected code",
+        prompt_body,
+    )
+    .with_insertion(InsertionMode::InsertBelow)
+}
+
+/// The idiomatic documentation comment style of a language
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DocCommentStyle {
+    /// `///` line comments, as in Rust
+    TripleSlash,
+    /// `//` line comments, as in Go
+    DoubleSlash,
+    /// A `/** ... */` block, as in JSDoc and Javadoc
+    SlashStarStar,
+    /// A `"""` docstring as the first statement of the body, as in Python
+    TripleQuote,
+}
+
+impl DocCommentStyle {
+    /// Get the doc comment style for `language`, as returned by `ContextInfo::infer_language`
+    pub fn for_language(language: &str) -> Option<Self> {
+        match language {
+            "rust" => Some(Self::TripleSlash),
+            "go" => Some(Self::DoubleSlash),
+            "typescript" | "javascript" | "java" | "c" | "cpp" => Some(Self::SlashStarStar),
+            "python" => Some(Self::TripleQuote),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::TripleSlash => "`///` doc comments",
+            Self::DoubleSlash => "`//` comments starting with the symbol's name",
+            Self::SlashStarStar => "a `/** ... */` block comment",
+            Self::TripleQuote => "a `\"\"\"` docstring",
+        }
+    }
+
+    /// Get the row at which the doc comment is inserted for a symbol starting at `symbol_row`.
+    ///
+    /// Docstrings go on the line after the signature, every other style on the line above the symbol.
+    pub fn insertion_row(&self, symbol_row: u32) -> u32 {
+        match self {
+            Self::TripleQuote => symbol_row + 1,
+            Self::TripleSlash | Self::DoubleSlash | Self::SlashStarStar => symbol_row,
+        }
+    }
+}
+
+/// Build the quick edit prompt asking for only the doc comment of `code`
+pub fn generate_docstring_prompt(
+    style: Option<DocCommentStyle>,
+    context: &ContextInfo,
+    code: &str,
+) -> String {
+    let style = style.map_or(
+        "the idiomatic doc comment style for the language",
+        |style| style.description(),
+    );
+    let instruction = format!(
+        "Write documentation for the following code using {style}. Reply with only the doc comment, indented to match the code, without repeating the code itself."
+    );
+    format_agent_prompt(&instruction, context, code)
+}
+
+/// Get the built-in templates and conversions merged with those from the user's settings
+pub fn available_templates(cx: &App) -> Vec<CodeTemplate> {
+    let mut templates = builtin_templates();
+    templates.extend(conversion_templates());
+    merge_templates(
+        templates,
+        load_user_templates(&AgentSettings::get_global(cx).code_templates),
+    )
+}
+
+/// Find a template by name, ignoring case
+pub fn template_named<'a>(templates: &'a [CodeTemplate], name: &str) -> Option<&'a CodeTemplate> {
+    templates
+        .iter()
+        .find(|template| template.name.eq_ignore_ascii_case(name))
+}
+
+/// Get the templates relevant for code in `language`
+pub fn templates_for_language<'a>(
+    templates: &'a [CodeTemplate],
+    language: &str,
+) -> Vec<&'a CodeTemplate> {
+    templates
+        .iter()
+        .filter(|template| template.applies_to(language))
+        .collect()
+}
+
+/// Rank the templates applicable to `language` against `query`, returning their indices.
+///
+/// The query is fuzzy matched against each template's name and description. Templates
+/// targeting `language` specifically come before generic ones, and within each group
+/// better matches come first. An empty query keeps every applicable template in order.
+pub async fn rank_templates(
+    templates: &[CodeTemplate],
+    query: &str,
+    language: &str,
+    executor: BackgroundExecutor,
+) -> Vec<usize> {
+    let applicable = templates
+        .iter()
+        .enumerate()
+        .filter(|(_, template)| template.applies_to(language))
+        .map(|(ix, _)| ix)
+        .collect::<Vec<_>>();
+    let mut ranked = if query.is_empty() {
+        applicable
+            .into_iter()
+            .map(|ix| (ix, 0.0))
+            .collect::<Vec<_>>()
+    } else {
+        let candidates = applicable
+            .iter()
+            .map(|&ix| {
+                let template = &templates[ix];
+                StringMatchCandidate::new(
+                    ix,
+                    &format!("{} {}", template.name, template.description),
+                )
+            })
+            .collect::<Vec<_>>();
+        match_strings(
+            &candidates,
+            query,
+            false,
+            true,
+            candidates.len(),
+            &AtomicBool::new(false),
+            executor,
+        )
+        .await
+        .into_iter()
+        .map(|string_match| (string_match.candidate_id, string_match.score))
+        .collect()
+    };
+
+    // Stable, so ties keep the templates' own order
+    ranked.sort_by(|(a_ix, a_score), (b_ix, b_score)| {
+        let a_targeted = templates[*a_ix].target_language.is_some();
+        let b_targeted = templates[*b_ix].target_language.is_some();
+        b_targeted.cmp(&a_targeted).then(b_score.total_cmp(a_score))
+    });
+    ranked.into_iter().map(|(ix, _)| ix).collect()
+}
+
+/// Modal picker listing the templates applicable to the current selection
+pub struct CodeTemplatePicker {
+    picker: Entity<Picker<CodeTemplatePickerDelegate>>,
+}
+
+impl CodeTemplatePicker {
+    pub(crate) fn toggle(
+        workspace: &mut Workspace,
+        editor: Entity<Editor>,
+        range: Range<usize>,
+        code: String,
+        context: ContextInfo,
+        window: &mut Window,
+        cx: &mut Context<Workspace>,
+    ) {
+        let templates = available_templates(cx).into();
+        let workspace_handle = cx.entity().downgrade();
+
+        workspace.toggle_modal(window, cx, move |window, cx| {
+            let delegate = CodeTemplatePickerDelegate {
+                template_picker: cx.entity().downgrade(),
+                workspace: workspace_handle,
+                editor,
+                range,
+                code,
+                context,
+                templates,
+                matches: Vec::new(),
+                selected_index: 0,
+            };
+            let picker = cx.new(|cx| Picker::uniform_list(delegate, window, cx));
+            Self { picker }
+        });
+    }
+}
+
+impl Render for CodeTemplatePicker {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        v_flex()
+            .key_context("CodeTemplatePicker")
+            .w(rems(34.))
+            .child(self.picker.clone())
+    }
+}
+
+impl Focusable for CodeTemplatePicker {
+    fn focus_handle(&self, cx: &App) -> FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodeTemplatePicker {}
+impl ModalView for CodeTemplatePicker {}
+
+pub struct CodeTemplatePickerDelegate {
+    template_picker: WeakEntity<CodeTemplatePicker>,
+    workspace: WeakEntity<Workspace>,
+    editor: Entity<Editor>,
+    range: Range<usize>,
+    code: String,
+    context: ContextInfo,
+    templates: Arc<[CodeTemplate]>,
+    /// Indices into `templates` matching the current query, best match first
+    matches: Vec<usize>,
+    selected_index: usize,
+}
+
+impl PickerDelegate for CodeTemplatePickerDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _window: &mut Window, _cx: &mut App) -> Arc<str> {
+        "Select a template…".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(
+        &mut self,
+        ix: usize,
+        _window: &mut Window,
+        _: &mut Context<Picker<Self>>,
+    ) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(
+        &mut self,
+        query: String,
+        window: &mut Window,
+        cx: &mut Context<Picker<Self>>,
+    ) -> Task<()> {
+        let templates = self.templates.clone();
+        let language = self.context.infer_language();
+        let executor = cx.background_executor().clone();
+        cx.spawn_in(window, async move |picker, cx| {
+            let matches = rank_templates(&templates, &query, &language, executor).await;
+            picker
+                .update(cx, |picker, cx| {
+                    picker.delegate.matches = matches;
+                    picker.delegate.selected_index = 0;
+                    cx.notify();
+                })
+                .log_err();
+        })
+    }
+
+    fn confirm(&mut self, _secondary: bool, window: &mut Window, cx: &mut Context<Picker<Self>>) {
+        let Some(template) = self
+            .matches
+            .get(self.selected_index)
+            .and_then(|ix| self.templates.get(*ix))
+        else {
+            return;
+        };
+
+        let prompt = template.build_prompt(&self.context, &self.code);
+        let editor = self.editor.clone();
+        let buffer = editor.read(cx).buffer().read(cx).snapshot(cx);
+        let range = template.insertion.target_range(&buffer, self.range.clone());
+        let edit_action = template.insertion.edit_action();
+        telemetry::send_event(code_template_used_event(&template.name));
+        self.workspace
+            .update(cx, |workspace, cx| {
+                // Whether the generated code is proposed or applied follows the active mode
+                let mode = active_agent_mode(workspace, editor.read(cx), cx);
+                // The template's prompt already holds the code, so it's sent as is
+                let state = start_quick_edit(
+                    workspace,
+                    editor,
+                    range,
+                    prompt.clone(),
+                    Some(prompt),
+                    window,
+                    cx,
+                );
+                if let Ok(state) = state {
+                    state.update(cx, |state, _| {
+                        state.set_propose_edits(mode.requires_confirmation(edit_action));
+                    });
+                }
+            })
+            .log_err();
+        self.dismissed(window, cx);
+    }
+
+    fn dismissed(&mut self, _window: &mut Window, cx: &mut Context<Picker<Self>>) {
+        self.template_picker
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _window: &mut Window,
+        _cx: &mut Context<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let template = self.templates.get(*self.matches.get(ix)?)?;
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .toggle_state(selected)
+                .child(
+                    v_flex().child(Label::new(template.name.clone())).child(
+                        Label::new(template.description.clone())
+                            .size(LabelSize::Small)
+                            .color(Color::Muted),
+                    ),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use multi_buffer::MultiBuffer;
+    use settings::SettingsStore;
+
+    use crate::quick_edit::tests::{build_test_editor, set_fake_model};
+    use crate::quick_edit::{QuickEditState, selection_text_and_context};
+
+    fn rust_context() -> ContextInfo {
+        ContextInfo {
+            file_path: Some("src/lib.rs".to_string()),
+            relative_path: None,
+            start_line: 9,
+            end_line: 11,
+            start_column: 0,
+            end_column: 0,
+            language: None,
+        }
+    }
+
+    #[test]
+    fn test_builtin_template_lookup() {
+        let templates = builtin_templates();
+        for name in [
+            "Add unit tests",
+            "Add docstring",
+            "Convert to async",
+            "Add error handling",
+        ] {
+            assert!(template_named(&templates, name).is_some(), "missing {name}");
+        }
+        assert!(template_named(&templates, "add UNIT tests").is_some());
+        assert!(template_named(&templates, "Does not exist").is_none());
+    }
+
+    #[test]
+    fn test_templates_filtered_by_language() {
+        let mut templates = builtin_templates();
+        templates.push(
+            CodeTemplate::new("Derive Debug", "Add #[derive(Debug)]", "Derive Debug.")
+                .with_target_language("rust"),
+        );
+
+        let rust = templates_for_language(&templates, "rust");
+        assert!(rust.iter().any(|template| template.name == "Derive Debug"));
+
+        let python = templates_for_language(&templates, "python");
+        assert!(
+            !python
+                .iter()
+                .any(|template| template.name == "Derive Debug")
+        );
+        assert_eq!(python.len(), builtin_templates().len());
+    }
+
+    #[test]
+    fn test_conversion_templates_filtered_by_language() {
+        let conversions = conversion_templates();
+        let names = |language| {
+            templates_for_language(&conversions, language)
+                .into_iter()
+                .map(|template| template.name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        let rust = names("rust");
+        assert_eq!(rust, ["Convert loops to iterators", "Convert unwrap to ?"]);
+
+        let javascript = names("javascript");
+        assert!(javascript.contains(&"Convert var to let/const"));
+        assert!(javascript.contains(&"Convert callbacks to promises"));
+        assert!(!javascript.contains(&"Convert unwrap to ?"));
+        assert_eq!(names("typescript"), javascript);
+
+        assert!(names("").is_empty());
+
+        for conversion in &conversions {
+            assert!(
+                conversion
+                    .prompt_body
+                    .ends_with(CONVERSION_OUTPUT_INSTRUCTION)
+            );
+            assert_eq!(conversion.insertion, InsertionMode::ReplaceSelection);
+        }
+    }
+
+    async fn ranked_names(
+        templates: &[CodeTemplate],
+        query: &str,
+        language: &str,
+        cx: &gpui::TestAppContext,
+    ) -> Vec<String> {
+        rank_templates(templates, query, language, cx.executor())
+            .await
+            .into_iter()
+            .map(|ix| templates[ix].name.clone())
+            .collect()
+    }
+
+    #[gpui::test]
+    async fn test_rank_templates(cx: &mut gpui::TestAppContext) {
+        let mut templates = builtin_templates();
+        templates.extend(conversion_templates());
+
+        // JavaScript conversions are boosted above the generic conversion
+        let ranked = ranked_names(&templates, "convert", "javascript", cx).await;
+        let generic_ix = ranked
+            .iter()
+            .position(|name| *name == "Convert to async")
+            .unwrap();
+        assert_eq!(generic_ix, 3);
+        assert!(ranked[..generic_ix].contains(&"Convert var to let/const".to_string()));
+        assert!(!ranked.contains(&"Convert unwrap to ?".to_string()));
+
+        // The best match ranks first within its group
+        let ranked = ranked_names(&templates, "let const", "javascript", cx).await;
+        assert_eq!(ranked[0], "Convert var to let/const");
+
+        // Without a query every applicable template is listed, targeted ones first
+        let ranked = ranked_names(&templates, "", "rust", cx).await;
+        assert_eq!(ranked.len(), builtin_templates().len() + 2);
+        assert_eq!(
+            ranked[..2],
+            ["Convert loops to iterators", "Convert unwrap to ?"]
+        );
+        assert_eq!(ranked[2], "Add unit tests");
+    }
+
+    #[test]
+    fn test_user_templates_override_builtins() {
+        let user_templates = load_user_templates(&[
+            template_content(serde_json::json!({
+                "name": "add docstring",
+                "description": "Team doc style",
+                "prompt_body": "Document this using our team conventions.",
+            })),
+            template_content(serde_json::json!({
+                "name": "Add tracing",
+                "prompt_body": "Add tracing spans.",
+                "target_language": "rust",
+                "insertion": "insert_above",
+            })),
+        ]);
+        assert_eq!(user_templates.len(), 2);
+
+        let templates = merge_templates(builtin_templates(), user_templates);
+        assert_eq!(templates.len(), builtin_templates().len() + 1);
+
+        let docstring = template_named(&templates, "Add docstring").unwrap();
+        assert_eq!(docstring.description, "Team doc style");
+        assert_eq!(
+            docstring.prompt_body,
+            "Document this using our team conventions."
+        );
+        assert_eq!(templates[1].name, "add docstring");
+
+        let tracing = template_named(&templates, "Add tracing").unwrap();
+        assert_eq!(tracing.target_language.as_deref(), Some("rust"));
+        assert_eq!(tracing.description, "");
+        assert_eq!(tracing.insertion, InsertionMode::InsertAbove);
+        assert_eq!(docstring.insertion, InsertionMode::ReplaceSelection);
+    }
+
+    #[gpui::test]
+    async fn test_template_edits_follow_agent_mode(cx: &mut gpui::TestAppContext) {
+        let templates = builtin_templates();
+        let template = template_named(&templates, "Add error handling").unwrap();
+        assert_eq!(template.insertion, InsertionMode::ReplaceSelection);
+        let (workspace, editor, mut cx) =
+            build_test_editor("let a = one();\nlet b = two();\n", cx).await;
+        let model = set_fake_model(&mut cx);
+
+        // Quick edit mode proposes edits by default, but not those within the selection
+        for (mode, range, expect_proposal) in
+            [("quick-edit", 15..29, false), ("manual", 0..14, true)]
+        {
+            cx.update(|_, cx| {
+                cx.update_global::<SettingsStore, _>(|store, cx| {
+                    store.update_user_settings(cx, |settings| {
+                        settings.agent.get_or_insert_default().default_mode =
+                            Some(mode.to_string());
+                    });
+                });
+            });
+            let (code, context) = editor.update(&mut cx, |editor, cx| {
+                selection_text_and_context(editor, range.clone(), cx).unwrap()
+            });
+            workspace.update_in(&mut cx, |workspace, window, cx| {
+                let (code, context) = (code.clone(), context);
+                CodeTemplatePicker::toggle(
+                    workspace,
+                    editor.clone(),
+                    range,
+                    code,
+                    context,
+                    window,
+                    cx,
+                );
+            });
+            cx.run_until_parked();
+
+            let picker = workspace.update(&mut cx, |workspace, cx| {
+                let template_picker = workspace.active_modal::<CodeTemplatePicker>(cx).unwrap();
+                template_picker.read(cx).picker.clone()
+            });
+            picker.update_in(&mut cx, |picker, window, cx| {
+                let delegate = &mut picker.delegate;
+                delegate.selected_index = delegate
+                    .matches
+                    .iter()
+                    .position(|ix| delegate.templates[*ix].name == template.name)
+                    .unwrap();
+                delegate.confirm(false, window, cx);
+            });
+            cx.run_until_parked();
+            model.send_last_completion_stream_text_chunk(format!("{}?;", &code[..13]));
+            model.end_last_completion_stream();
+            cx.run_until_parked();
+
+            let state = cx
+                .update(|_, cx| QuickEditState::for_editor(&editor, cx))
+                .unwrap();
+            assert_eq!(
+                state.read_with(&cx, |state, _| state.has_pending_proposal()),
+                expect_proposal,
+                "{mode}"
+            );
+        }
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "let a = one()?;\nlet b = two()?;\n"
+        );
+
+        // Quick edit mode writes code generated next to the selection without asking
+        assert!(
+            !AgentMode::QuickEdit.requires_confirmation(InsertionMode::InsertBelow.edit_action())
+        );
+        assert!(AgentMode::Ask.requires_confirmation(InsertionMode::InsertBelow.edit_action()));
+    }
+
+    #[gpui::test]
+    async fn test_append_to_file_template(cx: &mut gpui::TestAppContext) {
+        let (workspace, editor, mut cx) = build_test_editor("fn a() {}\n", cx).await;
+        let model = set_fake_model(&mut cx);
+        cx.update(|_, cx| {
+            cx.update_global::<SettingsStore, _>(|store, cx| {
+                store.update_user_settings(cx, |settings| {
+                    let agent = settings.agent.get_or_insert_default();
+                    agent.default_mode = Some("quick-edit".to_string());
+                    agent.code_templates = Some(vec![CodeTemplateContent {
+                        name: "Add a sibling".to_string(),
+                        description: String::new(),
+                        prompt_body: "Write a similar function.".to_string(),
+                        target_language: None,
+                        insertion: Some(CodeTemplateInsertion::AppendToFile),
+                    }]);
+                });
+            });
+        });
+
+        let range = 0..9;
+        let (code, context) = editor.update(&mut cx, |editor, cx| {
+            selection_text_and_context(editor, range.clone(), cx).unwrap()
+        });
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            CodeTemplatePicker::toggle(workspace, editor.clone(), range, code, context, window, cx);
+        });
+        cx.run_until_parked();
+
+        let picker = workspace.update(&mut cx, |workspace, cx| {
+            let template_picker = workspace.active_modal::<CodeTemplatePicker>(cx).unwrap();
+            template_picker.read(cx).picker.clone()
+        });
+        picker.update_in(&mut cx, |picker, window, cx| {
+            let delegate = &mut picker.delegate;
+            delegate.selected_index = delegate
+                .matches
+                .iter()
+                .position(|ix| delegate.templates[*ix].name == "Add a sibling")
+                .unwrap();
+            delegate.confirm(false, window, cx);
+        });
+        cx.run_until_parked();
+        model.send_last_completion_stream_text_chunk("fn b() {}\n");
+        model.end_last_completion_stream();
+        cx.run_until_parked();
+
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn a() {}\nfn b() {}\n"
+        );
+    }
+
+    #[gpui::test]
+    fn test_insertion_mode_target_range(cx: &mut App) {
+        let buffer = MultiBuffer::build_simple("fn a() {}\nfn b() {\n    1\n}\nfn c() {}", cx)
+            .read(cx)
+            .snapshot(cx);
+        let target =
+            |mode: InsertionMode, selection: Range<usize>| mode.target_range(&buffer, selection);
+
+        // `fn b` spans rows 1 through 3
+        assert_eq!(target(InsertionMode::ReplaceSelection, 10..26), 10..26);
+        assert_eq!(target(InsertionMode::InsertAbove, 10..26), 10..10);
+        assert_eq!(target(InsertionMode::InsertAbove, 14..26), 10..10);
+        assert_eq!(target(InsertionMode::InsertBelow, 10..26), 27..27);
+        assert_eq!(target(InsertionMode::AppendToFile, 10..26), 36..36);
+
+        // A whole-line selection ends on the line it covers, not the next one
+        assert_eq!(target(InsertionMode::InsertBelow, 10..27), 27..27);
+        // Below the last line is the end of the file
+        assert_eq!(target(InsertionMode::InsertBelow, 27..30), 36..36);
+    }
+
+    fn template_content(value: serde_json::Value) -> CodeTemplateContent {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_invalid_user_templates_are_skipped() {
+        let user_templates = load_user_templates(&[
+            template_content(serde_json::json!({ "name": "Blank body", "prompt_body": "  " })),
+            template_content(serde_json::json!({ "name": "", "prompt_body": "Nameless" })),
+            template_content(
+                serde_json::json!({ "name": "Valid", "prompt_body": "Do the thing." }),
+            ),
+        ]);
+        assert_eq!(user_templates.len(), 1);
+        assert_eq!(user_templates[0].name, "Valid");
+    }
+
+    #[test]
+    fn test_test_framework_per_language() {
+        let framework_name =
+            |language: &str| test_framework_for_language(language).map(|framework| framework.name);
+        assert_eq!(
+            framework_name("rust"),
+            Some("Rust's built-in #[test] harness")
+        );
+        assert_eq!(framework_name("python"), Some("pytest"));
+        assert_eq!(framework_name("typescript"), Some("Jest"));
+        assert_eq!(framework_name("javascript"), Some("Jest"));
+        assert_eq!(framework_name("go"), Some("Go's testing package"));
+        assert_eq!(framework_name("java"), Some("JUnit 5"));
+        assert_eq!(framework_name("sql"), None);
+        assert_eq!(framework_name(""), None);
+    }
+
+    #[test]
+    fn test_test_framework_for() {
+        assert_eq!(test_framework_for("rust"), Some("cargo test"));
+        assert_eq!(test_framework_for("python"), Some("pytest"));
+        assert_eq!(test_framework_for("typescript"), Some("jest"));
+        assert_eq!(test_framework_for("javascript"), Some("jest"));
+        assert_eq!(test_framework_for("go"), Some("go test"));
+        assert_eq!(test_framework_for("java"), Some("junit"));
+        assert_eq!(test_framework_for("cobol"), None);
+        assert_eq!(test_framework_for("Rust"), None);
+    }
+
+    #[test]
+    fn test_generate_tests_template() {
+        let code = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}";
+        let prompt = generate_tests_template("rust").build_prompt(&rust_context(), code);
+        assert!(prompt.contains("#[test]"));
+        assert!(prompt.contains("#[cfg(test)] mod tests"));
+        assert!(prompt.contains("src/lib.rs"));
+        assert!(prompt.contains(code));
+
+        let python = generate_tests_template("python");
+        assert!(python.prompt_body.contains("pytest"));
+        assert!(python.prompt_body.contains("tests directory"));
+
+        let unknown = generate_tests_template("");
+        assert!(unknown.prompt_body.starts_with("Write appropriate tests"));
+    }
+
+    #[test]
+    fn test_doc_comment_style_per_language() {
+        assert_eq!(
+            DocCommentStyle::for_language("rust"),
+            Some(DocCommentStyle::TripleSlash)
+        );
+        assert_eq!(
+            DocCommentStyle::for_language("python"),
+            Some(DocCommentStyle::TripleQuote)
+        );
+        for language in ["typescript", "javascript", "java"] {
+            assert_eq!(
+                DocCommentStyle::for_language(language),
+                Some(DocCommentStyle::SlashStarStar),
+                "{language}"
+            );
+        }
+        assert_eq!(
+            DocCommentStyle::for_language("go"),
+            Some(DocCommentStyle::DoubleSlash)
+        );
+        assert_eq!(DocCommentStyle::for_language("sql"), None);
+
+        assert_eq!(DocCommentStyle::TripleSlash.insertion_row(9), 9);
+        assert_eq!(DocCommentStyle::TripleQuote.insertion_row(9), 10);
+    }
+
+    #[test]
+    fn test_generate_docstring_prompt() {
+        let code = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}";
+        let prompt =
+            generate_docstring_prompt(Some(DocCommentStyle::TripleSlash), &rust_context(), code);
+        assert!(prompt.contains("`///`"));
+        assert!(prompt.contains("only the doc comment"));
+        assert!(prompt.contains(code));
+
+        let prompt = generate_docstring_prompt(None, &rust_context(), code);
+        assert!(prompt.contains("idiomatic doc comment style"));
+    }
+
+    #[test]
+    fn test_template_prompt_assembly() {
+        let templates = builtin_templates();
+        let template = template_named(&templates, "Add docstring").unwrap();
+        let code = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}";
+        let prompt = template.build_prompt(&rust_context(), code);
+
+        assert!(prompt.starts_with(&template.prompt_body));
+        assert!(prompt.contains("src/lib.rs"));
+        assert!(prompt.contains("Lines 10-12"));
+        assert!(prompt.contains("```rust"));
+        assert!(prompt.contains(code));
+    }
+}
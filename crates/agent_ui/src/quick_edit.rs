@@ -4,12 +4,62 @@
 /// select code and directly ask the agent to modify it, similar to Cursor's
 /// quick edit functionality.
 
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::fmt;
 use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
 
-use editor::Editor;
-use gpui::{Action, App, Context, Entity, Subscription, WeakEntity, Window};
-use multi_buffer::MultiBufferOffset;
-use workspace::Workspace;
+use agent_settings::AgentSettings;
+use anyhow::{Context as _, Result, anyhow};
+use cloud_llm_client::CompletionIntent;
+use collections::{HashMap, HashSet};
+use editor::display_map::{
+    BlockPlacement, BlockProperties, BlockStyle, CustomBlockId, RenderBlock,
+};
+use editor::{Addon, Editor, EditorEvent, SelectionEffects, scroll::Autoscroll};
+use futures::{Stream, StreamExt as _};
+use git::{BuildPermalinkParams, GitHostingProviderRegistry, parse_git_remote_url};
+use gpui::{
+    Action, App, AppContext as _, Context, DispatchPhase, Entity, EntityId, Global, HighlightStyle,
+    IntoElement as _, KeyContext, ParentElement as _, SharedString, Styled as _, StyledText,
+    Subscription, Task, WeakEntity, Window, div,
+};
+use language::{BufferSnapshot, File, Language, Point, TransactionId};
+use language_model::{
+    ConfiguredModel, LanguageModelRegistry, LanguageModelRequest, LanguageModelRequestMessage, Role,
+};
+use multi_buffer::{
+    Anchor, MultiBufferOffset, MultiBufferRow, MultiBufferSnapshot, ToOffset as _, ToPoint as _,
+};
+use project::Project;
+use rope::Rope;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+use streaming_diff::{LineDiff, LineOperation, StreamingDiff};
+use theme::ActiveTheme as _;
+use util::ResultExt as _;
+use workspace::{Toast, Workspace, notifications::NotificationId};
+
+use crate::agent_context_menu::{
+    SelectionRequirement, has_actionable_selection, register_menu_action,
+};
+use crate::agent_modes::LanguageModeDefaults;
+use crate::agent_telemetry::quick_edit_submitted_event;
+use crate::quick_edit_input::{QuickEditInput, last_prompt};
+use crate::visual_indicators::{
+    GutterIndicator, GutterIndicatorSpan, MIN_TEXT_CONTRAST_RATIO, SelectionInfo,
+    SelectionStatsCache, TokenInfo, VisualIndicatorSettings, accepted_change_style,
+    diff_added_style, diff_removed_style, ensure_min_contrast, gutter_spans_for_rows,
+    merge_gutter_rows, rejected_change_style, resolve_row_styles,
+};
+use crate::{
+    AcceptQuickEdit, AgentMode, AgentPanel, OpenQuickEditForSelection, RejectQuickEdit,
+    RepeatLastQuickEdit, SendSelectionToAgent,
+};
 
 /// Quick edit action triggered from editor with selected code
 #[derive(Clone, PartialEq, Action)]
@@ -17,217 +67,3976 @@ use workspace::Workspace;
 pub struct QuickEdit {
     /// The prompt/instruction for editing
     pub prompt: String,
+    /// What part of the buffer the edit targets
+    pub scope: QuickEditScope,
+}
+
+/// What part of the active editor's buffer a quick edit targets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuickEditScope {
+    /// The selection, or the cursor's line when nothing is selected
+    #[default]
+    Selection,
+    /// The innermost symbol containing the selection, per the syntax tree's outline
+    EnclosingSymbol,
+    /// The whole buffer
+    WholeFile,
+}
+
+impl QuickEditScope {
+    /// Resolve the offset range of the editor's buffer this scope targets.
+    ///
+    /// `EnclosingSymbol` falls back to `Selection` outside of any symbol.
+    pub(crate) fn resolve_range(&self, editor: &Editor, cx: &mut App) -> Option<Range<usize>> {
+        match self {
+            Self::Selection => resolve_target_range(editor, cx),
+            Self::EnclosingSymbol => {
+                let display_snapshot = editor.display_snapshot(cx);
+                let selection = editor
+                    .selections
+                    .newest::<MultiBufferOffset>(&display_snapshot);
+                let symbol_range = symbol_ranges_containing(
+                    display_snapshot.buffer_snapshot(),
+                    &(selection.start..selection.end),
+                )
+                .into_iter()
+                .next();
+                match symbol_range {
+                    Some(symbol_range) => Some(symbol_range.start.0..symbol_range.end.0),
+                    None => resolve_target_range(editor, cx),
+                }
+            }
+            Self::WholeFile => Some(0..editor.buffer().read(cx).len(cx).0),
+        }
+    }
+}
+
+/// Make `OpenQuickEditForSelection` and `RepeatLastQuickEdit` available to editors with
+/// a non-empty selection, and `AcceptQuickEdit`/`RejectQuickEdit` to editors with a
+/// pending proposal.
+///
+/// The default keymaps bind `OpenQuickEditForSelection` to `cmd-k` on macOS and `ctrl-alt-k`
+/// elsewhere, scoped to the `Editor` context, so other `cmd-k` bindings are unaffected
+/// outside of editors. Accept and reject are bound to enter and escape in the
+/// `quick_edit_proposal` context, which editors only have while a proposal is pending.
+pub(crate) fn init(cx: &mut App) {
+    cx.observe_new(|editor: &mut Editor, _, _| {
+        editor
+            .register_action_renderer(|editor, window, cx| {
+                let Some(workspace) = editor.workspace() else {
+                    return;
+                };
+                let has_selection =
+                    has_actionable_selection(editor, SelectionRequirement::NonEmpty, cx);
+                register_menu_action::<OpenQuickEditForSelection>(
+                    &workspace,
+                    has_selection,
+                    handle_open_quick_edit_for_selection,
+                    window,
+                );
+                register_menu_action::<RepeatLastQuickEdit>(
+                    &workspace,
+                    has_selection,
+                    handle_repeat_last_quick_edit,
+                    window,
+                );
+
+                let proposal_state = QuickEditState::for_editor(&cx.entity(), cx)
+                    .filter(|state| state.read(cx).has_pending_proposal());
+                if let Some(state) = proposal_state {
+                    register_proposal_action::<AcceptQuickEdit>(
+                        state.clone(),
+                        QuickEditState::accept,
+                        window,
+                    );
+                    register_proposal_action::<RejectQuickEdit>(
+                        state,
+                        QuickEditState::reject,
+                        window,
+                    );
+                }
+            })
+            .detach();
+    })
+    .detach();
+}
+
+/// Run `handler` on the quick edit `state` when action `A` is dispatched
+fn register_proposal_action<A: Action>(
+    state: Entity<QuickEditState>,
+    handler: fn(&mut QuickEditState, &mut Context<QuickEditState>),
+    window: &mut Window,
+) {
+    window.on_action(TypeId::of::<A>(), move |_, phase, _, cx| {
+        if phase == DispatchPhase::Bubble {
+            state.update(cx, handler);
+        }
+    });
+}
+
+/// Adds the `quick_edit_proposal` key context to an editor while a quick edit proposal
+/// is pending in it
+struct QuickEditProposalAddon;
+
+impl Addon for QuickEditProposalAddon {
+    fn extend_key_context(&self, key_context: &mut KeyContext, _: &App) {
+        key_context.add("quick_edit_proposal");
+    }
+
+    fn to_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// How long edits must settle before selection info is recomputed
+const SELECTION_INFO_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// How long an accepted or rejected proposal stays highlighted before it's cleared
+const RESOLVED_PROPOSAL_HIGHLIGHT_DURATION: Duration = Duration::from_millis(600);
+
+/// Lines of context added above and below the selection on a retry, when it isn't
+/// inside a larger symbol
+const RETRY_CONTEXT_LINES: u32 = 10;
+
+/// Toast naming the quick edit that an undo reverted
+struct QuickEditUndoneToast;
+
+/// Quick edit sessions that are currently open, keyed by the editor they target
+#[derive(Default)]
+struct QuickEditSessions(HashMap<EntityId, Entity<QuickEditState>>);
+
+impl Global for QuickEditSessions {}
+
+pub struct QuickEditState {
+    editor: WeakEntity<Editor>,
+    workspace: WeakEntity<Workspace>,
+    /// The range of text to edit
+    selection_range: Range<usize>,
+    /// Current edit prompt
+    prompt: String,
+    /// The selected text as of construction, kept even as the buffer changes
+    original_text: Option<String>,
+    /// Edit currently being streamed into the selection, if any
+    streamed_edit: Option<StreamedEdit>,
+    /// Streamed edit awaiting the user's accept or reject, if any
+    proposal: Option<Proposal>,
+    /// Labels of the transactions quick edits applied, shown when they're undone
+    undo_labels: HashMap<TransactionId, String>,
+    /// Related files sent along with the selection for context
+    attached_files: Vec<PathBuf>,
+    /// Whether applied edits are selected and scrolled into view
+    reveal_edits: bool,
+    /// How many times the selection was expanded to retry the quick edit
+    retry_count: u32,
+    /// Whether the last `submit` sent a full prompt as is, which can't be rebuilt
+    /// for an expanded selection
+    sent_full_prompt: bool,
+    /// Whether finished streamed edits are proposed rather than applied, overriding
+    /// the mode's default
+    propose_edits: Option<bool>,
+    /// Size of the selected text, as of the last refresh
+    selection_info: Option<SelectionInfo>,
+    /// Skips recounting the selection when edits elsewhere leave it unchanged
+    selection_stats: SelectionStatsCache,
+    /// Token usage of the selected text, as of the last refresh
+    token_info: TokenInfo,
+    /// Context info for each buffer the current selection spans, computed on demand
+    cached_context_info: Option<CachedContextInfo>,
+    /// Pending debounced refresh of `selection_info` and `token_info`
+    _refresh_selection_info: Task<()>,
+    /// Request sent by `submit`, until the model's response starts streaming
+    _pending_request: Task<()>,
+    /// Clears the highlight of the last accepted or rejected proposal
+    _clear_resolved_highlight: Task<()>,
+    /// Subscription to editor changes
+    _subscriptions: Vec<Subscription>,
+}
+
+struct CachedContextInfo {
+    selection_range: Range<usize>,
+    /// File and lines of the selection when the context info was computed
+    region: ContextInfo,
+    /// Edit count of the buffer when the context info was computed
+    edit_count: usize,
+    context_info: Vec<ContextInfo>,
+}
+
+/// Highlight key for text written by a quick edit
+enum QuickEditHighlight {}
+
+/// Highlight key for the lines a proposal adds or rewrites
+enum QuickEditAddedHighlight {}
+
+struct Proposal {
+    /// The selection range before the proposal was written, restored on reject
+    original_range: Range<usize>,
+    /// The transaction that wrote the proposal
+    transaction_id: Option<TransactionId>,
+    /// Blocks showing the lines the proposal removes
+    removed_line_blocks: HashSet<CustomBlockId>,
+}
+
+/// The selection with as much surrounding code as fits a token budget
+#[derive(Clone, Debug, PartialEq)]
+pub struct BudgetedContext {
+    /// Location of the selection and its surrounding lines
+    pub context_info: ContextInfo,
+    /// Text of the selection and its surrounding lines
+    pub text: String,
+    /// Lines of surrounding code included above and below the selection
+    pub context_lines: u32,
+    /// Whether the bare selection alone is over the budget
+    pub truncated: bool,
 }
 
-/// Send selected code to the agent panel in the main thread
-#[derive(Clone, PartialEq, Action)]
-#[action(namespace = agent)]
-pub struct SendSelectionToAgent;
+impl BudgetedContext {
+    /// Format as a block of the quick edit prompt, set apart from the selection
+    /// the agent is asked to edit
+    fn format_for_agent(&self) -> String {
+        format!(
+            "Code surrounding the selection, for reference only:\n\n{}",
+            self.context_info.format_for_agent(&self.text)
+        )
+    }
+}
+
+struct StreamedEdit {
+    /// The selection range before streaming started, restored on cancel
+    original_range: Range<usize>,
+    /// The selected text before streaming started, which a proposal is diffed against
+    original_text: String,
+    /// The text written so far, anchored so that it grows as chunks are appended
+    range: Option<Range<Anchor>>,
+    /// The transaction that all chunks are grouped into
+    transaction_id: Option<TransactionId>,
+    _task: Task<()>,
+}
+
+impl QuickEditState {
+    pub fn new(
+        editor: Entity<Editor>,
+        workspace: Entity<Workspace>,
+        selection_range: Range<usize>,
+        cx: &App,
+    ) -> Self {
+        let mut state = Self {
+            editor: editor.downgrade(),
+            workspace: workspace.downgrade(),
+            selection_range,
+            prompt: String::new(),
+            original_text: None,
+            streamed_edit: None,
+            proposal: None,
+            undo_labels: HashMap::default(),
+            attached_files: Vec::new(),
+            reveal_edits: true,
+            retry_count: 0,
+            sent_full_prompt: false,
+            propose_edits: None,
+            selection_info: None,
+            selection_stats: SelectionStatsCache::default(),
+            token_info: TokenInfo::default(),
+            cached_context_info: None,
+            _refresh_selection_info: Task::ready(()),
+            _pending_request: Task::ready(()),
+            _clear_resolved_highlight: Task::ready(()),
+            _subscriptions: vec![],
+        };
+        state.original_text = state.selected_text(cx);
+        state
+    }
+
+    /// Keep selection and token info up to date as the editor is edited.
+    ///
+    /// Refreshes are debounced so that a burst of edits only recomputes once.
+    pub fn observe_editor_edits(&mut self, cx: &mut Context<Self>) {
+        let Some(editor) = self.editor.upgrade() else {
+            return;
+        };
+        self.refresh_selection_info(cx);
+        let subscription = cx.subscribe(&editor, |this, _, event: &EditorEvent, cx| match event {
+            EditorEvent::BufferEdited => {
+                this.invalidate_context_info_if_moved(cx);
+                this.schedule_selection_info_refresh(cx);
+            }
+            EditorEvent::TransactionUndone { transaction_id } => {
+                this.show_undone_label(*transaction_id, cx);
+            }
+            _ => {}
+        });
+        self._subscriptions.push(subscription);
+    }
+
+    /// Tell the user which quick edit an undo reverted
+    fn show_undone_label(&self, transaction_id: TransactionId, cx: &mut App) {
+        let Some(label) = self.undo_label(transaction_id) else {
+            return;
+        };
+        let message = format!("Undid {label}");
+        self.workspace
+            .update(cx, |workspace, cx| {
+                workspace.show_toast(
+                    Toast::new(NotificationId::unique::<QuickEditUndoneToast>(), message)
+                        .autohide(),
+                    cx,
+                );
+            })
+            .log_err();
+    }
+
+    /// Size of the selected text, as of the last refresh
+    pub fn selection_info(&self) -> Option<&SelectionInfo> {
+        self.selection_info.as_ref()
+    }
+
+    /// Token usage of the selected text, as of the last refresh
+    pub fn token_info(&self) -> &TokenInfo {
+        &self.token_info
+    }
+
+    /// Whether the selection, plus `reserve` tokens kept free for the response,
+    /// would overflow a context window of `context_window` tokens
+    pub fn exceeds_budget(&self, context_window: u32, reserve: u32) -> bool {
+        let selection_tokens = self
+            .selection_info
+            .as_ref()
+            .map_or(0, |info| info.token_estimate);
+        selection_tokens.saturating_add(reserve) > context_window
+    }
+
+    fn schedule_selection_info_refresh(&mut self, cx: &mut Context<Self>) {
+        self._refresh_selection_info = cx.spawn(async move |this, cx| {
+            cx.background_executor()
+                .timer(SELECTION_INFO_DEBOUNCE)
+                .await;
+            this.update(cx, |this, cx| this.refresh_selection_info(cx))
+                .ok();
+        });
+    }
+
+    fn refresh_selection_info(&mut self, cx: &mut Context<Self>) {
+        self.selection_info = match self.selected_text(cx) {
+            Some(text) => Some(self.selection_stats.get(&text).clone()),
+            None => {
+                self.selection_stats.clear();
+                None
+            }
+        };
+        self.token_info.selection_tokens = self
+            .selection_info
+            .as_ref()
+            .map_or(0, |info| info.token_estimate);
+        cx.notify();
+    }
+
+    /// Get the quick edit session currently open for `editor`, if any
+    pub fn for_editor(editor: &Entity<Editor>, cx: &App) -> Option<Entity<QuickEditState>> {
+        cx.try_global::<QuickEditSessions>()?
+            .0
+            .get(&editor.entity_id())
+            .cloned()
+    }
+
+    /// Set whether finished streamed edits are proposed for review rather than applied.
+    ///
+    /// By default only Write mode applies them directly.
+    pub fn set_propose_edits(&mut self, propose_edits: bool) {
+        self.propose_edits = Some(propose_edits);
+    }
+
+    /// Get the current edit prompt
+    pub fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    /// Set the current edit prompt
+    pub fn set_prompt(&mut self, prompt: impl Into<String>) {
+        self.prompt = prompt.into();
+    }
+
+    /// Set whether applied edits are selected and centered in the viewport. On by default.
+    pub fn set_reveal_edits(&mut self, reveal_edits: bool) {
+        self.reveal_edits = reveal_edits;
+    }
+
+    /// The label of a transaction applied by `apply_edit`, as stored by this quick edit
+    pub fn undo_label(&self, transaction_id: TransactionId) -> Option<&str> {
+        self.undo_labels.get(&transaction_id).map(String::as_str)
+    }
+
+    /// The editor being quick edited, unless it was closed
+    pub fn editor(&self) -> Option<Entity<Editor>> {
+        self.editor.upgrade()
+    }
+
+    pub fn selection_range(&self) -> Range<usize> {
+        self.selection_range.clone()
+    }
+
+    /// How many times the quick edit was retried with expanded context
+    pub fn retry_count(&self) -> u32 {
+        self.retry_count
+    }
+
+    /// Grow the selection so a quick edit whose result couldn't be applied can be retried.
+    ///
+    /// The selection grows to the innermost symbol that encloses it, else by
+    /// `RETRY_CONTEXT_LINES` lines on each side. The prompt and attached files are kept.
+    /// Returns the new range, or `None` once `max_retries` retries were made or when
+    /// the selection can't grow any further.
+    pub fn expand_context_for_retry(
+        &mut self,
+        max_retries: u32,
+        cx: &mut Context<Self>,
+    ) -> Option<Range<usize>> {
+        if self.retry_count >= max_retries {
+            return None;
+        }
+        let buffer = self.buffer_snapshot(cx)?;
+        let range = self.validated_range(&buffer)?;
+        let expanded_range = enclosing_symbol_range(&buffer, &range)
+            .unwrap_or_else(|| surrounding_lines_range(&buffer, &range, RETRY_CONTEXT_LINES));
+        if expanded_range == range {
+            return None;
+        }
+
+        self.retry_count += 1;
+        self.selection_range = expanded_range.start.0..expanded_range.end.0;
+        self.cached_context_info = None;
+        self.refresh_selection_info(cx);
+        Some(self.selection_range.clone())
+    }
+
+    /// Re-send the quick edit's prompt for an expanded selection, after the agent's
+    /// result couldn't be applied.
+    ///
+    /// Returns whether a retry was sent, which stops once `quick_edit_max_retries`
+    /// retries were made. Full prompts sent as is aren't retried.
+    pub fn retry_with_expanded_context(
+        &mut self,
+        mode: AgentMode,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> bool {
+        if self.sent_full_prompt {
+            return false;
+        }
+        let max_retries = AgentSettings::get_global(cx).quick_edit_max_retries;
+        let Some(editor) = self.editor.upgrade() else {
+            return false;
+        };
+        let Some(range) = self.expand_context_for_retry(max_retries, cx) else {
+            return false;
+        };
+
+        editor.update(cx, |editor, cx| {
+            editor.change_selections(SelectionEffects::default(), window, cx, |selections| {
+                selections
+                    .select_ranges([MultiBufferOffset(range.start)..MultiBufferOffset(range.end)]);
+            });
+        });
+        self.submit(None, mode, window, cx).log_err().is_some()
+    }
+
+    /// Files attached to this quick edit for context
+    pub fn attached_files(&self) -> &[PathBuf] {
+        &self.attached_files
+    }
+
+    /// Attach a file of the workspace's project to send along with the selection.
+    ///
+    /// `path` is absolute or relative to a worktree. Errors when it doesn't name
+    /// a file in the project.
+    pub fn attach_file(&mut self, path: impl Into<PathBuf>, cx: &App) -> Result<()> {
+        let path = path.into();
+        let workspace = self.workspace.upgrade().context("workspace was dropped")?;
+        let project = workspace.read(cx).project().read(cx);
+        let is_file = project
+            .find_project_path(&path, cx)
+            .and_then(|project_path| project.entry_for_path(&project_path, cx))
+            .is_some_and(|entry| entry.is_file());
+        if !is_file {
+            return Err(anyhow!("no such file in the project: {}", path.display()));
+        }
+        if !self.attached_files.contains(&path) {
+            self.attached_files.push(path);
+        }
+        Ok(())
+    }
+
+    /// Detach a previously attached file. Returns whether it was attached.
+    pub fn detach_file(&mut self, path: &Path) -> bool {
+        let attached_count = self.attached_files.len();
+        self.attached_files
+            .retain(|attached_path| attached_path != path);
+        self.attached_files.len() != attached_count
+    }
+
+    /// Load the current content of every attached file, in attachment order
+    pub fn load_attached_files(&self, cx: &mut App) -> Task<Result<Vec<AttachedFile>>> {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let project = workspace.read(cx).project().clone();
+        let buffer_tasks = self
+            .attached_files
+            .iter()
+            .map(|path| {
+                let project_path = project
+                    .read(cx)
+                    .find_project_path(path, cx)
+                    .with_context(|| format!("no such file in the project: {}", path.display()));
+                let buffer = match project_path {
+                    Ok(project_path) => {
+                        project.update(cx, |project, cx| project.open_buffer(project_path, cx))
+                    }
+                    Err(error) => Task::ready(Err(error)),
+                };
+                (path.clone(), buffer)
+            })
+            .collect::<Vec<_>>();
+
+        cx.spawn(async move |cx| {
+            let mut attached_files = Vec::with_capacity(buffer_tasks.len());
+            for (path, buffer) in buffer_tasks {
+                let buffer = buffer.await?;
+                let content = buffer.read_with(cx, |buffer, _| buffer.text())?;
+                attached_files.push(AttachedFile { path, content });
+            }
+            Ok(attached_files)
+        })
+    }
+
+    /// Build the prompt applying `instruction` to the selection, followed by the code
+    /// surrounding it and the content of `attached_files`.
+    ///
+    /// The surrounding code is cut down as `get_context_info_within_budget` does. Each
+    /// attached file is cut to `ATTACHED_FILE_MAX_LINES` lines, and files that would
+    /// take the prompt past `token_budget` tokens are left out. Returns `None` when the
+    /// selection range is out of bounds.
+    pub fn build_prompt(
+        &self,
+        instruction: &str,
+        attached_files: &[AttachedFile],
+        token_budget: u32,
+        cx: &App,
+    ) -> Option<String> {
+        let editor = self.editor.upgrade()?;
+        let mut prompt = build_agent_prompt(
+            editor.read(cx),
+            self.selection_range.clone(),
+            instruction,
+            cx,
+        )?;
+        let mut prompt_tokens = TokenInfo::estimate_tokens_from_text(&prompt);
+        let selected_text = self.selected_text(cx)?;
+        let surrounding_code = self
+            .get_context_info_within_budget(token_budget.saturating_sub(prompt_tokens), cx)
+            .filter(|context| !context.truncated && context.text.trim() != selected_text.trim());
+        if let Some(surrounding_code) = surrounding_code {
+            let block = surrounding_code.format_for_agent();
+            prompt_tokens += TokenInfo::estimate_tokens_from_text(&block);
+            prompt.push_str("\n\n");
+            prompt.push_str(&block);
+        }
+        for attached_file in attached_files {
+            let block = attached_file.format_for_agent();
+            let block_tokens = TokenInfo::estimate_tokens_from_text(&block);
+            if prompt_tokens.saturating_add(block_tokens) > token_budget {
+                continue;
+            }
+            prompt.push_str("\n\n");
+            prompt.push_str(&block);
+            prompt_tokens += block_tokens;
+        }
+        Some(prompt)
+    }
+
+    /// Get the text that was selected when the quick edit started, before any
+    /// change to the buffer. `None` if the range was invalid at the time.
+    pub fn original_text(&self) -> Option<&str> {
+        self.original_text.as_deref()
+    }
+
+    /// Get the selected text from the editor
+    pub fn selected_text(&self, cx: &App) -> Option<String> {
+        let buffer = self.buffer_snapshot(cx)?;
+        let range = self.validated_range(&buffer)?;
+        Some(buffer.text_for_range(range).collect())
+    }
+
+    /// Get the selected text with each line prefixed by its 1-indexed line number in the file
+    pub fn selected_text_with_line_numbers(&self, cx: &App) -> Option<String> {
+        let buffer = self.buffer_snapshot(cx)?;
+        let range = self.validated_range(&buffer)?;
+        let first_line_number = buffer.offset_to_point(range.start).row + 1;
+        let text = buffer.text_for_range(range).collect::<String>();
+        let lines = text.lines().collect::<Vec<_>>();
+        let width = (first_line_number as usize + lines.len().saturating_sub(1))
+            .to_string()
+            .len();
+
+        let numbered_lines = lines
+            .into_iter()
+            .zip(first_line_number..)
+            .map(|(line, line_number)| format!("{line_number:>width$} | {line}"))
+            .collect::<Vec<_>>();
+        Some(numbered_lines.join("\n"))
+    }
+
+    /// Get file context information for the selection, with one entry per
+    /// underlying buffer when the selection spans excerpts of a multibuffer.
+    ///
+    /// The result is cached until the selection or the buffer changes.
+    pub fn get_context_info(&mut self, cx: &App) -> Vec<ContextInfo> {
+        let Some(buffer) = self.buffer_snapshot(cx) else {
+            return Vec::new();
+        };
+        let edit_count = buffer.edit_count();
+        if let Some(cached) = &self.cached_context_info
+            && cached.selection_range == self.selection_range
+            && cached.edit_count == edit_count
+        {
+            return cached.context_info.clone();
+        }
+
+        let context_info = ContextInfo::for_excerpts(&buffer, self.selection_range.clone(), cx);
+        self.cached_context_info = Some(CachedContextInfo {
+            selection_range: self.selection_range.clone(),
+            region: ContextInfo::for_range(&buffer, self.selection_range.clone(), cx),
+            edit_count,
+            context_info: context_info.clone(),
+        });
+        context_info
+    }
+
+    /// Get the selection along with up to `quick_edit_context_lines` lines of surrounding
+    /// code on each side, fewer when needed to fit in `max_tokens` tokens.
+    ///
+    /// See [`Self::context_within_budget`].
+    pub fn get_context_info_within_budget(
+        &self,
+        max_tokens: u32,
+        cx: &App,
+    ) -> Option<BudgetedContext> {
+        let max_context_lines = AgentSettings::get_global(cx).quick_edit_context_lines;
+        self.context_within_budget(max_context_lines, max_tokens, cx)
+    }
+
+    /// Get the selection along with up to `max_context_lines` lines of surrounding code
+    /// on each side, trimming the same number of lines from both sides until the whole
+    /// fits in `max_tokens` tokens.
+    ///
+    /// When even the bare selection doesn't fit, it's returned without context and
+    /// marked as truncated. Returns `None` when the selection range is out of bounds.
+    pub fn context_within_budget(
+        &self,
+        max_context_lines: u32,
+        max_tokens: u32,
+        cx: &App,
+    ) -> Option<BudgetedContext> {
+        let buffer = self.buffer_snapshot(cx)?;
+        let selection_range = self.validated_range(&buffer)?;
+        let budgeted_context = |context_lines: u32, truncated: bool| {
+            let range = if context_lines == 0 {
+                selection_range.clone()
+            } else {
+                surrounding_lines_range(&buffer, &selection_range, context_lines)
+            };
+            BudgetedContext {
+                context_info: ContextInfo::for_range(&buffer, range.start.0..range.end.0, cx),
+                text: buffer.text_for_range(range).collect(),
+                context_lines,
+                truncated,
+            }
+        };
+
+        for context_lines in (0..=max_context_lines).rev() {
+            let context = budgeted_context(context_lines, false);
+            if TokenInfo::estimate_tokens_from_text(&context.text) <= max_tokens {
+                return Some(context);
+            }
+        }
+        Some(budgeted_context(0, true))
+    }
+
+    /// Drop the cached context info when an edit moved the selection to other lines,
+    /// keeping it across edits that leave the selection's region unchanged
+    fn invalidate_context_info_if_moved(&mut self, cx: &App) {
+        let Some(buffer) = self.buffer_snapshot(cx) else {
+            self.cached_context_info = None;
+            return;
+        };
+        let Some(cached) = &mut self.cached_context_info else {
+            return;
+        };
+        let region_is_valid = cached.selection_range.end <= buffer.len().0;
+        if region_is_valid
+            && ContextInfo::for_range(&buffer, cached.selection_range.clone(), cx)
+                .is_same_region(&cached.region)
+        {
+            cached.edit_count = buffer.edit_count();
+        } else {
+            self.cached_context_info = None;
+        }
+    }
+
+    /// Replace the selection with `replacement` as a single undoable transaction.
+    /// The transaction's label, derived from the prompt, is shown in a toast when it's
+    /// undone. A code fence wrapping the whole replacement is removed first, as with
+    /// `strip_code_fence`.
+    ///
+    /// On success the stored selection range spans the replacement text, and the
+    /// lines that differ from the original selection are returned in buffer rows.
+    /// The replacement is then revealed, unless turned off with `set_reveal_edits`.
+    pub fn apply_edit(
+        &mut self,
+        replacement: String,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> Result<Vec<ChangedLines>, QuickEditError> {
+        let editor = self.editor.upgrade().ok_or(QuickEditError::NoEditor)?;
+        let buffer = editor.read(cx).buffer().clone();
+        let range = self
+            .validated_range(&buffer.read(cx).snapshot(cx))
+            .ok_or(QuickEditError::InvalidRange)?;
+        let snapshot = buffer.read(cx).snapshot(cx);
+        let language =
+            ContextInfo::for_range(&snapshot, self.selection_range.clone(), cx).infer_language();
+        let replacement = strip_code_fence(&replacement, &language);
+        let start_row = snapshot.offset_to_point(range.start).row;
+        let original = snapshot.text_for_range(range.clone()).collect::<String>();
+        let changes = changed_lines(&original, &replacement)
+            .into_iter()
+            .map(|change| change.offset_rows(start_row))
+            .collect::<Vec<_>>();
+
+        // Finalize around the edit so it's never merged with neighbouring typing
+        let transaction_id = buffer.update(cx, |buffer, cx| {
+            buffer.finalize_last_transaction(cx);
+            buffer.start_transaction(cx);
+            buffer.edit([(range, replacement.as_str())], None, cx);
+            let transaction_id = buffer.end_transaction(cx);
+            buffer.finalize_last_transaction(cx);
+            transaction_id
+        });
+        if let Some(transaction_id) = transaction_id {
+            self.undo_labels
+                .insert(transaction_id, quick_edit_undo_label(&self.prompt));
+        }
+        self.selection_range =
+            self.selection_range.start..self.selection_range.start + replacement.len();
+        let primary_row = changes.first().map(ChangedLines::first_row);
+        self.reveal_edit(primary_row, window, cx);
+        Ok(changes)
+    }
+
+    /// Select the current range and center `primary_row`, or the range's first line,
+    /// in the viewport. Does nothing when revealing edits is turned off.
+    fn reveal_edit(&self, primary_row: Option<u32>, window: &mut Window, cx: &mut App) {
+        if !self.reveal_edits {
+            return;
+        }
+        let Some(editor) = self.editor.upgrade() else {
+            return;
+        };
+        let range = self.selection_range.clone();
+        editor.update(cx, |editor, cx| {
+            let buffer = editor.buffer().read(cx).snapshot(cx);
+            let primary_offset = match primary_row {
+                Some(row) => buffer.point_to_offset(Point::new(row.min(buffer.max_point().row), 0)),
+                None => MultiBufferOffset(range.start),
+            };
+            let autoscroll = Autoscroll::center().for_anchor(buffer.anchor_before(primary_offset));
+            editor.change_selections(
+                SelectionEffects::scroll(autoscroll),
+                window,
+                cx,
+                |selections| {
+                    selections.select_ranges([
+                        MultiBufferOffset(range.start)..MultiBufferOffset(range.end)
+                    ]);
+                },
+            );
+        });
+    }
+
+    /// Send the quick edit to the inline assistant model, streaming its response into
+    /// the selection with `stream_edit`.
+    ///
+    /// The prompt is built from the instruction, the selection and the attached files,
+    /// unless `full_prompt` is given, which is then sent as is. Fails when no model
+    /// is configured.
+    pub fn submit(
+        &mut self,
+        full_prompt: Option<String>,
+        mode: AgentMode,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Result<(), QuickEditError> {
+        let Some(ConfiguredModel { model, .. }) =
+            LanguageModelRegistry::read_global(cx).inline_assistant_model()
+        else {
+            return Err(QuickEditError::ModelError(
+                "no language model is configured".to_string(),
+            ));
+        };
+        let context_window = u32::try_from(model.max_token_count()).unwrap_or(u32::MAX);
+        let token_budget = context_window
+            .saturating_sub(AgentSettings::get_global(cx).quick_edit_response_reserve);
+        let temperature = AgentSettings::temperature_for_model(&model, cx);
+        let attached_files = self.load_attached_files(cx);
+        self.sent_full_prompt = full_prompt.is_some();
+
+        self._pending_request = cx.spawn_in(window, async move |this, cx| {
+            let attached_files = attached_files.await.log_err().unwrap_or_default();
+            let prompt = match full_prompt {
+                Some(full_prompt) => Some(full_prompt),
+                None => this
+                    .update(cx, |this, cx| {
+                        this.build_prompt(&this.prompt, &attached_files, token_budget, cx)
+                    })
+                    .ok()
+                    .flatten(),
+            };
+            let response = match prompt {
+                Some(prompt) => model
+                    .stream_completion_text(quick_edit_request(prompt, temperature), cx)
+                    .await
+                    .map_err(|error| QuickEditError::ModelError(error.to_string())),
+                None => Err(QuickEditError::InvalidRange),
+            };
+
+            this.update_in(cx, |this, window, cx| match response {
+                Ok(response) => {
+                    let chunks = response
+                        .stream
+                        .map(|chunk| chunk.map_err(anyhow::Error::from));
+                    this.stream_edit(chunks, mode, window, cx);
+                }
+                Err(error) => {
+                    log::error!("quick edit request failed: {error}");
+                    this.show_error(&error, cx);
+                }
+            })
+            .log_err();
+        });
+        Ok(())
+    }
+
+    /// Tell the user why the quick edit failed, in its workspace
+    fn show_error(&self, error: &QuickEditError, cx: &mut App) {
+        if let Some(workspace) = self.workspace.upgrade() {
+            workspace.update(cx, |workspace, cx| {
+                show_quick_edit_error(workspace, error, cx)
+            });
+        }
+    }
+
+    /// Progressively replace the selection with text chunks as they arrive.
+    ///
+    /// The selection is highlighted as sent to the agent until the first chunk arrives,
+    /// and text written so far as being edited. Once the stream ends the result is
+    /// applied directly in Write mode, and otherwise highlighted as a proposed change,
+    /// and revealed like `apply_edit`. If the stream fails or is cancelled, every
+    /// chunk is undone together. A proposal that's still pending is accepted first.
+    pub fn stream_edit(
+        &mut self,
+        chunks: impl Stream<Item = Result<String>> + 'static,
+        mode: AgentMode,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.cancel_streamed_edit(cx);
+        self.accept(cx);
+        self._clear_resolved_highlight = Task::ready(());
+
+        let task = cx.spawn_in(window, async move |this, cx| {
+            let result = async {
+                let mut chunks = pin!(chunks);
+                while let Some(chunk) = chunks.next().await {
+                    let chunk =
+                        chunk.map_err(|error| QuickEditError::ModelError(format!("{error:#}")))?;
+                    this.update(cx, |this, cx| this.push_streamed_chunk(&chunk, cx))
+                        .map_err(|_| QuickEditError::Cancelled)??;
+                }
+                Ok::<_, QuickEditError>(())
+            }
+            .await;
+
+            this.update_in(cx, |this, window, cx| match result {
+                Ok(()) => this.finish_streamed_edit(&mode, window, cx),
+                Err(error) => {
+                    log::error!("quick edit stream failed: {error}");
+                    this.cancel_streamed_edit(cx);
+                    this.show_error(&error, cx);
+                }
+            })
+            .log_err();
+        });
+
+        if let Some(editor) = self.editor.upgrade() {
+            let selection_range = self.selection_range.clone();
+            editor.update(cx, |editor, cx| {
+                let snapshot = editor.buffer().read(cx).snapshot(cx);
+                let start_row = snapshot
+                    .offset_to_point(MultiBufferOffset(selection_range.start))
+                    .row;
+                let end_row = snapshot
+                    .offset_to_point(MultiBufferOffset(selection_range.end))
+                    .row;
+                let spans = gutter_spans_for_rows(
+                    start_row..end_row + 1,
+                    GutterIndicator::SelectedForAgent,
+                );
+                highlight_indicator_rows(editor, spans, cx);
+            });
+        }
+        self.streamed_edit = Some(StreamedEdit {
+            original_range: self.selection_range.clone(),
+            original_text: self.selected_text(cx).unwrap_or_default(),
+            range: None,
+            transaction_id: None,
+            _task: task,
+        });
+    }
+
+    /// Stop streaming and undo any text written by the current stream
+    pub fn cancel_streamed_edit(&mut self, cx: &mut Context<Self>) {
+        let Some(streamed_edit) = self.streamed_edit.take() else {
+            return;
+        };
+        self.selection_range = streamed_edit.original_range;
+
+        let Some(editor) = self.editor.upgrade() else {
+            return;
+        };
+        editor.update(cx, |editor, cx| {
+            if let Some(transaction_id) = streamed_edit.transaction_id {
+                editor.buffer().update(cx, |buffer, cx| {
+                    buffer.undo_transaction(transaction_id, cx);
+                });
+            }
+            editor.clear_highlights::<QuickEditHighlight>(cx);
+        });
+    }
+
+    /// Whether an edit is currently being streamed into the selection
+    pub fn is_streaming(&self) -> bool {
+        self.streamed_edit.is_some()
+    }
+
+    /// Whether a streamed edit is proposed in the editor, awaiting `accept` or `reject`
+    pub fn has_pending_proposal(&self) -> bool {
+        self.proposal.is_some()
+    }
+
+    /// Keep the pending proposal's text, briefly highlighting it as accepted
+    pub fn accept(&mut self, cx: &mut Context<Self>) {
+        if let Some(proposal) = self.proposal.take() {
+            self.highlight_resolved_proposal(
+                proposal.removed_line_blocks,
+                accepted_change_style(),
+                cx,
+            );
+        }
+    }
+
+    /// Undo the pending proposal, restoring the original text and selection range,
+    /// which is briefly highlighted as rejected
+    pub fn reject(&mut self, cx: &mut Context<Self>) {
+        let Some(proposal) = self.proposal.take() else {
+            return;
+        };
+        self.selection_range = proposal.original_range;
+        if let Some((editor, transaction_id)) = self.editor.upgrade().zip(proposal.transaction_id) {
+            editor.update(cx, |editor, cx| {
+                editor.buffer().update(cx, |buffer, cx| {
+                    buffer.undo_transaction(transaction_id, cx);
+                });
+            });
+        }
+        self.highlight_resolved_proposal(proposal.removed_line_blocks, rejected_change_style(), cx);
+    }
+
+    /// Replace the proposal's inline diff with `style` over the selection, until
+    /// `RESOLVED_PROPOSAL_HIGHLIGHT_DURATION` has passed
+    fn highlight_resolved_proposal(
+        &mut self,
+        removed_line_blocks: HashSet<CustomBlockId>,
+        style: HighlightStyle,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(editor) = self.editor.upgrade() else {
+            return;
+        };
+        editor.update(cx, |editor, cx| {
+            editor.clear_highlights::<QuickEditHighlight>(cx);
+            editor.clear_highlights::<QuickEditAddedHighlight>(cx);
+            editor.remove_blocks(removed_line_blocks, None, cx);
+            editor.unregister_addon::<QuickEditProposalAddon>();
+            let snapshot = editor.buffer().read(cx).snapshot(cx);
+            if let Some(range) = self.validated_range(&snapshot) {
+                let range = snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end);
+                let style = legible_highlight(style, cx);
+                editor.highlight_text::<QuickEditHighlight>(vec![range], style, cx);
+            }
+            cx.notify();
+        });
+        self._clear_resolved_highlight = cx.spawn(async move |this, cx| {
+            cx.background_executor()
+                .timer(RESOLVED_PROPOSAL_HIGHLIGHT_DURATION)
+                .await;
+            this.update(cx, |this, cx| {
+                if let Some(editor) = this.editor.upgrade() {
+                    editor.update(cx, |editor, cx| {
+                        editor.clear_highlights::<QuickEditHighlight>(cx);
+                    });
+                }
+            })
+            .log_err();
+        });
+    }
+
+    fn push_streamed_chunk(
+        &mut self,
+        chunk: &str,
+        cx: &mut Context<Self>,
+    ) -> Result<(), QuickEditError> {
+        let editor = self.editor.upgrade().ok_or(QuickEditError::NoEditor)?;
+        let buffer = editor.read(cx).buffer().clone();
+        let snapshot = buffer.read(cx).snapshot(cx);
+        let streamed_range = self
+            .streamed_edit
+            .as_ref()
+            .ok_or(QuickEditError::Cancelled)?
+            .range
+            .clone();
+
+        // The first chunk replaces the selection, later chunks are appended after it.
+        let (range, edit_range) = match streamed_range {
+            Some(range) => (range.clone(), range.end..range.end),
+            None => {
+                let range = self
+                    .validated_range(&snapshot)
+                    .ok_or(QuickEditError::InvalidRange)?;
+                let range = snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end);
+                (range.clone(), range)
+            }
+        };
+
+        let Some(streamed_edit) = self.streamed_edit.as_mut() else {
+            return Ok(());
+        };
+        let transaction_id = buffer.update(cx, |buffer, cx| {
+            buffer.start_transaction(cx);
+            buffer.edit([(edit_range, chunk)], None, cx);
+            let transaction_id = buffer.end_transaction(cx);
+            if let Some(first_transaction_id) = streamed_edit.transaction_id {
+                buffer.group_until_transaction(first_transaction_id, cx);
+            }
+            transaction_id
+        });
+        streamed_edit.transaction_id = streamed_edit.transaction_id.or(transaction_id);
+        streamed_edit.range = Some(range.clone());
+
+        let snapshot = buffer.read(cx).snapshot(cx);
+        self.selection_range = range.start.to_offset(&snapshot).0..range.end.to_offset(&snapshot).0;
+        let spans = gutter_spans_for_rows(
+            anchor_range_rows(&range, &snapshot),
+            GutterIndicator::BeingEdited,
+        );
+        editor.update(cx, |editor, cx| highlight_indicator_rows(editor, spans, cx));
+        Ok(())
+    }
+
+    fn finish_streamed_edit(
+        &mut self,
+        mode: &AgentMode,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(streamed_edit) = self.streamed_edit.take() else {
+            return;
+        };
+        let Some(editor) = self.editor.upgrade() else {
+            return;
+        };
+        // Nothing can be applied from an empty response, so the prompt is sent again
+        // with more of the code around the selection
+        if streamed_edit.range.is_none() {
+            self.selection_range = streamed_edit.original_range;
+            if !self.retry_with_expanded_context(mode.clone(), window, cx) {
+                self.show_error(&QuickEditError::EmptyResponse, cx);
+            }
+            return;
+        }
+        self.strip_streamed_code_fence(&streamed_edit, cx);
+        if let Some(transaction_id) = streamed_edit.transaction_id {
+            self.undo_labels
+                .insert(transaction_id, quick_edit_undo_label(&self.prompt));
+        }
+        let propose = self
+            .propose_edits
+            .unwrap_or(!matches!(mode, AgentMode::Write));
+        let removed_line_blocks =
+            editor.update(cx, |editor, cx| match (propose, streamed_edit.range) {
+                (false, _) | (_, None) => {
+                    editor.clear_highlights::<QuickEditHighlight>(cx);
+                    None
+                }
+                (true, Some(range)) => {
+                    let blocks =
+                        highlight_proposal_diff(editor, range, &streamed_edit.original_text, cx);
+                    editor.register_addon(QuickEditProposalAddon);
+                    Some(blocks)
+                }
+            });
+        if let Some(removed_line_blocks) = removed_line_blocks {
+            self.proposal = Some(Proposal {
+                original_range: streamed_edit.original_range,
+                transaction_id: streamed_edit.transaction_id,
+                removed_line_blocks,
+            });
+        }
+        self.reveal_edit(None, window, cx);
+    }
+
+    /// Remove a code fence wrapping the whole streamed text, as `apply_edit` does,
+    /// grouped with the stream's transaction
+    fn strip_streamed_code_fence(&mut self, streamed_edit: &StreamedEdit, cx: &mut App) {
+        let (Some(editor), Some(range)) = (self.editor.upgrade(), streamed_edit.range.clone())
+        else {
+            return;
+        };
+        let buffer = editor.read(cx).buffer().clone();
+        let snapshot = buffer.read(cx).snapshot(cx);
+        let text = snapshot.text_for_range(range.clone()).collect::<String>();
+        let language =
+            ContextInfo::for_range(&snapshot, self.selection_range.clone(), cx).infer_language();
+        let code = strip_code_fence(&text, &language);
+        if code == text {
+            return;
+        }
+
+        buffer.update(cx, |buffer, cx| {
+            buffer.start_transaction(cx);
+            buffer.edit([(range.clone(), code.as_str())], None, cx);
+            buffer.end_transaction(cx);
+            if let Some(transaction_id) = streamed_edit.transaction_id {
+                buffer.group_until_transaction(transaction_id, cx);
+            }
+        });
+        let snapshot = buffer.read(cx).snapshot(cx);
+        self.selection_range = range.start.to_offset(&snapshot).0..range.end.to_offset(&snapshot).0;
+    }
+
+    fn buffer_snapshot(&self, cx: &App) -> Option<MultiBufferSnapshot> {
+        let editor = self.editor.upgrade()?;
+        Some(editor.read(cx).buffer().read(cx).snapshot(cx))
+    }
+
+    /// Convert the selection range to buffer offsets, rejecting ranges that no longer fit the buffer.
+    ///
+    /// An empty range at the end of the buffer is valid, since that's where code appended to
+    /// the file is inserted.
+    fn validated_range(&self, buffer: &MultiBufferSnapshot) -> Option<Range<MultiBufferOffset>> {
+        let start_offset = MultiBufferOffset(self.selection_range.start);
+        let end_offset = MultiBufferOffset(self.selection_range.end);
+
+        if end_offset > buffer.len() || start_offset > end_offset {
+            return None;
+        }
+
+        Some(start_offset..end_offset)
+    }
+}
+
+/// Get the ranges of the symbols containing `range`, innermost first
+fn symbol_ranges_containing(
+    buffer: &MultiBufferSnapshot,
+    range: &Range<MultiBufferOffset>,
+) -> Vec<Range<MultiBufferOffset>> {
+    let Some((_, symbols)) = buffer.symbols_containing(range.start, None) else {
+        return Vec::new();
+    };
+    symbols
+        .iter()
+        .rev()
+        .map(|symbol| symbol.range.start.to_offset(buffer)..symbol.range.end.to_offset(buffer))
+        .filter(|symbol_range| symbol_range.start <= range.start && range.end <= symbol_range.end)
+        .collect()
+}
+
+/// Get the range of the innermost symbol strictly enclosing `range`
+fn enclosing_symbol_range(
+    buffer: &MultiBufferSnapshot,
+    range: &Range<MultiBufferOffset>,
+) -> Option<Range<MultiBufferOffset>> {
+    symbol_ranges_containing(buffer, range)
+        .into_iter()
+        .find(|symbol_range| symbol_range != range)
+}
+
+/// Grow `range` to whole lines, plus `line_count` lines above and below
+fn surrounding_lines_range(
+    buffer: &MultiBufferSnapshot,
+    range: &Range<MultiBufferOffset>,
+    line_count: u32,
+) -> Range<MultiBufferOffset> {
+    let start_row = buffer
+        .offset_to_point(range.start)
+        .row
+        .saturating_sub(line_count);
+    let end_row = (buffer.offset_to_point(range.end).row + line_count).min(buffer.max_point().row);
+    let start = buffer.point_to_offset(Point::new(start_row, 0));
+    let end = buffer.point_to_offset(Point::new(
+        end_row,
+        buffer.line_len(MultiBufferRow(end_row)),
+    ));
+    start..end
+}
+
+/// Longest prompt summary shown in a quick edit's undo label
+const UNDO_LABEL_MAX_CHARS: usize = 40;
+
+/// Most lines of an attached file sent to the agent
+const ATTACHED_FILE_MAX_LINES: usize = 200;
+
+/// The content of a file attached to a quick edit, as loaded for a prompt
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachedFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl AttachedFile {
+    /// Format the file as a context block for the agent, cut to `ATTACHED_FILE_MAX_LINES`
+    fn format_for_agent(&self) -> String {
+        let last_line = self.content.lines().count().saturating_sub(1) as u32;
+        let context = ContextInfo::builder(0, last_line)
+            .relative_path(self.path.to_string_lossy())
+            .build();
+        context.format_for_agent_with_options(
+            &self.content,
+            FormatStyle::default(),
+            Some(ATTACHED_FILE_MAX_LINES),
+        )
+    }
+}
+
+/// The undo history label for a quick edit made with `prompt`, summarized by its
+/// first non-blank line
+pub fn quick_edit_undo_label(prompt: &str) -> String {
+    let summary = prompt
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    if summary.is_empty() {
+        return "Quick Edit".to_string();
+    }
+
+    let truncated = summary
+        .chars()
+        .take(UNDO_LABEL_MAX_CHARS)
+        .collect::<String>();
+    if truncated.len() < summary.len() {
+        format!("Quick Edit: {}…", truncated.trim_end())
+    } else {
+        format!("Quick Edit: {summary}")
+    }
+}
+
+/// Lines of an edit's replacement text that differ from the original text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangedLines {
+    /// Rows of the replacement that were rewritten from original lines
+    Modified(Range<u32>),
+    /// Rows of the replacement that were added without removing original lines
+    Inserted(Range<u32>),
+    /// `count` original lines were removed just before `row` of the replacement
+    Deleted { row: u32, count: u32 },
+}
+
+impl ChangedLines {
+    /// The first row of the replacement this change touches
+    fn first_row(&self) -> u32 {
+        match self {
+            Self::Modified(range) | Self::Inserted(range) => range.start,
+            Self::Deleted { row, .. } => *row,
+        }
+    }
+
+    fn offset_rows(self, rows: u32) -> Self {
+        match self {
+            Self::Modified(range) => Self::Modified(range.start + rows..range.end + rows),
+            Self::Inserted(range) => Self::Inserted(range.start + rows..range.end + rows),
+            Self::Deleted { row, count } => Self::Deleted {
+                row: row + rows,
+                count,
+            },
+        }
+    }
+}
+
+/// Diff `original` against `replacement` line by line, with rows counted from the
+/// start of `replacement`
+pub fn changed_lines(original: &str, replacement: &str) -> Vec<ChangedLines> {
+    let mut changes = Vec::new();
+    let mut row = 0;
+    let mut deleted_lines = None;
+    for operation in line_operations(original, replacement) {
+        match operation {
+            LineOperation::Keep { lines } => {
+                if let Some(count) = deleted_lines.take() {
+                    changes.push(ChangedLines::Deleted { row, count });
+                }
+                row += lines;
+            }
+            LineOperation::Delete { lines } => {
+                if let Some(count) = deleted_lines.take() {
+                    changes.push(ChangedLines::Deleted { row, count });
+                }
+                deleted_lines = Some(lines);
+            }
+            LineOperation::Insert { lines } => {
+                let rows = row..row + lines;
+                if deleted_lines.take().is_some() {
+                    changes.push(ChangedLines::Modified(rows));
+                } else {
+                    changes.push(ChangedLines::Inserted(rows));
+                }
+                row += lines;
+            }
+        }
+    }
+    if let Some(count) = deleted_lines {
+        changes.push(ChangedLines::Deleted { row, count });
+    }
+    changes
+}
+
+/// Original lines removed by an edit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemovedLines {
+    /// Row of the replacement the lines were removed just before
+    pub row: u32,
+    /// The removed lines, without a trailing newline
+    pub text: String,
+}
+
+/// Diff `original` against `replacement` line by line, returning each run of removed
+/// original lines, including the ones rewritten into new lines
+pub fn removed_lines(original: &str, replacement: &str) -> Vec<RemovedLines> {
+    let original_lines = original.lines().collect::<Vec<_>>();
+    let mut removed = Vec::new();
+    let mut row = 0;
+    let mut original_row = 0;
+    for operation in line_operations(original, replacement) {
+        match operation {
+            LineOperation::Keep { lines } => {
+                row += lines;
+                original_row += lines;
+            }
+            LineOperation::Delete { lines } => {
+                let start = (original_row as usize).min(original_lines.len());
+                let end = (original_row + lines) as usize;
+                removed.push(RemovedLines {
+                    row,
+                    text: original_lines[start..end.min(original_lines.len())].join("\n"),
+                });
+                original_row += lines;
+            }
+            LineOperation::Insert { lines } => row += lines,
+        }
+    }
+    removed
+}
+
+fn line_operations(original: &str, replacement: &str) -> Vec<LineOperation> {
+    let original_text = Rope::from(original);
+    let mut diff = StreamingDiff::new(original.to_string());
+    let mut char_operations = diff.push_new(replacement);
+    char_operations.extend(diff.finish());
+    let mut line_diff = LineDiff::default();
+    line_diff.push_char_operations(&char_operations, &original_text);
+    line_diff.finish(&original_text);
+    line_diff.line_operations()
+}
+
+/// Rows spanned by `range`, leaving out a last row it only reaches the start of
+fn anchor_range_rows(range: &Range<Anchor>, snapshot: &MultiBufferSnapshot) -> Range<u32> {
+    let start = range.start.to_point(snapshot);
+    let end = range.end.to_point(snapshot);
+    if end.column == 0 && end.row > start.row {
+        start.row..end.row
+    } else {
+        start.row..end.row + 1
+    }
+}
+
+/// Get the range from the start of the first row to the end of the last, clipped to
+/// the buffer
+fn row_span_range(rows: Range<u32>, snapshot: &MultiBufferSnapshot) -> Option<Range<Anchor>> {
+    let max_row = snapshot.max_point().row;
+    if rows.is_empty() || rows.start > max_row {
+        return None;
+    }
+    let end_row = (rows.end - 1).min(max_row);
+    let start = snapshot.anchor_before(Point::new(rows.start, 0));
+    let end = snapshot.anchor_after(Point::new(
+        end_row,
+        snapshot.line_len(MultiBufferRow(end_row)),
+    ));
+    Some(start..end)
+}
+
+/// Show a proposal as an inline diff against `original_text`: added and rewritten
+/// rows are highlighted as added, the other rows as proposed, and removed lines are
+/// shown struck through in blocks. Returns the blocks, removed with the proposal.
+fn highlight_proposal_diff(
+    editor: &mut Editor,
+    range: Range<Anchor>,
+    original_text: &str,
+    cx: &mut Context<Editor>,
+) -> HashSet<CustomBlockId> {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let rows = anchor_range_rows(&range, &snapshot);
+    let proposed_text = snapshot.text_for_range(range).collect::<String>();
+
+    let mut added_rows = Vec::new();
+    for change in changed_lines(original_text, &proposed_text) {
+        if let ChangedLines::Modified(changed) | ChangedLines::Inserted(changed) =
+            change.offset_rows(rows.start)
+        {
+            added_rows.push(changed);
+        }
+    }
+    let unchanged_rows = rows
+        .clone()
+        .filter(|row| !added_rows.iter().any(|added| added.contains(row)))
+        .map(|row| (row, GutterIndicator::Proposed));
+    highlight_indicator_rows(editor, merge_gutter_rows(unchanged_rows), cx);
+
+    let added_style = legible_highlight(diff_added_style(), cx);
+    editor.clear_highlights::<QuickEditAddedHighlight>(cx);
+    for (key, added) in added_rows.into_iter().enumerate() {
+        if let Some(range) = row_span_range(added, &snapshot) {
+            editor.highlight_text_key::<QuickEditAddedHighlight>(
+                key,
+                vec![range],
+                added_style,
+                false,
+                cx,
+            );
+        }
+    }
+
+    let removed_style = legible_highlight(diff_removed_style(), cx);
+    let blocks = removed_lines(original_text, &proposed_text)
+        .into_iter()
+        .map(|removed| {
+            let row = rows.start + removed.row;
+            let placement = if row < rows.end {
+                BlockPlacement::Above(snapshot.anchor_before(Point::new(row, 0)))
+            } else {
+                let last_row = rows.end.saturating_sub(1).max(rows.start);
+                BlockPlacement::Below(snapshot.anchor_after(Point::new(
+                    last_row,
+                    snapshot.line_len(MultiBufferRow(last_row)),
+                )))
+            };
+            BlockProperties {
+                placement,
+                height: Some(removed.text.lines().count().max(1) as u32),
+                style: BlockStyle::Flex,
+                render: render_removed_lines(removed.text.into(), removed_style),
+                priority: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+    editor.insert_blocks(blocks, None, cx).into_iter().collect()
+}
+
+fn render_removed_lines(text: SharedString, style: HighlightStyle) -> RenderBlock {
+    Arc::new(move |cx| {
+        let text_style = cx.editor_style.text.clone();
+        div()
+            .pl(cx.margins.gutter.full_width())
+            .child(
+                StyledText::new(text.clone())
+                    .with_default_highlights(&text_style, [(0..text.len(), style)]),
+            )
+            .into_any_element()
+    })
+}
+
+/// Highlight the rows of each indicator span, one style per row
+fn highlight_indicator_rows(
+    editor: &mut Editor,
+    spans: Vec<GutterIndicatorSpan>,
+    cx: &mut Context<Editor>,
+) {
+    let snapshot = editor.buffer().read(cx).snapshot(cx);
+    let rows = spans
+        .into_iter()
+        .flat_map(|span| span.rows.map(move |row| (row, span.indicator)));
+    let spans = resolve_row_styles(rows, VisualIndicatorSettings::get(cx));
+    editor.clear_highlights::<QuickEditHighlight>(cx);
+    for (key, (span_rows, style)) in spans.into_iter().enumerate() {
+        if let Some(range) = row_span_range(span_rows, &snapshot) {
+            let style = legible_highlight(style, cx);
+            editor.highlight_text_key::<QuickEditHighlight>(key, vec![range], style, false, cx);
+        }
+    }
+}
+
+/// Keep text legible under `style` on the current theme's editor background
+fn legible_highlight(style: HighlightStyle, cx: &App) -> HighlightStyle {
+    ensure_min_contrast(
+        style,
+        cx.theme().colors().editor_background,
+        MIN_TEXT_CONTRAST_RATIO,
+    )
+}
+
+/// Why a quick edit couldn't be started or applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QuickEditError {
+    /// There's no editor to edit, or it was closed
+    NoEditor,
+    /// There's no code to edit
+    EmptySelection,
+    /// The range to edit is no longer within the buffer
+    InvalidRange,
+    /// The buffer can't be written to
+    BufferReadOnly,
+    /// The model failed to produce an edit
+    ModelError(String),
+    /// The model's response had no code to apply
+    EmptyResponse,
+    /// The quick edit was stopped before it finished
+    Cancelled,
+}
+
+impl fmt::Display for QuickEditError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoEditor => write!(formatter, "There's no open editor to quick edit."),
+            Self::EmptySelection => write!(formatter, "There's no code to quick edit."),
+            Self::InvalidRange => write!(
+                formatter,
+                "The code changed before the quick edit could be applied."
+            ),
+            Self::BufferReadOnly => write!(
+                formatter,
+                "This buffer is read-only, so a quick edit can't be applied to it."
+            ),
+            Self::ModelError(message) => write!(formatter, "The quick edit failed: {message}"),
+            Self::EmptyResponse => write!(formatter, "The model didn't return any code."),
+            Self::Cancelled => write!(formatter, "The quick edit was cancelled."),
+        }
+    }
+}
+
+impl std::error::Error for QuickEditError {}
+
+/// Tell the user why a quick edit didn't happen. Cancellations are left silent,
+/// as the user asked for them.
+pub(crate) fn show_quick_edit_error(
+    workspace: &mut Workspace,
+    error: &QuickEditError,
+    cx: &mut Context<Workspace>,
+) {
+    if *error == QuickEditError::Cancelled {
+        return;
+    }
+
+    struct QuickEditErrorToast;
+    workspace.show_toast(
+        Toast::new(
+            NotificationId::unique::<QuickEditErrorToast>(),
+            error.to_string(),
+        )
+        .autohide(),
+        cx,
+    );
+}
+
+/// Check that a quick edit's result can be written to `editor`'s buffer.
+///
+/// Untitled buffers are fine: their context simply has no file path.
+pub(crate) fn ensure_quick_editable(editor: &Editor, cx: &App) -> Result<(), QuickEditError> {
+    if editor.read_only(cx) {
+        return Err(QuickEditError::BufferReadOnly);
+    }
+    Ok(())
+}
+
+/// Check that `editor` can be quick edited, and tell the user why not otherwise
+pub(crate) fn check_quick_editable(
+    workspace: &mut Workspace,
+    editor: &Entity<Editor>,
+    cx: &mut Context<Workspace>,
+) -> bool {
+    let Err(error) = ensure_quick_editable(editor.read(cx), cx) else {
+        return true;
+    };
+    show_quick_edit_error(workspace, &error, cx);
+    false
+}
+
+/// Start a quick edit session for `range` of `editor`, with `prompt` as its instruction.
+///
+/// The editor's selections are collapsed to `range` first, so that the session
+/// targets a single region even when multiple cursors were active. Returns `None`,
+/// after telling the user why, when the editor is read-only.
+fn start_quick_edit_session(
+    workspace: &mut Workspace,
+    editor: Entity<Editor>,
+    range: Range<usize>,
+    prompt: Option<String>,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) -> Option<Entity<QuickEditState>> {
+    if !check_quick_editable(workspace, &editor, cx) {
+        return None;
+    }
+
+    editor.update(cx, |editor, cx| {
+        editor.change_selections(SelectionEffects::default(), window, cx, |selections| {
+            selections
+                .select_ranges([MultiBufferOffset(range.start)..MultiBufferOffset(range.end)]);
+        });
+    });
+
+    let workspace_entity = cx.entity();
+    let state = cx.new(|cx| {
+        let mut state = QuickEditState::new(editor.clone(), workspace_entity, range, cx);
+        if let Some(prompt) = prompt {
+            state.set_prompt(prompt);
+        }
+        state.observe_editor_edits(cx);
+        state.refresh_selection_info(cx);
+        state
+    });
+    warn_if_over_budget(workspace, &state, cx);
+    warn_if_minified(workspace, &state, cx);
+    let previous_state = cx
+        .default_global::<QuickEditSessions>()
+        .0
+        .insert(editor.entity_id(), state.clone());
+    // Earlier quick edits of this editor can still be undone, so their labels are kept
+    if let Some(previous_state) = previous_state {
+        let undo_labels = previous_state.read(cx).undo_labels.clone();
+        state.update(cx, |state, _| state.undo_labels.extend(undo_labels));
+    }
+    Some(state)
+}
+
+/// Start a quick edit session for `range` of `editor` and open the quick edit input
+/// for it, prefilled with `initial_prompt`.
+///
+/// Returns `None`, after telling the user why, when the editor is read-only.
+pub(crate) fn open_quick_edit_input(
+    workspace: &mut Workspace,
+    editor: Entity<Editor>,
+    range: Range<usize>,
+    initial_prompt: Option<String>,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) -> Option<Entity<QuickEditState>> {
+    let state = start_quick_edit_session(workspace, editor.clone(), range, None, window, cx)?;
+    QuickEditInput::toggle(workspace, editor, state.clone(), initial_prompt, window, cx);
+    Some(state)
+}
+
+/// Start a quick edit session for `range` of `editor` and send it right away.
+///
+/// `full_prompt`, when given, is sent instead of the prompt built from `instruction`
+/// and the selection. Fails, after telling the user why, when the editor is read-only
+/// or no model is configured.
+pub(crate) fn start_quick_edit(
+    workspace: &mut Workspace,
+    editor: Entity<Editor>,
+    range: Range<usize>,
+    instruction: String,
+    full_prompt: Option<String>,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) -> Result<Entity<QuickEditState>, QuickEditError> {
+    let state = start_quick_edit_session(
+        workspace,
+        editor.clone(),
+        range,
+        Some(instruction),
+        window,
+        cx,
+    )
+    .ok_or(QuickEditError::BufferReadOnly)?;
+    let mode = active_agent_mode(workspace, editor.read(cx), cx);
+    let result = state.update(cx, |state, cx| state.submit(full_prompt, mode, window, cx));
+    match &result {
+        Ok(()) => report_quick_edit_submitted(state.read(cx), cx),
+        Err(error) => show_quick_edit_error(workspace, error, cx),
+    }
+    result.map(|()| state)
+}
+
+/// The mode selected in the workspace's agent panel, which decides whether quick
+/// edits are applied or proposed. Without the panel, quick edits use the default
+/// mode for the language of `editor`'s buffer.
+pub(crate) fn active_agent_mode(workspace: &Workspace, editor: &Editor, cx: &App) -> AgentMode {
+    match workspace.panel::<AgentPanel>(cx) {
+        Some(panel) => panel.read(cx).active_mode().clone(),
+        None => LanguageModeDefaults::get(cx).default_mode_for_editor(editor, cx),
+    }
+}
+
+/// Start a quick edit of `range` in `editor`, with the quick edit input prefilled with `prompt`.
+///
+/// This is the programmatic counterpart to the `QuickEdit` action, for other
+/// features to offer quick edits of their own. When `auto_submit` is set and a
+/// prompt is given, the edit is sent right away instead of waiting for the user.
+/// Fails when the editor isn't part of a workspace, is read-only, or doesn't contain `range`.
+pub fn open_quick_edit(
+    editor: &Entity<Editor>,
+    range: Range<usize>,
+    prompt: Option<String>,
+    auto_submit: bool,
+    window: &mut Window,
+    cx: &mut App,
+) -> Result<Entity<QuickEditState>, QuickEditError> {
+    let workspace = editor
+        .read(cx)
+        .workspace()
+        .ok_or(QuickEditError::NoEditor)?;
+    ensure_quick_editable(editor.read(cx), cx)?;
+    if range.start > range.end || range.end > editor.read(cx).buffer().read(cx).len(cx).0 {
+        return Err(QuickEditError::InvalidRange);
+    }
+    workspace.update(cx, |workspace, cx| match prompt {
+        Some(prompt) if auto_submit => {
+            start_quick_edit(workspace, editor.clone(), range, prompt, None, window, cx)
+        }
+        prompt => open_quick_edit_input(workspace, editor.clone(), range, prompt, window, cx)
+            .ok_or(QuickEditError::BufferReadOnly),
+    })
+}
+
+/// Re-run the workspace's most recently submitted quick edit prompt on `range` of
+/// `editor`, sending it right away. Returns `None`, after telling the user why, when
+/// no prompt was submitted yet or the editor is read-only.
+pub(crate) fn repeat_last_quick_edit(
+    workspace: &mut Workspace,
+    editor: Entity<Editor>,
+    range: Range<usize>,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) -> Option<Entity<QuickEditState>> {
+    let Some(prompt) = last_prompt(workspace.database_id(), cx) else {
+        struct NoQuickEditToRepeatToast;
+        workspace.show_toast(
+            Toast::new(
+                NotificationId::unique::<NoQuickEditToRepeatToast>(),
+                "There is no previous quick edit to repeat.",
+            )
+            .autohide(),
+            cx,
+        );
+        return None;
+    };
+    start_quick_edit(workspace, editor, range, prompt, None, window, cx).log_err()
+}
+
+/// Report that a quick edit was sent, with its selection size and language but
+/// without any of its code
+pub(crate) fn report_quick_edit_submitted(state: &QuickEditState, cx: &App) {
+    let line_count = state
+        .selection_info
+        .as_ref()
+        .map_or(0, |selection_info| selection_info.line_count);
+    let language_name = state.editor.upgrade().and_then(|editor| {
+        editor
+            .read(cx)
+            .buffer()
+            .read(cx)
+            .language_at(MultiBufferOffset(state.selection_range.start), cx)
+            .map(|language| language.name())
+    });
+    telemetry::send_event(quick_edit_submitted_event(
+        line_count,
+        language_name.as_ref().map(AsRef::as_ref),
+    ));
+}
+
+/// Warn when the quick edit's selection is unlikely to fit in the inline assistant
+/// model's context window, before the user sends it
+fn warn_if_over_budget(
+    workspace: &mut Workspace,
+    state: &Entity<QuickEditState>,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(model) = LanguageModelRegistry::read_global(cx).inline_assistant_model() else {
+        return;
+    };
+    let context_window = u32::try_from(model.model.max_token_count()).unwrap_or(u32::MAX);
+    let reserve = AgentSettings::get_global(cx).quick_edit_response_reserve;
+    if !state.read(cx).exceeds_budget(context_window, reserve) {
+        return;
+    }
+
+    struct QuickEditOverBudgetToast;
+    workspace.show_toast(
+        Toast::new(
+            NotificationId::unique::<QuickEditOverBudgetToast>(),
+            "This selection may not fit in the model's context window. Consider narrowing the selection.",
+        )
+        .autohide(),
+        cx,
+    );
+}
+
+fn warn_if_minified(
+    workspace: &mut Workspace,
+    state: &Entity<QuickEditState>,
+    cx: &mut Context<Workspace>,
+) {
+    let looks_minified = state
+        .read(cx)
+        .selection_info()
+        .is_some_and(|info| info.looks_minified());
+    if !looks_minified {
+        return;
+    }
+
+    struct QuickEditMinifiedToast;
+    workspace.show_toast(
+        Toast::new(
+            NotificationId::unique::<QuickEditMinifiedToast>(),
+            "This selection looks minified or generated. Editing it may use many tokens and produce hard-to-review changes.",
+        )
+        .autohide(),
+        cx,
+    );
+}
+
+/// Get the offset range of the editor's newest selection, expanding an empty
+/// selection to the innermost symbol containing the cursor. Outside of a symbol,
+/// this falls back like `resolve_target_range`.
+pub(crate) fn selection_or_symbol_range(editor: &Editor, cx: &mut App) -> Option<Range<usize>> {
+    let display_snapshot = editor.display_snapshot(cx);
+    let selection = editor
+        .selections
+        .newest::<MultiBufferOffset>(&display_snapshot);
+    if selection.is_empty() {
+        let buffer = display_snapshot.buffer_snapshot();
+        if let Some((_, symbols)) = buffer.symbols_containing(selection.head(), None)
+            && let Some(symbol) = symbols.last()
+        {
+            let start = symbol.range.start.to_offset(buffer);
+            let end = symbol.range.end.to_offset(buffer);
+            return Some(start.0..end.0);
+        }
+    }
+    resolve_target_range(editor, cx)
+}
+
+/// Resolve the offset range an agent action targets.
+///
+/// This is the newest selection, else the cursor's line when it has content,
+/// else the whole buffer. Returns `None` when the buffer is empty.
+pub(crate) fn resolve_target_range(editor: &Editor, cx: &mut App) -> Option<Range<usize>> {
+    let has_selection = editor.has_non_empty_selection(&editor.display_snapshot(cx));
+    let range = selection_or_line_range(editor, cx);
+    let buffer = editor.buffer().read(cx).snapshot(cx);
+    let line_has_content = buffer
+        .text_for_range(MultiBufferOffset(range.start)..MultiBufferOffset(range.end))
+        .any(|chunk| !chunk.trim().is_empty());
+    if has_selection || line_has_content {
+        return Some(range);
+    }
+
+    let len = buffer.len().0;
+    (len > 0).then_some(0..len)
+}
+
+/// Get the offset range of the editor's newest selection, expanding an empty
+/// selection to the whole line containing the cursor.
+pub(crate) fn selection_or_line_range(editor: &Editor, cx: &mut App) -> Range<usize> {
+    let display_snapshot = editor.display_snapshot(cx);
+    let selection = editor
+        .selections
+        .newest::<MultiBufferOffset>(&display_snapshot);
+    if !selection.is_empty() {
+        return selection.start.0..selection.end.0;
+    }
+
+    let buffer = display_snapshot.buffer_snapshot();
+    let row = buffer.offset_to_point(selection.head()).row;
+    let start = buffer.point_to_offset(Point::new(row, 0));
+    let end = buffer.point_to_offset(Point::new(row, buffer.line_len(MultiBufferRow(row))));
+    start.0..end.0
+}
+
+/// Instruction sent ahead of every quick edit prompt, since the response replaces the selection
+const QUICK_EDIT_SYSTEM_PROMPT: &str = "You rewrite the code you are given according to the \
+    user's instruction. Reply only with the code that replaces it, without explanations.";
+
+/// Build the request sending a quick edit prompt to the inline assistant model
+fn quick_edit_request(prompt: String, temperature: Option<f32>) -> LanguageModelRequest {
+    LanguageModelRequest {
+        thread_id: None,
+        prompt_id: None,
+        intent: Some(CompletionIntent::InlineAssist),
+        mode: None,
+        messages: vec![
+            LanguageModelRequestMessage {
+                role: Role::System,
+                content: vec![QUICK_EDIT_SYSTEM_PROMPT.into()],
+                cache: false,
+                reasoning_details: None,
+            },
+            LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![prompt.into()],
+                cache: false,
+                reasoning_details: None,
+            },
+        ],
+        tools: Vec::new(),
+        tool_choice: None,
+        stop: Vec::new(),
+        temperature,
+        thinking_allowed: false,
+    }
+}
+
+/// Combine an instruction with the code it applies to and that code's context.
+///
+/// Every prompt built from a selection goes through here, so they share one layout.
+pub(crate) fn format_agent_prompt(instruction: &str, context: &ContextInfo, code: &str) -> String {
+    format!(
+        "{}\n\n{}",
+        instruction.trim_end(),
+        context.format_for_agent(code)
+    )
+}
+
+/// Build the prompt applying `instruction` to an offset range of the editor's buffer.
+///
+/// Returns `None` when the range is out of bounds.
+pub(crate) fn build_agent_prompt(
+    editor: &Editor,
+    range: Range<usize>,
+    instruction: &str,
+    cx: &App,
+) -> Option<String> {
+    let (code, context) = selection_text_and_context(editor, range, cx)?;
+    Some(format_agent_prompt(instruction, &context, &code))
+}
+
+/// Get the text and context information for an offset range of the editor's buffer
+pub(crate) fn selection_text_and_context(
+    editor: &Editor,
+    range: Range<usize>,
+    cx: &App,
+) -> Option<(String, ContextInfo)> {
+    let buffer = editor.buffer().read(cx).snapshot(cx);
+    let start_offset = MultiBufferOffset(range.start);
+    let end_offset = MultiBufferOffset(range.end);
+    if end_offset > buffer.len() || start_offset > end_offset {
+        return None;
+    }
+
+    let text = buffer.text_for_range(start_offset..end_offset).collect();
+    Some((text, ContextInfo::for_range(&buffer, range, cx)))
+}
+
+/// How `ContextInfo::format_for_agent_with_style` lays out the file/line context
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FormatStyle {
+    /// The context goes in its own code block above the code
+    #[default]
+    FencedContext,
+    /// The context is a plain Markdown line above the code block
+    MarkdownHeader,
+    /// The context is a comment on the first line of the code block, in the
+    /// language's comment syntax. Falls back to `FencedContext` for languages
+    /// without a known comment syntax.
+    CommentHeader,
+}
+
+/// Get the delimiters of a single-line comment in `language`, as returned by
+/// `ContextInfo::infer_language`
+fn comment_delimiters(language: &str) -> Option<(&'static str, &'static str)> {
+    match language {
+        "rust" | "typescript" | "javascript" | "go" | "c" | "cpp" | "java" => Some(("// ", "")),
+        "python" | "shell" => Some(("# ", "")),
+        "sql" => Some(("-- ", "")),
+        "html" => Some(("<!-- ", " -->")),
+        _ => None,
+    }
+}
+
+/// Builder for `ContextInfo`, created with `ContextInfo::builder`
+#[derive(Clone, Debug)]
+pub struct ContextInfoBuilder {
+    context: ContextInfo,
+}
+
+impl ContextInfoBuilder {
+    pub fn file_path(mut self, file_path: impl Into<String>) -> Self {
+        self.context.file_path = Some(file_path.into());
+        self
+    }
+
+    pub fn relative_path(mut self, relative_path: impl Into<String>) -> Self {
+        self.context.relative_path = Some(relative_path.into());
+        self
+    }
+
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.context.language = Some(language.into());
+        self
+    }
+
+    pub fn columns(mut self, start_column: u32, end_column: u32) -> Self {
+        self.context.start_column = start_column;
+        self.context.end_column = end_column;
+        self
+    }
+
+    pub fn build(self) -> ContextInfo {
+        self.context
+    }
+}
+
+/// Get a Markdown code fence long enough that no backtick run in `text` closes it
+fn code_fence(text: &str) -> String {
+    let mut longest_run = 0;
+    let mut current_run = 0;
+    for character in text.chars() {
+        if character == '`' {
+            current_run += 1;
+            longest_run = longest_run.max(current_run);
+        } else {
+            current_run = 0;
+        }
+    }
+    "`".repeat((longest_run + 1).max(3))
+}
+
+/// Remove a single code fence wrapping the whole of an agent's response, so that
+/// applying it doesn't write the fence into the buffer.
+///
+/// The opening fence may be tagged with `expected_language`, or with any language
+/// when that's empty. A fence tagged with another language is kept, since it's
+/// likely part of the intended content, e.g. a Rust example in a Markdown file.
+/// Fences inside the response are left intact, and a response that isn't fenced
+/// is returned unchanged.
+pub fn strip_code_fence(response: &str, expected_language: &str) -> String {
+    let trimmed_response = response.trim();
+    let Some((opening_fence, rest)) = trimmed_response.split_once('\n') else {
+        return response.to_string();
+    };
+    let fence_len = opening_fence
+        .chars()
+        .take_while(|character| *character == '`')
+        .count();
+    let language_tag = opening_fence[fence_len..].trim();
+    let tag_matches = language_tag.is_empty()
+        || expected_language.is_empty()
+        || language_tag.eq_ignore_ascii_case(expected_language);
+    if fence_len < 3 || !tag_matches || language_tag.contains(char::is_whitespace) {
+        return response.to_string();
+    }
+
+    let (body, closing_fence) = rest.rsplit_once('\n').unwrap_or(("", rest));
+    let closing_fence = closing_fence.trim();
+    if closing_fence.len() < fence_len || !closing_fence.chars().all(|character| character == '`') {
+        return response.to_string();
+    }
+    body.to_string()
+}
+
+/// Shorten `code` to its first and last lines when it has more than `max_lines`,
+/// putting a marker with the number of omitted lines in between
+fn truncate_lines(code: &str, max_lines: usize) -> Cow<'_, str> {
+    let lines = code.lines().collect::<Vec<_>>();
+    if lines.len() <= max_lines {
+        return Cow::Borrowed(code);
+    }
+
+    let tail_len = max_lines / 2;
+    let head_len = max_lines - tail_len;
+    let marker = format!("… ({} lines omitted) …", lines.len() - max_lines);
+    let mut kept = lines[..head_len].to_vec();
+    kept.push(&marker);
+    kept.extend_from_slice(&lines[lines.len() - tail_len..]);
+    Cow::Owned(kept.join("\n"))
+}
+
+/// Information about the context of a selection
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ContextInfo {
+    /// Full path of the file, including the worktree root name
+    pub file_path: Option<String>,
+    /// Path of the file relative to its worktree
+    pub relative_path: Option<String>,
+    pub start_line: u32,
+    pub end_line: u32,
+    pub start_column: u32,
+    pub end_column: u32,
+    /// Name of the buffer's language, for when the path doesn't tell it
+    pub language: Option<String>,
+}
+
+/// Stands in for the path of buffers that don't have one, such as unsaved scratch buffers
+const UNTITLED_BUFFER_LABEL: &str = "Untitled buffer";
+
+impl ContextInfo {
+    /// Start building context information for the given zero-based lines.
+    ///
+    /// Every other field is left empty unless set on the builder, so columns default to 0.
+    pub fn builder(start_line: u32, end_line: u32) -> ContextInfoBuilder {
+        ContextInfoBuilder {
+            context: Self {
+                file_path: None,
+                relative_path: None,
+                start_line,
+                end_line,
+                start_column: 0,
+                end_column: 0,
+                language: None,
+            },
+        }
+    }
+
+    /// Build context information for an offset range of a buffer
+    pub(crate) fn for_range(buffer: &MultiBufferSnapshot, range: Range<usize>, cx: &App) -> Self {
+        let start_offset = MultiBufferOffset(range.start);
+        let end_offset = MultiBufferOffset(range.end);
+
+        let file = buffer.file_at(start_offset);
+        let language = buffer.language_at(start_offset);
+        let start_point = buffer.offset_to_point(start_offset);
+        let end_point = buffer.offset_to_point(end_offset);
+        Self::for_point_range(file, language, start_point..end_point, cx)
+    }
+
+    /// Build context information for each buffer that an offset range of a
+    /// multibuffer spans, using that buffer's own path and lines.
+    ///
+    /// Excerpts of the same buffer are merged into a single entry covering all of them.
+    pub(crate) fn for_excerpts(
+        buffer: &MultiBufferSnapshot,
+        range: Range<usize>,
+        cx: &App,
+    ) -> Vec<Self> {
+        let mut buffer_ranges: Vec<(&BufferSnapshot, Range<Point>)> = Vec::new();
+        for (buffer_snapshot, buffer_range, _) in buffer
+            .range_to_buffer_ranges(MultiBufferOffset(range.start)..MultiBufferOffset(range.end))
+        {
+            let start = buffer_snapshot.offset_to_point(buffer_range.start.0);
+            let end = buffer_snapshot.offset_to_point(buffer_range.end.0);
+            let buffer_id = buffer_snapshot.remote_id();
+            if let Some((_, point_range)) = buffer_ranges
+                .iter_mut()
+                .find(|(existing, _)| existing.remote_id() == buffer_id)
+            {
+                point_range.start = point_range.start.min(start);
+                point_range.end = point_range.end.max(end);
+            } else {
+                buffer_ranges.push((buffer_snapshot, start..end));
+            }
+        }
+
+        if buffer_ranges.is_empty() {
+            return vec![Self::for_range(buffer, range, cx)];
+        }
+        buffer_ranges
+            .into_iter()
+            .map(|(buffer_snapshot, point_range)| {
+                Self::for_point_range(
+                    buffer_snapshot.file(),
+                    buffer_snapshot.language(),
+                    point_range,
+                    cx,
+                )
+            })
+            .collect()
+    }
+
+    fn for_point_range(
+        file: Option<&Arc<dyn File>>,
+        language: Option<&Arc<Language>>,
+        range: Range<Point>,
+        cx: &App,
+    ) -> Self {
+        let file_path = file.map(|file| file.full_path(cx).to_string_lossy().to_string());
+        let relative_path = file.map(|file| file.path().display(file.path_style(cx)).to_string());
+
+        Self {
+            file_path,
+            relative_path,
+            start_line: range.start.row,
+            end_line: range.end.row,
+            start_column: range.start.column,
+            end_column: range.end.column,
+            language: language.map(|language| language.name().to_string()),
+        }
+    }
+
+    /// Whether `other` covers the same lines of the same file.
+    ///
+    /// Columns are ignored, as they shift with any edit on the selection's lines.
+    pub fn is_same_region(&self, other: &ContextInfo) -> bool {
+        self.file_path == other.file_path
+            && self.relative_path == other.relative_path
+            && self.start_line == other.start_line
+            && self.end_line == other.end_line
+    }
+
+    /// Format context info as a human-readable string
+    pub fn format(&self) -> String {
+        self.format_with_file_label("File:")
+    }
+
+    fn format_with_file_label(&self, file_label: &str) -> String {
+        let mut parts = Vec::new();
+
+        // Without a path, still say where the code is from, so it isn't mistaken for a file
+        let path = self.relative_path.as_ref().or(self.file_path.as_ref());
+        parts.push(format!(
+            "{file_label} {}",
+            path.map_or(UNTITLED_BUFFER_LABEL, String::as_str)
+        ));
+
+        let full_lines = self.start_column == 0 && self.end_column == 0;
+        if self.start_line == self.end_line {
+            if full_lines {
+                parts.push(format!("Line {}", self.start_line + 1));
+            } else {
+                parts.push(format!(
+                    "Line {}:{}-{}",
+                    self.start_line + 1,
+                    self.start_column,
+                    self.end_column
+                ));
+            }
+        } else if full_lines {
+            parts.push(format!(
+                "Lines {}-{}",
+                self.start_line + 1,
+                self.end_line + 1
+            ));
+        } else {
+            parts.push(format!(
+                "Lines {}:{}-{}:{}",
+                self.start_line + 1,
+                self.start_column,
+                self.end_line + 1,
+                self.end_column
+            ));
+        }
+
+        parts.join(" • ")
+    }
+
+    /// Format selection with code block and context for agent
+    pub fn format_for_agent(&self, code: &str) -> String {
+        self.format_for_agent_with_style(code, FormatStyle::default())
+    }
+
+    /// Format selection with code block and context for agent, laying out the
+    /// context as `style` describes
+    pub fn format_for_agent_with_style(&self, code: &str, style: FormatStyle) -> String {
+        self.format_for_agent_with_options(code, style, None)
+    }
+
+    /// Format selection with code block and context for agent, keeping at most
+    /// `max_lines` lines of `code` when given.
+    ///
+    /// Longer code keeps its first and last lines around an omission marker, while
+    /// the context still reports the full range.
+    pub fn format_for_agent_with_options(
+        &self,
+        code: &str,
+        style: FormatStyle,
+        max_lines: Option<usize>,
+    ) -> String {
+        let code = match max_lines {
+            Some(max_lines) => truncate_lines(code, max_lines),
+            None => Cow::Borrowed(code),
+        };
+        let language = self.infer_language();
+        let context_comment = match style {
+            FormatStyle::CommentHeader => comment_delimiters(&language),
+            FormatStyle::FencedContext | FormatStyle::MarkdownHeader => None,
+        };
+        let mut result = String::new();
+
+        // Paths may contain backticks, which would otherwise run into the fences.
+        let code = match (style, context_comment) {
+            (FormatStyle::CommentHeader, Some((prefix, suffix))) => {
+                // The fence below is chosen after the comment, so backticks in it are safe
+                Cow::Owned(format!("{prefix}{}{suffix}\n{code}", self.format()))
+            }
+            (FormatStyle::FencedContext | FormatStyle::CommentHeader, _) => {
+                // Add file/line context as a comment in the code block
+                let context = self.format().replace('`', "'");
+                let fence = code_fence(&context);
+                result.push_str(&format!("{fence}\n{context}\n{fence}\n\n"));
+                code
+            }
+            (FormatStyle::MarkdownHeader, _) => {
+                let context = self.format_with_file_label("**File:**").replace('`', "'");
+                result.push_str(&format!("{context}\n\n"));
+                code
+            }
+        };
+
+        // Add the code block with syntax hint
+        let fence = code_fence(&code);
+        result.push_str(&format!("{fence}{language}\n{code}\n{fence}"));
+
+        result
+    }
+
+    /// Build a structured payload of the code and its context, for tools that
+    /// consume it without parsing Markdown.
+    ///
+    /// Lines are 1-based and inclusive, matching `format`. `file` and `language`
+    /// are null when unknown.
+    pub fn to_payload(&self, code: &str) -> serde_json::Value {
+        let language = self.infer_language();
+        serde_json::json!({
+            "file": self.relative_path.as_ref().or(self.file_path.as_ref()),
+            "start_line": self.start_line + 1,
+            "end_line": self.end_line + 1,
+            "language": (!language.is_empty()).then_some(language),
+            "code": code,
+        })
+    }
+
+    /// Reference to the lines in GitHub style, e.g. `src/main.rs#L6-L11`, using the
+    /// relative path when known. `None` without any path.
+    pub fn local_reference(&self) -> Option<String> {
+        let path = self.relative_path.as_ref().or(self.file_path.as_ref())?;
+        let lines = if self.start_line == self.end_line {
+            format!("L{}", self.start_line + 1)
+        } else {
+            format!("L{}-L{}", self.start_line + 1, self.end_line + 1)
+        };
+        Some(format!("{path}#{lines}"))
+    }
+
+    /// Permalink to the lines at the current commit on the hosting provider of the
+    /// file's git remote.
+    ///
+    /// Falls back to `local_reference` when the file isn't in a repository with a
+    /// recognized origin remote and a commit.
+    pub fn permalink(&self, project: &Project, cx: &App) -> Option<String> {
+        self.remote_permalink(project, cx)
+            .or_else(|| self.local_reference())
+    }
+
+    fn remote_permalink(&self, project: &Project, cx: &App) -> Option<String> {
+        let project_path = project.find_project_path(self.file_path.as_ref()?, cx)?;
+        let (repository, repo_path) = project
+            .git_store()
+            .read(cx)
+            .repository_and_path_for_project_path(&project_path, cx)?;
+        let repository = repository.read(cx);
+        let origin_url = repository.remote_origin_url.as_ref()?;
+        let sha = &repository.head_commit.as_ref()?.sha;
+
+        let provider_registry = GitHostingProviderRegistry::try_global(cx)?;
+        let (provider, remote) = parse_git_remote_url(provider_registry, origin_url)?;
+        let permalink = provider.build_permalink(
+            remote,
+            BuildPermalinkParams::new(sha, &repo_path, Some(self.start_line..self.end_line)),
+        );
+        Some(permalink.to_string())
+    }
+
+    /// Infer programming language from file path
+    pub(crate) fn infer_language(&self) -> String {
+        let from_path = self.file_path.as_deref().and_then(|path| {
+            let language = if path.ends_with(".rs") {
+                "rust"
+            } else if path.ends_with(".ts") || path.ends_with(".tsx") {
+                "typescript"
+            } else if path.ends_with(".js") || path.ends_with(".jsx") {
+                "javascript"
+            } else if path.ends_with(".py") {
+                "python"
+            } else if path.ends_with(".go") {
+                "go"
+            } else if path.ends_with(".c") || path.ends_with(".h") {
+                "c"
+            } else if path.ends_with(".cpp") || path.ends_with(".cc") {
+                "cpp"
+            } else if path.ends_with(".java") {
+                "java"
+            } else if path.ends_with(".sql") {
+                "sql"
+            } else if path.ends_with(".sh") || path.ends_with(".bash") {
+                "shell"
+            } else if path.ends_with(".html") || path.ends_with(".htm") {
+                "html"
+            } else {
+                return None;
+            };
+            Some(language.to_string())
+        });
+        // Untitled buffers and unknown extensions fall back to the buffer's language
+        from_path
+            .or_else(|| {
+                self.language
+                    .as_deref()
+                    .map(|language| match language.to_lowercase().as_str() {
+                        "c++" => "cpp".to_string(),
+                        "shell script" => "shell".to_string(),
+                        language => language.to_string(),
+                    })
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// What an agent action would target and prompt with, computed without side effects.
+///
+/// Handlers build this with a `prepare_*` function and then open the UI for it or
+/// send it, so the payload can be previewed or tested on its own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PreparedAction {
+    /// Offset range of the editor's buffer the action applies to
+    pub range: Range<usize>,
+    /// Prompt to prefill or send, or `None` to let the user write one
+    pub prompt: Option<String>,
+}
+
+/// Prepare a quick edit of the range `scope` resolves to, with an optional prefilled prompt
+pub(crate) fn prepare_quick_edit(
+    editor: &Editor,
+    prompt: Option<String>,
+    scope: QuickEditScope,
+    cx: &mut App,
+) -> Option<PreparedAction> {
+    let range = scope.resolve_range(editor, cx)?;
+    Some(PreparedAction { range, prompt })
+}
+
+/// Handle quick edit action from editor, sending its prompt right away, or opening
+/// the quick edit input when it has none
+pub(crate) fn handle_quick_edit(
+    workspace: &mut Workspace,
+    action: &QuickEdit,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let prompt = (!action.prompt.is_empty()).then(|| action.prompt.clone());
+    let Some(prepared) = editor.update(cx, |editor, cx| {
+        prepare_quick_edit(editor, prompt, action.scope, cx)
+    }) else {
+        show_quick_edit_error(workspace, &QuickEditError::EmptySelection, cx);
+        return;
+    };
+    match prepared.prompt {
+        Some(prompt) => {
+            start_quick_edit(workspace, editor, prepared.range, prompt, None, window, cx).log_err();
+        }
+        None => {
+            open_quick_edit_input(workspace, editor, prepared.range, None, window, cx);
+        }
+    }
+}
+
+/// Open the quick edit input for the active editor's selection
+fn handle_open_quick_edit_for_selection(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    // The action closes the input when it's already open
+    if workspace.active_modal::<QuickEditInput>(cx).is_some() {
+        workspace.hide_modal(window, cx);
+        return;
+    }
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prepared) = editor.update(cx, |editor, cx| {
+        prepare_quick_edit(editor, None, QuickEditScope::Selection, cx)
+    }) else {
+        return;
+    };
+    open_quick_edit_input(workspace, editor, prepared.range, None, window, cx);
+}
+
+/// Repeat the last quick edit on the active editor's selection
+fn handle_repeat_last_quick_edit(
+    workspace: &mut Workspace,
+    window: &mut Window,
+    cx: &mut Context<Workspace>,
+) {
+    let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+        return;
+    };
+    let Some(prepared) = editor.update(cx, |editor, cx| {
+        prepare_quick_edit(editor, None, QuickEditScope::Selection, cx)
+    }) else {
+        show_quick_edit_error(workspace, &QuickEditError::EmptySelection, cx);
+        return;
+    };
+    repeat_last_quick_edit(workspace, editor, prepared.range, window, cx);
+}
+
+/// Handle sending selection to agent panel
+pub(crate) fn handle_send_selection_to_agent(
+    _workspace: &mut Workspace,
+    _action: &SendSelectionToAgent,
+    _window: &mut Window,
+    _cx: &mut Context<Workspace>,
+) {
+    // TODO: Add selection to agent panel message editor
+    // This would require integrating with the agent panel directly
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use gpui::{TestAppContext, VisualTestContext};
+    use language::Capability;
+    use language_model::fake_provider::{FakeLanguageModel, FakeLanguageModelProvider};
+    use multi_buffer::{ExcerptRange, MultiBuffer};
+    use project::{FakeFs, Project};
+    use settings::SettingsStore;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use util::path;
+
+    fn init_test(cx: &mut App) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        theme::init(theme::LoadThemes::JustBase, cx);
+        LanguageModelRegistry::test(cx);
+    }
+
+    pub(crate) async fn build_test_editor(
+        text: &str,
+        cx: &mut TestAppContext,
+    ) -> (Entity<Workspace>, Entity<Editor>, VisualTestContext) {
+        build_test_editor_with_files(text, serde_json::json!({}), cx).await
+    }
+
+    /// Like `build_test_editor`, with `files` in the project's `/test` worktree
+    pub(crate) async fn build_test_editor_with_files(
+        text: &str,
+        files: serde_json::Value,
+        cx: &mut TestAppContext,
+    ) -> (Entity<Workspace>, Entity<Editor>, VisualTestContext) {
+        cx.update(init_test);
+
+        let fs = FakeFs::new(cx.executor());
+        fs.insert_tree(path!("/test"), files).await;
+        let project = Project::test(fs, [path!("/test").as_ref()], cx).await;
+        let window = cx.add_window(|window, cx| Workspace::test_new(project.clone(), window, cx));
+        let workspace = window.root(cx).unwrap();
+        let mut cx = VisualTestContext::from_window(*window, cx);
+
+        let editor = window
+            .update(&mut cx, |_, window, cx| {
+                cx.new(|cx| {
+                    Editor::for_multibuffer(MultiBuffer::build_simple(text, cx), None, window, cx)
+                })
+            })
+            .unwrap();
+
+        (workspace, editor, cx)
+    }
+
+    fn newest_selection(editor: &Entity<Editor>, cx: &mut VisualTestContext) -> Range<usize> {
+        editor.update(cx, |editor, cx| {
+            let selection = editor
+                .selections
+                .newest::<MultiBufferOffset>(&editor.display_snapshot(cx));
+            selection.start.0..selection.end.0
+        })
+    }
+
+    /// Make a fake model the inline assistant model, so tests can drive its responses
+    pub(crate) fn set_fake_model(cx: &mut VisualTestContext) -> Arc<FakeLanguageModel> {
+        cx.update(|_, cx| {
+            let provider = Arc::new(FakeLanguageModelProvider::default());
+            let model = Arc::new(provider.test_model());
+            LanguageModelRegistry::global(cx).update(cx, |registry, cx| {
+                registry.set_inline_assistant_model(
+                    Some(ConfiguredModel {
+                        provider,
+                        model: model.clone(),
+                    }),
+                    cx,
+                );
+            });
+            model
+        })
+    }
+
+    /// The prompt of each request the model is still answering
+    pub(crate) fn sent_prompts(model: &FakeLanguageModel) -> Vec<String> {
+        model
+            .pending_completions()
+            .iter()
+            .filter_map(|request| request.messages.last())
+            .map(|message| message.string_contents())
+            .collect()
+    }
+
+    #[gpui::test]
+    async fn test_quick_edit_state_for_range(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    println!(\"hi\");\n}\n", cx).await;
+
+        let state = cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace, 12..31, cx));
+        assert_eq!(state.selection_range(), 12..31);
+        assert_eq!(state.prompt, "");
+
+        // An empty cursor expands to the whole line it sits on.
+        let range = editor.update_in(&mut cx, |editor, window, cx| {
+            editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                selections.select_ranges([MultiBufferOffset(16)..MultiBufferOffset(16)]);
+            });
+            selection_or_line_range(editor, cx)
+        });
+        assert_eq!(range, 12..31);
+    }
+
+    #[gpui::test]
+    async fn test_original_text_survives_buffer_edits(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let state = cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        assert_eq!(state.original_text(), Some("let a = 1;"));
+
+        editor.update(&mut cx, |editor, cx| {
+            editor.buffer().update(cx, |buffer, cx| {
+                buffer.edit(
+                    [(MultiBufferOffset(20)..MultiBufferOffset(21), "b")],
+                    None,
+                    cx,
+                );
+            });
+        });
+        assert_eq!(
+            cx.update(|_, cx| state.selected_text(cx)).as_deref(),
+            Some("let b = 1;")
+        );
+        assert_eq!(state.original_text(), Some("let a = 1;"));
+    }
+
+    #[gpui::test]
+    async fn test_resolve_target_range(cx: &mut TestAppContext) {
+        let (_workspace, editor, mut cx) = build_test_editor("let a = 1;\n\nlet b = 2;", cx).await;
+        let resolve = |range: Range<usize>, cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                    selections.select_ranges([
+                        MultiBufferOffset(range.start)..MultiBufferOffset(range.end)
+                    ]);
+                });
+                resolve_target_range(editor, cx)
+            })
+        };
+
+        // A selection is used as is
+        assert_eq!(resolve(4..9, &mut cx), Some(4..9));
+        // An empty cursor expands to its line
+        assert_eq!(resolve(2..2, &mut cx), Some(0..10));
+        // An empty cursor on a blank line falls back to the whole buffer
+        assert_eq!(resolve(11..11, &mut cx), Some(0..22));
+
+        let (_workspace, editor, mut cx) = build_test_editor("", cx).await;
+        let range = editor.update(&mut cx, |editor, cx| resolve_target_range(editor, cx));
+        assert_eq!(range, None);
+    }
+
+    #[gpui::test]
+    async fn test_selected_text_with_line_numbers(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n    let b = 2;\n}\n", cx).await;
+
+        // Rows 1 and 2, which are lines 2 and 3 in the file
+        let state =
+            cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace.clone(), 12..42, cx));
+        let numbered = cx.update(|_, cx| state.selected_text_with_line_numbers(cx));
+        assert_eq!(
+            numbered.as_deref(),
+            Some("2 |     let a = 1;\n3 |     let b = 2;")
+        );
+        assert_eq!(
+            cx.update(|_, cx| state.selected_text(cx)).as_deref(),
+            Some("    let a = 1;\n    let b = 2;\n")
+        );
+
+        let out_of_bounds = cx.update(|_, cx| QuickEditState::new(editor, workspace, 30..100, cx));
+        assert_eq!(
+            cx.update(|_, cx| out_of_bounds.selected_text_with_line_numbers(cx)),
+            None
+        );
+    }
+
+    #[gpui::test]
+    async fn test_apply_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let mut state =
+            cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        state.set_prompt("use a clearer name\nand sum the values");
+        let changes = cx
+            .update(|window, cx| state.apply_edit("let total = 1 + 2;".to_string(), window, cx))
+            .unwrap();
+        assert_eq!(changes, [ChangedLines::Modified(1..2)]);
+
+        assert_eq!(state.selection_range(), 16..34);
+        // The replacement is selected
+        assert_eq!(newest_selection(&editor, &mut cx), 16..34);
+        assert_eq!(
+            cx.update(|_, cx| state.selected_text(cx)).as_deref(),
+            Some("let total = 1 + 2;")
+        );
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let total = 1 + 2;\n}\n"
+        );
+
+        // The replacement is a single labelled transaction
+        let transaction_id = editor
+            .update(&mut cx, |editor, cx| {
+                editor.buffer().read(cx).last_transaction_id(cx)
+            })
+            .unwrap();
+        assert_eq!(
+            state.undo_label(transaction_id),
+            Some("Quick Edit: use a clearer name")
+        );
+        assert_eq!(state.undo_labels.len(), 1);
+
+        // The whole replacement is undone in one step
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.undo(&editor::actions::Undo, window, cx)
+        });
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let a = 1;\n}\n"
+        );
+
+        state.selection_range = 100..120;
+        assert_eq!(
+            cx.update(|window, cx| state.apply_edit("x".to_string(), window, cx)),
+            Err(QuickEditError::InvalidRange)
+        );
+    }
+
+    #[gpui::test]
+    async fn test_apply_edit_strips_code_fence(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let mut state =
+            cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        cx.update(|window, cx| {
+            state.apply_edit("```rust\nlet total = 1;\n```\n".to_string(), window, cx)
+        })
+        .unwrap();
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let total = 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_code_fence() {
+        // Fenced, with or without a language tag
+        assert_eq!(
+            strip_code_fence("```rust\nlet a = 1;\n```", "rust"),
+            "let a = 1;"
+        );
+        assert_eq!(
+            strip_code_fence("```Rust\nlet a = 1;\n```", "rust"),
+            "let a = 1;"
+        );
+        assert_eq!(
+            strip_code_fence("\n```\nfn a() {}\nfn b() {}\n```\n", "rust"),
+            "fn a() {}\nfn b() {}"
+        );
+        assert_eq!(strip_code_fence("```python\npass\n```", ""), "pass");
+        assert_eq!(strip_code_fence("```rust\n```", "rust"), "");
+
+        // Unfenced
+        assert_eq!(strip_code_fence("let a = 1;\n", "rust"), "let a = 1;\n");
+        assert_eq!(strip_code_fence("```rust", "rust"), "```rust");
+        assert_eq!(
+            strip_code_fence("```rust\nlet a = 1;\n```\nThis renames a.", "rust"),
+            "```rust\nlet a = 1;\n```\nThis renames a."
+        );
+
+        // A fence in another language is part of the content
+        assert_eq!(
+            strip_code_fence("```rust\nlet a = 1;\n```", "markdown"),
+            "```rust\nlet a = 1;\n```"
+        );
+
+        // Nested fences are kept
+        assert_eq!(
+            strip_code_fence(
+                "````markdown\nUsage:\n```rust\nlet a = 1;\n```\n````",
+                "markdown"
+            ),
+            "Usage:\n```rust\nlet a = 1;\n```"
+        );
+        assert_eq!(
+            strip_code_fence("Usage:\n```rust\nlet a = 1;\n```", "markdown"),
+            "Usage:\n```rust\nlet a = 1;\n```"
+        );
+    }
+
+    #[test]
+    fn test_quick_edit_undo_label() {
+        assert_eq!(quick_edit_undo_label(""), "Quick Edit");
+        assert_eq!(quick_edit_undo_label("\n  \n"), "Quick Edit");
+        assert_eq!(
+            quick_edit_undo_label("\n  add error handling  \nand logging"),
+            "Quick Edit: add error handling"
+        );
+        assert_eq!(
+            quick_edit_undo_label(&"word ".repeat(20)),
+            format!("Quick Edit: {}…", "word ".repeat(8).trim_end())
+        );
+    }
+
+    #[test]
+    fn test_changed_lines() {
+        assert_eq!(
+            changed_lines(
+                "alpha\nbeta\ngamma\ndelta\n",
+                "alpha\nBETA\ngamma\nxyz\ndelta\n"
+            ),
+            [ChangedLines::Modified(1..2), ChangedLines::Inserted(3..4)]
+        );
+        assert_eq!(
+            changed_lines("alpha\nbeta\ngamma\n", "alpha\ngamma\n"),
+            [ChangedLines::Deleted { row: 1, count: 1 }]
+        );
+        assert!(changed_lines("alpha\n", "alpha\n").is_empty());
+    }
+
+    #[test]
+    fn test_removed_lines() {
+        assert_eq!(
+            removed_lines(
+                "alpha\nbeta\ngamma\ndelta\nepsilon\n",
+                "alpha\nBETA\ngamma\n"
+            ),
+            [
+                RemovedLines {
+                    row: 1,
+                    text: "beta".to_string()
+                },
+                RemovedLines {
+                    row: 3,
+                    text: "delta\nepsilon".to_string()
+                },
+            ]
+        );
+        assert!(removed_lines("alpha\n", "alpha\nbeta\n").is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_proposal_shows_inline_diff(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n    let b = 2;\n}\n", cx).await;
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 12..42, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            let chunks = [Ok("    let a = 1;\n    let c = 3;\n".to_string())];
+            state.stream_edit(futures::stream::iter(chunks), AgentMode::Manual, window, cx);
+        });
+        cx.run_until_parked();
+
+        // The kept line is highlighted as proposed and the rewritten one as added
+        let (proposed_style, added_style) = cx.update(|_, cx| {
+            let settings = VisualIndicatorSettings::get(cx);
+            (
+                legible_highlight(GutterIndicator::Proposed.style(settings), cx),
+                legible_highlight(diff_added_style(), cx),
+            )
+        });
+        let mut highlighted_rows = editor.update_in(&mut cx, |editor, window, cx| {
+            editor
+                .all_text_highlights(window, cx)
+                .into_iter()
+                .flat_map(|(style, ranges)| {
+                    ranges
+                        .into_iter()
+                        .map(move |range| (range.start.row().0..range.end.row().0 + 1, style))
+                })
+                .collect::<Vec<_>>()
+        });
+        highlighted_rows.sort_by_key(|(rows, _)| rows.start);
+        assert_eq!(
+            highlighted_rows,
+            vec![(1..2, proposed_style), (2..3, added_style)]
+        );
+        state.read_with(&cx, |state, _| {
+            let proposal = state.proposal.as_ref().unwrap();
+            assert_eq!(proposal.removed_line_blocks.len(), 1);
+        });
+
+        // Resolving the proposal removes the diff
+        state.update(&mut cx, |state, cx| state.accept(cx));
+        state.read_with(&cx, |state, _| assert!(state.proposal.is_none()));
+        cx.executor()
+            .advance_clock(RESOLVED_PROPOSAL_HIGHLIGHT_DURATION);
+        cx.run_until_parked();
+        assert!(
+            editor
+                .update_in(&mut cx, |editor, window, cx| editor
+                    .all_text_highlights(window, cx))
+                .is_empty()
+        );
+    }
+
+    #[gpui::test]
+    async fn test_stream_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            let chunks = ["let total", " = 1", " + 2;"].map(|chunk| Ok(chunk.to_string()));
+            state.stream_edit(futures::stream::iter(chunks), AgentMode::Write, window, cx);
+        });
+        cx.run_until_parked();
+
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let total = 1 + 2;\n}\n"
+        );
+        state.update(&mut cx, |state, _| {
+            assert!(!state.is_streaming());
+            assert_eq!(state.selection_range(), 16..34);
+        });
+        assert_eq!(newest_selection(&editor, &mut cx), 16..34);
+
+        // All chunks are undone together
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.undo(&editor::actions::Undo, window, cx)
+        });
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let a = 1;\n}\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_submit_streams_response(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        let model = set_fake_model(&mut cx);
+
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            state.set_prompt("Rename a to total".to_string());
+            state.submit(None, AgentMode::Write, window, cx).unwrap();
+        });
+        cx.run_until_parked();
+
+        let expected_prompt = cx.update(|_, cx| {
+            state
+                .read(cx)
+                .build_prompt("Rename a to total", &[], u32::MAX, cx)
+                .unwrap()
+        });
+        assert!(expected_prompt.contains("fn main() {"));
+        assert_eq!(sent_prompts(&model), vec![expected_prompt]);
+
+        // The code fence around the response is left out of the buffer
+        model.send_last_completion_stream_text_chunk("```\nlet total");
+        model.send_last_completion_stream_text_chunk(" = 1;\n```");
+        model.end_last_completion_stream();
+        cx.run_until_parked();
+
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let total = 1;\n}\n"
+        );
+        state.update(&mut cx, |state, _| {
+            assert!(!state.is_streaming());
+            assert_eq!(state.selection_range(), 16..30);
+        });
+    }
+
+    #[gpui::test]
+    async fn test_submit_without_model(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        cx.update(|_, cx| {
+            LanguageModelRegistry::global(cx).update(cx, |registry, cx| {
+                registry.set_default_model(None, cx);
+            });
+        });
+
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        let result = state.update_in(&mut cx, |state, window, cx| {
+            state.submit(None, AgentMode::Write, window, cx)
+        });
+        assert!(matches!(result, Err(QuickEditError::ModelError(_))));
+    }
+
+    #[gpui::test]
+    async fn test_agent_mode_without_panel(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) = build_test_editor("SELECT 1;\n", cx).await;
+        let mode = |cx: &mut VisualTestContext| {
+            cx.update(|_, cx| active_agent_mode(workspace.read(cx), editor.read(cx), cx))
+        };
+        assert_eq!(mode(&mut cx), AgentMode::Write);
+
+        // The buffer has no language, so the fallback default mode applies
+        cx.update(|_, cx| {
+            cx.update_global::<SettingsStore, _>(|store, cx| {
+                store.update_user_settings(cx, |settings| {
+                    settings.agent.get_or_insert_default().default_mode = Some("ask".to_string());
+                });
+            });
+        });
+        assert_eq!(mode(&mut cx), AgentMode::Ask);
+    }
+
+    #[gpui::test]
+    async fn test_empty_response_retries_with_expanded_context(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        let model = set_fake_model(&mut cx);
+
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace.clone(), 16..26, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            state.set_prompt("Rename a to total".to_string());
+            state.submit(None, AgentMode::Write, window, cx).unwrap();
+        });
+        cx.run_until_parked();
+        model.end_last_completion_stream();
+        cx.run_until_parked();
+
+        // The same prompt is sent again for the larger selection
+        let expanded_range = state.read_with(&cx, |state, _| {
+            assert_eq!(state.retry_count(), 1);
+            state.selection_range()
+        });
+        assert!(expanded_range.start < 16 && expanded_range.end > 26);
+        let expected_prompt = cx.update(|_, cx| {
+            let state = state.read(cx);
+            assert_eq!(state.prompt(), "Rename a to total");
+            state
+                .build_prompt(state.prompt(), &[], u32::MAX, cx)
+                .unwrap()
+        });
+        assert_eq!(sent_prompts(&model), vec![expected_prompt]);
+
+        // Full prompts are sent as is, so they aren't retried
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            let prompt = Some("Rename a to total in: let a = 1;".to_string());
+            state.submit(prompt, AgentMode::Write, window, cx).unwrap();
+        });
+        cx.run_until_parked();
+        model.end_last_completion_stream();
+        cx.run_until_parked();
+        state.read_with(&cx, |state, _| {
+            assert_eq!(state.retry_count(), 0);
+            assert_eq!(state.selection_range(), 16..26);
+            assert!(!state.is_streaming());
+        });
+    }
+
+    #[gpui::test]
+    async fn test_accept_and_reject_proposal(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        let propose = |replacement: &str, cx: &mut VisualTestContext| {
+            state.update_in(cx, |state, window, cx| {
+                let chunks = [Ok(replacement.to_string())];
+                state.stream_edit(futures::stream::iter(chunks), AgentMode::Manual, window, cx);
+            });
+            cx.run_until_parked();
+        };
+        let has_proposal_context = |cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor
+                    .key_context(window, cx)
+                    .contains("quick_edit_proposal")
+            })
+        };
+        let highlight_styles = |cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor
+                    .all_text_highlights(window, cx)
+                    .into_iter()
+                    .map(|(style, _)| style)
+                    .collect::<Vec<_>>()
+            })
+        };
+        let (accepted_style, rejected_style) = cx.update(|_, cx| {
+            (
+                legible_highlight(accepted_change_style(), cx),
+                legible_highlight(rejected_change_style(), cx),
+            )
+        });
+
+        // Accepting keeps the proposed text, highlighted as accepted for a moment
+        propose("let b = 2;", &mut cx);
+        assert!(state.read_with(&cx, |state, _| state.has_pending_proposal()));
+        assert!(has_proposal_context(&mut cx));
+        assert!(!highlight_styles(&mut cx).is_empty());
+        state.update(&mut cx, |state, cx| state.accept(cx));
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let b = 2;\n}\n"
+        );
+        assert!(!state.read_with(&cx, |state, _| state.has_pending_proposal()));
+        assert!(!has_proposal_context(&mut cx));
+        assert_eq!(highlight_styles(&mut cx), vec![accepted_style]);
+        cx.executor()
+            .advance_clock(RESOLVED_PROPOSAL_HIGHLIGHT_DURATION);
+        cx.run_until_parked();
+        assert!(highlight_styles(&mut cx).is_empty());
+
+        // Rejecting restores the original text
+        propose("let total = 3;", &mut cx);
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let total = 3;\n}\n"
+        );
+        state.update(&mut cx, |state, cx| state.reject(cx));
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let b = 2;\n}\n"
+        );
+        state.read_with(&cx, |state, _| {
+            assert!(!state.has_pending_proposal());
+            assert_eq!(state.selection_range(), 16..26);
+        });
+        assert!(!has_proposal_context(&mut cx));
+        assert_eq!(highlight_styles(&mut cx), vec![rejected_style]);
+        cx.executor()
+            .advance_clock(RESOLVED_PROPOSAL_HIGHLIGHT_DURATION);
+        cx.run_until_parked();
+        assert!(highlight_styles(&mut cx).is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_cancel_stream_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            let chunks =
+                futures::stream::iter([Ok("let b".to_string())]).chain(futures::stream::pending());
+            state.stream_edit(chunks, AgentMode::Ask, window, cx);
+        });
+        cx.run_until_parked();
+
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let b\n}\n"
+        );
+        state.update(&mut cx, |state, cx| {
+            assert!(state.is_streaming());
+            state.cancel_streamed_edit(cx);
+            assert_eq!(state.selection_range(), 16..26);
+        });
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| editor.text(cx)),
+            "fn main() {\n    let a = 1;\n}\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_streamed_rows_highlighted_by_indicator(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let (chunks_tx, chunks_rx) = futures::channel::mpsc::unbounded();
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 0..28, cx));
+        state.update_in(&mut cx, |state, window, cx| {
+            state.stream_edit(chunks_rx, AgentMode::Ask, window, cx);
+        });
+        cx.run_until_parked();
+
+        let (selected_style, edited_style) = cx.update(|_, cx| {
+            let settings = VisualIndicatorSettings::get(cx);
+            (
+                legible_highlight(GutterIndicator::SelectedForAgent.style(settings), cx),
+                legible_highlight(GutterIndicator::BeingEdited.style(settings), cx),
+            )
+        });
+        let highlighted_rows = |cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor
+                    .all_text_highlights(window, cx)
+                    .into_iter()
+                    .flat_map(|(style, ranges)| {
+                        ranges
+                            .into_iter()
+                            .map(move |range| (range.start.row().0..range.end.row().0 + 1, style))
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // Until the model responds, the whole selection is marked as sent to it
+        assert_eq!(highlighted_rows(&mut cx), vec![(0..3, selected_style)]);
+
+        // Then only the rows written so far, as being edited
+        chunks_tx
+            .unbounded_send(Ok("fn run() {".to_string()))
+            .unwrap();
+        cx.run_until_parked();
+        assert_eq!(highlighted_rows(&mut cx), vec![(0..1, edited_style)]);
+    }
+
+    #[gpui::test]
+    async fn test_undoing_quick_edit_shows_label(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        let state = cx.new(|cx| {
+            let mut state = QuickEditState::new(editor.clone(), workspace.clone(), 16..26, cx);
+            state.set_prompt("use a clearer name");
+            state.observe_editor_edits(cx);
+            state
+        });
+        state
+            .update_in(&mut cx, |state, window, cx| {
+                state.apply_edit("let total = 1;".to_string(), window, cx)
+            })
+            .unwrap();
+        let toast_id = NotificationId::unique::<QuickEditUndoneToast>();
+        let has_toast = |cx: &mut VisualTestContext| {
+            workspace.read_with(cx, |workspace, _| {
+                workspace.notification_ids().contains(&toast_id)
+            })
+        };
+        assert!(!has_toast(&mut cx));
 
-/// Open quick edit UI for current selection
-#[derive(Clone, PartialEq, Action)]
-#[action(namespace = agent)]
-pub struct OpenQuickEditForSelection;
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.undo(&editor::actions::Undo, window, cx)
+        });
+        cx.run_until_parked();
+        assert!(has_toast(&mut cx));
+    }
 
-pub struct QuickEditState {
-    editor: WeakEntity<Editor>,
-    workspace: WeakEntity<Workspace>,
-    /// The range of text to edit
-    selection_range: Range<usize>,
-    /// Current edit prompt
-    prompt: String,
-    /// Subscription to editor changes
-    _subscriptions: Vec<Subscription>,
-}
+    #[gpui::test]
+    async fn test_selection_info_refresh_is_debounced(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
 
-impl QuickEditState {
-    pub fn new(
-        editor: Entity<Editor>,
-        workspace: Entity<Workspace>,
-        selection_range: Range<usize>,
-    ) -> Self {
-        Self {
-            editor: editor.downgrade(),
-            workspace: workspace.downgrade(),
-            selection_range,
-            prompt: String::new(),
-            _subscriptions: vec![],
+        let state = cx.new(|cx| {
+            let mut state = QuickEditState::new(editor.clone(), workspace, 0..26, cx);
+            state.observe_editor_edits(cx);
+            state
+        });
+        let refresh_count = Rc::new(Cell::new(0));
+        cx.update(|_, cx| {
+            let refresh_count = refresh_count.clone();
+            cx.observe(&state, move |_, _| {
+                refresh_count.set(refresh_count.get() + 1)
+            })
+            .detach();
+        });
+
+        for _ in 0..3 {
+            editor.update_in(&mut cx, |editor, window, cx| editor.insert("x", window, cx));
+            cx.executor().advance_clock(Duration::from_millis(50));
+            cx.run_until_parked();
         }
+        assert_eq!(refresh_count.get(), 0);
+
+        cx.executor().advance_clock(SELECTION_INFO_DEBOUNCE);
+        cx.run_until_parked();
+        assert_eq!(refresh_count.get(), 1);
+        state.update(&mut cx, |state, _| {
+            assert_eq!(state.selection_info().map(|info| info.line_count), Some(2));
+        });
     }
 
-    /// Get the selected text from the editor
-    pub fn selected_text(&self, window: &Window, cx: &App) -> Option<String> {
-        let editor = self.editor.upgrade()?;
-        let snapshot = editor.read(cx).snapshot(window, cx);
-        let buffer = snapshot.buffer_snapshot();
+    #[gpui::test]
+    async fn test_context_info_is_cached_until_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
 
-        let start_offset = MultiBufferOffset(self.selection_range.start);
-        let end_offset = MultiBufferOffset(self.selection_range.end);
+        let state = cx.new(|cx| {
+            let mut state = QuickEditState::new(editor.clone(), workspace, 16..26, cx);
+            state.observe_editor_edits(cx);
+            state
+        });
 
-        if start_offset >= buffer.len() || end_offset > buffer.len() || start_offset > end_offset
-        {
-            return None;
-        }
+        state.update(&mut cx, |state, cx| {
+            let first = state.get_context_info(cx);
+            assert_eq!(first.len(), 1);
+            let edit_count = state
+                .cached_context_info
+                .as_ref()
+                .map(|cached| cached.edit_count);
+            assert!(edit_count.is_some());
+            assert_eq!(state.get_context_info(cx), first);
+            assert_eq!(
+                state
+                    .cached_context_info
+                    .as_ref()
+                    .map(|cached| cached.edit_count),
+                edit_count
+            );
+        });
+
+        // Edits that keep the selection on the same lines keep the cache
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.insert("// ", window, cx)
+        });
+        state.update(&mut cx, |state, _| {
+            assert!(state.cached_context_info.is_some());
+        });
 
-        Some(buffer.text_for_range(start_offset..end_offset).collect())
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.insert("\n", window, cx)
+        });
+        state.update(&mut cx, |state, cx| {
+            assert!(state.cached_context_info.is_none());
+            assert_eq!(state.get_context_info(cx).len(), 1);
+            assert!(state.cached_context_info.is_some());
+        });
     }
 
-    /// Get file context information for the selection
-    pub fn get_context_info(&self, window: &Window, cx: &App) -> Option<ContextInfo> {
-        let editor = self.editor.upgrade()?;
-        let snapshot = editor.read(cx).snapshot(window, cx);
-        let buffer = snapshot.buffer_snapshot();
+    #[gpui::test]
+    async fn test_build_agent_prompt(cx: &mut TestAppContext) {
+        let (_workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
 
-        // Get file path
-        let file_path = buffer.file_at(MultiBufferOffset(self.selection_range.start)).and_then(|file| {
-            file.full_path(cx)
-                .ok()
-                .map(|p| p.to_string_lossy().to_string())
+        let prompt = editor.update(&mut cx, |editor, cx| {
+            build_agent_prompt(editor, 16..26, "Rename the variable.\n", cx)
         });
+        let prompt = prompt.unwrap();
+        assert!(prompt.starts_with("Rename the variable.\n\n```\n"));
+        assert!(prompt.contains("Line 2:4-14"));
+        assert!(prompt.contains("let a = 1;"));
 
-        // Get line range for the selection
-        let start_offset = MultiBufferOffset(self.selection_range.start);
-        let end_offset = MultiBufferOffset(self.selection_range.end);
+        let out_of_bounds = editor.update(&mut cx, |editor, cx| {
+            build_agent_prompt(editor, 16..500, "", cx)
+        });
+        assert_eq!(out_of_bounds, None);
+    }
 
-        let start_point = buffer.offset_to_point(start_offset);
-        let end_point = buffer.offset_to_point(end_offset);
+    #[gpui::test]
+    async fn test_retry_expands_context(cx: &mut TestAppContext) {
+        let text = (0..30)
+            .map(|row| format!("line {row:02}\n"))
+            .collect::<String>();
+        let (workspace, editor, mut cx) =
+            build_test_editor_with_files(&text, serde_json::json!({ "notes.md": "Context\n" }), cx)
+                .await;
+        cx.run_until_parked();
 
-        Some(ContextInfo {
-            file_path,
-            start_line: start_point.row,
-            end_line: end_point.row,
-            start_column: start_point.column,
-            end_column: end_point.column,
-        })
+        let state = cx.update(|_, cx| {
+            cx.new(|cx| {
+                let mut state = QuickEditState::new(editor.clone(), workspace, 120..127, cx);
+                state.set_prompt("Rename the line");
+                state.attach_file(path!("/test/notes.md"), cx).unwrap();
+                state
+            })
+        });
+
+        let first_retry = state.update(&mut cx, |state, cx| state.expand_context_for_retry(2, cx));
+        assert_eq!(first_retry, Some(40..207));
+        let second_retry = state.update(&mut cx, |state, cx| state.expand_context_for_retry(2, cx));
+        assert_eq!(second_retry, Some(0..240));
+        let capped_retry = state.update(&mut cx, |state, cx| state.expand_context_for_retry(2, cx));
+        assert_eq!(capped_retry, None);
+
+        state.update(&mut cx, |state, _| {
+            assert_eq!(state.retry_count(), 2);
+            assert_eq!(state.selection_range(), 0..240);
+            assert_eq!(state.prompt, "Rename the line");
+            assert_eq!(
+                state.attached_files(),
+                [PathBuf::from(path!("/test/notes.md"))]
+            );
+        });
     }
-}
 
-/// Information about the context of a selection
-#[derive(Clone, Debug)]
-pub struct ContextInfo {
-    pub file_path: Option<String>,
-    pub start_line: u32,
-    pub end_line: u32,
-    pub start_column: u32,
-    pub end_column: u32,
-}
+    #[gpui::test]
+    async fn test_context_within_budget(cx: &mut TestAppContext) {
+        let text = (0..30)
+            .map(|row| format!("line {row:02}\n"))
+            .collect::<String>();
+        let (workspace, editor, mut cx) = build_test_editor(&text, cx).await;
+        let state = cx.new(|cx| QuickEditState::new(editor.clone(), workspace, 120..127, cx));
+        let within_budget = |max_tokens: u32, cx: &mut VisualTestContext| {
+            state
+                .read_with(cx, |state, cx| {
+                    state.context_within_budget(10, max_tokens, cx)
+                })
+                .unwrap()
+        };
 
-impl ContextInfo {
-    /// Format context info as a human-readable string
-    pub fn format(&self) -> String {
-        let mut parts = Vec::new();
+        // An ample budget gets every requested line of context
+        let context = within_budget(1000, &mut cx);
+        assert_eq!(context.context_lines, 10);
+        assert!(!context.truncated);
+        assert_eq!(context.context_info.start_line, 5);
+        assert_eq!(context.context_info.end_line, 25);
+        assert_eq!(context.text, text[40..207]);
 
-        if let Some(path) = &self.file_path {
-            parts.push(format!("File: {}", path));
-        }
+        // Context is trimmed from both sides until it fits
+        let context = within_budget(10, &mut cx);
+        assert_eq!(context.context_lines, 2);
+        assert_eq!(context.text, "line 13\nline 14\nline 15\nline 16\nline 17");
 
-        if self.start_line == self.end_line {
-            parts.push(format!("Line {}", self.start_line + 1));
-        } else {
-            parts.push(format!("Lines {}-{}", self.start_line + 1, self.end_line + 1));
-        }
+        // A tight budget leaves only the selection
+        let context = within_budget(2, &mut cx);
+        assert_eq!(context.context_lines, 0);
+        assert!(!context.truncated);
+        assert_eq!(context.text, "line 15");
 
-        parts.join(" • ")
+        // The selection is kept even when it alone is over budget
+        let context = within_budget(0, &mut cx);
+        assert_eq!(context.context_lines, 0);
+        assert!(context.truncated);
+        assert_eq!(context.text, "line 15");
     }
 
-    /// Format selection with code block and context for agent
-    pub fn format_for_agent(&self, code: &str) -> String {
-        let mut result = String::new();
+    #[gpui::test]
+    async fn test_attached_files_in_prompt(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) = build_test_editor_with_files(
+            "impl Shape for Square {}\n",
+            serde_json::json!({ "shape.rs": "pub trait Shape {\n    fn area(&self) -> f32;\n}\n" }),
+            cx,
+        )
+        .await;
+        cx.run_until_parked();
+
+        let mut state =
+            cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace, 0..24, cx));
+        cx.update(|_, cx| {
+            assert!(state.attach_file(path!("/test/missing.rs"), cx).is_err());
+            state.attach_file(path!("/test/shape.rs"), cx).unwrap();
+        });
+        assert_eq!(
+            state.attached_files(),
+            [PathBuf::from(path!("/test/shape.rs"))]
+        );
 
-        // Add file/line context as a comment in the code block
-        let context = self.format();
-        result.push_str(&format!("```\n{}\n```\n\n", context));
+        let attached_files = cx
+            .update(|_, cx| state.load_attached_files(cx))
+            .await
+            .unwrap();
+        let prompt = cx
+            .update(|_, cx| {
+                state.build_prompt("Implement the trait.", &attached_files, u32::MAX, cx)
+            })
+            .unwrap();
+        let selection_ix = prompt.find("impl Shape for Square {}").unwrap();
+        let attached_ix = prompt.find("fn area(&self) -> f32;").unwrap();
+        assert!(selection_ix < attached_ix);
+        assert!(prompt.contains("Lines 1-3"));
 
-        // Add the code block with syntax hint
-        let language = self.infer_language();
-        result.push_str(&format!("```{}\n{}\n```", language, code));
+        // Files that don't fit the budget are left out
+        let prompt = cx
+            .update(|_, cx| state.build_prompt("Implement the trait.", &attached_files, 25, cx))
+            .unwrap();
+        assert!(!prompt.contains("fn area"));
 
-        result
+        assert!(state.detach_file(Path::new(path!("/test/shape.rs"))));
+        assert!(state.attached_files().is_empty());
     }
 
-    /// Infer programming language from file path
-    fn infer_language(&self) -> String {
-        if let Some(path) = &self.file_path {
-            if path.ends_with(".rs") {
-                "rust".to_string()
-            } else if path.ends_with(".ts") || path.ends_with(".tsx") {
-                "typescript".to_string()
-            } else if path.ends_with(".js") || path.ends_with(".jsx") {
-                "javascript".to_string()
-            } else if path.ends_with(".py") {
-                "python".to_string()
-            } else if path.ends_with(".go") {
-                "go".to_string()
-            } else if path.ends_with(".c") || path.ends_with(".h") {
-                "c".to_string()
-            } else if path.ends_with(".cpp") || path.ends_with(".cc") {
-                "cpp".to_string()
-            } else if path.ends_with(".java") {
-                "java".to_string()
-            } else if path.ends_with(".sql") {
-                "sql".to_string()
-            } else {
-                String::new()
-            }
-        } else {
-            String::new()
+    #[gpui::test]
+    async fn test_surrounding_code_in_prompt(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        let state = cx.update(|_, cx| QuickEditState::new(editor.clone(), workspace, 16..26, cx));
+        let selection_prompt = editor.update(&mut cx, |editor, cx| {
+            build_agent_prompt(editor, 16..26, "Rename a.", cx).unwrap()
+        });
+
+        cx.update(|_, cx| {
+            let prompt = state.build_prompt("Rename a.", &[], u32::MAX, cx).unwrap();
+            let surrounding_code = state.get_context_info_within_budget(u32::MAX, cx).unwrap();
+            assert_eq!(
+                prompt,
+                format!(
+                    "{selection_prompt}\n\n{}",
+                    surrounding_code.format_for_agent()
+                )
+            );
+            assert!(prompt.contains("fn main() {"));
+
+            // Without room for the surrounding code, only the selection is sent
+            let token_budget = TokenInfo::estimate_tokens_from_text(&selection_prompt);
+            assert_eq!(
+                state.build_prompt("Rename a.", &[], token_budget, cx),
+                Some(selection_prompt.clone())
+            );
+        });
+    }
+
+    #[test]
+    fn test_quick_edit_error_display() {
+        let errors = [
+            (
+                QuickEditError::NoEditor,
+                "There's no open editor to quick edit.",
+            ),
+            (
+                QuickEditError::EmptySelection,
+                "There's no code to quick edit.",
+            ),
+            (
+                QuickEditError::InvalidRange,
+                "The code changed before the quick edit could be applied.",
+            ),
+            (
+                QuickEditError::BufferReadOnly,
+                "This buffer is read-only, so a quick edit can't be applied to it.",
+            ),
+            (
+                QuickEditError::ModelError("rate limited".to_string()),
+                "The quick edit failed: rate limited",
+            ),
+            (QuickEditError::Cancelled, "The quick edit was cancelled."),
+        ];
+        for (error, message) in errors {
+            assert_eq!(error.to_string(), message);
+            // Errors convert to `anyhow::Error` with `?`, keeping the variant
+            let converted = anyhow::Error::from(error.clone());
+            assert_eq!(converted.to_string(), message);
+            assert_eq!(converted.downcast_ref::<QuickEditError>(), Some(&error));
         }
     }
-}
 
-/// Handle quick edit action from editor
-pub(crate) fn handle_quick_edit(
-    _workspace: &mut Workspace,
-    _action: &QuickEdit,
-    _window: &mut Window,
-    _cx: &mut Context<Workspace>,
-) {
-    // TODO: Implement quick edit with inline assistant
-    // This will be connected to the InlineAssistant once fully integrated
-}
+    #[gpui::test]
+    async fn test_read_only_buffers_block_quick_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        assert!(editor.update(&mut cx, |editor, cx| {
+            ensure_quick_editable(editor, cx).is_ok()
+        }));
 
-/// Handle sending selection to agent panel
-pub(crate) fn handle_send_selection_to_agent(
-    _workspace: &mut Workspace,
-    _action: &SendSelectionToAgent,
-    _window: &mut Window,
-    _cx: &mut Context<Workspace>,
-) {
-    // TODO: Add selection to agent panel message editor
-    // This would require integrating with the agent panel directly
-}
+        editor.update(&mut cx, |editor, _| editor.set_read_only(true));
+        assert_eq!(
+            editor.update(&mut cx, |editor, cx| ensure_quick_editable(editor, cx)),
+            Err(QuickEditError::BufferReadOnly)
+        );
+        let state = workspace.update_in(&mut cx, |workspace, window, cx| {
+            open_quick_edit_input(workspace, editor.clone(), 16..26, None, window, cx)
+        });
+        assert!(state.is_none());
+        assert!(
+            cx.update(|_, cx| QuickEditState::for_editor(&editor, cx))
+                .is_none()
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[gpui::test]
+    async fn test_open_quick_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        // Quick edits need the editor's workspace, which it only knows once added to it
+        assert_eq!(
+            cx.update(|window, cx| open_quick_edit(&editor, 16..26, None, false, window, cx))
+                .err(),
+            Some(QuickEditError::NoEditor)
+        );
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            workspace.add_item_to_active_pane(Box::new(editor.clone()), None, true, window, cx);
+        });
+
+        let state = cx
+            .update(|window, cx| {
+                open_quick_edit(
+                    &editor,
+                    16..26,
+                    Some("Rename a to answer".to_string()),
+                    true,
+                    window,
+                    cx,
+                )
+            })
+            .unwrap();
+        cx.update(|_, cx| {
+            let state = state.read(cx);
+            assert_eq!(state.selection_range(), 16..26);
+            assert_eq!(state.prompt(), "Rename a to answer");
+            assert_eq!(state.original_text(), Some("let a = 1;"));
+            assert_eq!(
+                QuickEditState::for_editor(&editor, cx).map(|session| session.entity_id()),
+                Some(state.entity_id())
+            );
+        });
+        assert_eq!(newest_selection(&editor, &mut cx), 16..26);
+    }
+
+    #[gpui::test]
+    async fn test_untitled_buffers_format_as_untitled(cx: &mut TestAppContext) {
+        // Buffers built without a file stand in for untitled ones
+        let (_workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+
+        editor.update(&mut cx, |editor, cx| {
+            assert!(ensure_quick_editable(editor, cx).is_ok());
+            let (_, context) = selection_text_and_context(editor, 16..26, cx).unwrap();
+            assert_eq!(context.file_path, None);
+            assert_eq!(context.relative_path, None);
+            assert_eq!(context.language, None);
+            assert_eq!(context.infer_language(), "");
+            let prompt = build_agent_prompt(editor, 16..26, "Rename a.", cx).unwrap();
+            assert!(prompt.contains("File: Untitled buffer • Line 2:4-14"));
+        });
+
+        // The buffer's language stands in for the file extension
+        editor.update(&mut cx, |editor, cx| {
+            let buffer = editor.buffer().read(cx).as_singleton().unwrap();
+            buffer.update(cx, |buffer, cx| {
+                buffer.set_language(Some(language::rust_lang()), cx)
+            });
+        });
+        cx.run_until_parked();
+        editor.update(&mut cx, |editor, cx| {
+            let (_, context) = selection_text_and_context(editor, 16..26, cx).unwrap();
+            assert_eq!(context.language.as_deref(), Some("Rust"));
+            assert_eq!(context.infer_language(), "rust");
+            let prompt = build_agent_prompt(editor, 16..26, "Rename a.", cx).unwrap();
+            assert!(prompt.contains("```\nFile: Untitled buffer • Line 2:4-14\n```"));
+            assert!(prompt.contains("```rust\nlet a = 1;\n```"));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_prepare_quick_edit(cx: &mut TestAppContext) {
+        let (_workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                selections.select_ranges([MultiBufferOffset(16)..MultiBufferOffset(26)]);
+            });
+        });
+
+        let prepared = editor.update(&mut cx, |editor, cx| {
+            prepare_quick_edit(
+                editor,
+                Some("Rename it".to_string()),
+                QuickEditScope::Selection,
+                cx,
+            )
+        });
+        assert_eq!(
+            prepared,
+            Some(PreparedAction {
+                range: 16..26,
+                prompt: Some("Rename it".to_string()),
+            })
+        );
+        // Preparing has no side effects, so no quick edit session is started
+        assert!(cx.update(|_, cx| QuickEditState::for_editor(&editor, cx).is_none()));
+    }
+
+    #[gpui::test]
+    async fn test_quick_edit_scope_ranges(cx: &mut TestAppContext) {
+        let (_workspace, editor, mut cx) =
+            build_test_editor("struct A;\n\nfn main() {\n    let a = 1;\n}\n", cx).await;
+        editor.update(&mut cx, |editor, cx| {
+            let buffer = editor.buffer().read(cx).as_singleton().unwrap();
+            buffer.update(cx, |buffer, cx| {
+                buffer.set_language(Some(language::rust_lang()), cx)
+            });
+        });
+        cx.run_until_parked();
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                selections.select_ranges([MultiBufferOffset(27)..MultiBufferOffset(37)]);
+            });
+        });
+
+        let resolve_range = |scope: QuickEditScope, cx: &mut VisualTestContext| {
+            editor.update(cx, |editor, cx| scope.resolve_range(editor, cx))
+        };
+        assert_eq!(
+            resolve_range(QuickEditScope::Selection, &mut cx),
+            Some(27..37)
+        );
+        assert_eq!(
+            resolve_range(QuickEditScope::EnclosingSymbol, &mut cx),
+            Some(11..39)
+        );
+        assert_eq!(
+            resolve_range(QuickEditScope::WholeFile, &mut cx),
+            Some(0..40)
+        );
+
+        // Outside of any symbol, the enclosing symbol scope targets the selection
+        editor.update_in(&mut cx, |editor, window, cx| {
+            editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                selections.select_ranges([MultiBufferOffset(10)..MultiBufferOffset(11)]);
+            });
+        });
+        assert_eq!(
+            resolve_range(QuickEditScope::EnclosingSymbol, &mut cx),
+            Some(10..11)
+        );
+    }
+
+    #[gpui::test]
+    async fn test_exceeds_budget(cx: &mut TestAppContext) {
+        let text = "let value = 1;\n".repeat(400);
+        let (workspace, editor, mut cx) = build_test_editor(&text, cx).await;
+
+        let small = cx.new(|cx| {
+            let mut state = QuickEditState::new(editor.clone(), workspace.clone(), 0..14, cx);
+            state.refresh_selection_info(cx);
+            state
+        });
+        small.read_with(&cx, |state, _| {
+            assert!(!state.exceeds_budget(8192, 4096));
+            // The reserve alone may use up the window
+            assert!(state.exceeds_budget(4096, 4096));
+        });
+
+        let large = cx.new(|cx| {
+            let mut state = QuickEditState::new(editor.clone(), workspace, 0..text.len(), cx);
+            state.refresh_selection_info(cx);
+            state
+        });
+        large.read_with(&cx, |state, _| {
+            let tokens = state.selection_info().map_or(0, |info| info.token_estimate);
+            assert!(tokens > 1000);
+            assert!(state.exceeds_budget(tokens + 4095, 4096));
+            assert!(!state.exceeds_budget(tokens + 4096, 4096));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_context_info_for_multi_excerpt_selection(cx: &mut TestAppContext) {
+        cx.update(init_test);
+
+        let fs = FakeFs::new(cx.executor());
+        fs.insert_tree(
+            path!("/test"),
+            serde_json::json!({
+                "a.rs": "fn a() {}\nfn b() {}\nfn c() {}\n",
+                "b.rs": "fn d() {}\nfn e() {}\n",
+            }),
+        )
+        .await;
+        let project = Project::test(fs, [path!("/test").as_ref()], cx).await;
+        let buffer_a = project
+            .update(cx, |project, cx| {
+                project.open_local_buffer(path!("/test/a.rs"), cx)
+            })
+            .await
+            .unwrap();
+        let buffer_b = project
+            .update(cx, |project, cx| {
+                project.open_local_buffer(path!("/test/b.rs"), cx)
+            })
+            .await
+            .unwrap();
+
+        let multi_buffer = cx.new(|cx| {
+            let mut multi_buffer = MultiBuffer::new(Capability::ReadWrite);
+            multi_buffer.push_excerpts(
+                buffer_a,
+                [ExcerptRange::new(Point::new(1, 0)..Point::new(2, 9))],
+                cx,
+            );
+            multi_buffer.push_excerpts(
+                buffer_b,
+                [ExcerptRange::new(Point::new(0, 0)..Point::new(1, 9))],
+                cx,
+            );
+            multi_buffer
+        });
+        let snapshot = multi_buffer.read_with(cx, |multi_buffer, cx| multi_buffer.snapshot(cx));
+
+        // Select from the middle of the first excerpt into the second one.
+        let start = snapshot.point_to_offset(Point::new(0, 3)).0;
+        let end = snapshot.point_to_offset(Point::new(3, 2)).0;
+        let contexts = cx.update(|cx| ContextInfo::for_excerpts(&snapshot, start..end, cx));
+
+        assert_eq!(contexts.len(), 2);
+        assert_eq!(contexts[0].relative_path.as_deref(), Some("a.rs"));
+        assert_eq!((contexts[0].start_line, contexts[0].start_column), (1, 3));
+        assert_eq!((contexts[0].end_line, contexts[0].end_column), (2, 9));
+        assert_eq!(contexts[1].relative_path.as_deref(), Some("b.rs"));
+        assert_eq!((contexts[1].start_line, contexts[1].start_column), (0, 0));
+        assert_eq!((contexts[1].end_line, contexts[1].end_column), (1, 2));
+    }
+
+    #[gpui::test]
+    async fn test_open_quick_edit_requires_selection(cx: &mut TestAppContext) {
+        cx.update(init);
+        let (workspace, editor, mut cx) = build_test_editor("let a = 1;\nlet b = 2;\n", cx).await;
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            workspace.add_item_to_active_pane(Box::new(editor.clone()), None, true, window, cx);
+        });
+
+        let is_available = |range: Range<usize>, cx: &mut VisualTestContext| {
+            editor.update_in(cx, |editor, window, cx| {
+                editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                    selections.select_ranges([
+                        MultiBufferOffset(range.start)..MultiBufferOffset(range.end)
+                    ]);
+                });
+            });
+            cx.run_until_parked();
+            cx.update(|window, cx| window.is_action_available(&OpenQuickEditForSelection, cx))
+        };
+
+        assert!(!is_available(4..4, &mut cx));
+        assert!(is_available(4..9, &mut cx));
+    }
+
+    #[test]
+    fn test_context_info_serde_round_trip() {
+        let context = ContextInfo {
+            file_path: Some("src/main.rs".to_string()),
+            relative_path: None,
+            start_line: 5,
+            end_line: 10,
+            start_column: 4,
+            end_column: 20,
+            language: None,
+        };
+
+        let json = serde_json::to_value(&context).unwrap();
+        assert_eq!(json["file_path"], "src/main.rs");
+        assert_eq!(json["start_line"], 5);
+
+        let deserialized: ContextInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, context);
+    }
 
     #[test]
     fn test_context_info_format() {
         let context = ContextInfo {
             file_path: Some("src/main.rs".to_string()),
+            relative_path: None,
             start_line: 5,
             end_line: 10,
             start_column: 0,
             end_column: 20,
+            language: None,
         };
 
         let formatted = context.format();
         assert!(formatted.contains("src/main.rs"));
-        assert!(formatted.contains("Lines 6-11")); // 1-indexed for display
+        assert!(formatted.contains("Lines 6:0-11:20")); // 1-indexed for display
+    }
+
+    #[test]
+    fn test_context_info_format_full_lines() {
+        let context = ContextInfo {
+            file_path: None,
+            relative_path: None,
+            start_line: 5,
+            end_line: 10,
+            start_column: 0,
+            end_column: 0,
+            language: None,
+        };
+        assert_eq!(context.format(), "File: Untitled buffer • Lines 6-11");
+
+        let context = ContextInfo {
+            end_line: 5,
+            ..context
+        };
+        assert_eq!(context.format(), "File: Untitled buffer • Line 6");
+    }
+
+    #[test]
+    fn test_context_info_format_prefers_relative_path() {
+        let mut context = ContextInfo {
+            file_path: Some("/home/user/project/src/main.rs".to_string()),
+            relative_path: Some("src/main.rs".to_string()),
+            start_line: 0,
+            end_line: 3,
+            start_column: 0,
+            end_column: 0,
+            language: None,
+        };
+        assert!(context.format().starts_with("File: src/main.rs •"));
+
+        context.relative_path = None;
+        assert!(
+            context
+                .format()
+                .starts_with("File: /home/user/project/src/main.rs •")
+        );
+    }
+
+    #[test]
+    fn test_context_info_is_same_region() {
+        let context = ContextInfo::builder(4, 9)
+            .file_path("project/src/main.rs")
+            .relative_path("src/main.rs")
+            .columns(2, 10)
+            .build();
+
+        let other_columns = ContextInfo::builder(4, 9)
+            .file_path("project/src/main.rs")
+            .relative_path("src/main.rs")
+            .columns(0, 3)
+            .build();
+        assert!(context.is_same_region(&other_columns));
+        assert!(other_columns.is_same_region(&context));
+
+        let other_lines = ContextInfo::builder(4, 12)
+            .file_path("project/src/main.rs")
+            .relative_path("src/main.rs")
+            .columns(2, 10)
+            .build();
+        assert!(!context.is_same_region(&other_lines));
+
+        let other_file = ContextInfo::builder(4, 9)
+            .file_path("project/src/lib.rs")
+            .relative_path("src/lib.rs")
+            .columns(2, 10)
+            .build();
+        assert!(!context.is_same_region(&other_file));
     }
 
     #[test]
     fn test_context_info_single_line() {
         let context = ContextInfo {
             file_path: Some("utils.rs".to_string()),
+            relative_path: None,
             start_line: 42,
             end_line: 42,
             start_column: 10,
             end_column: 30,
+            language: None,
         };
 
         let formatted = context.format();
-        assert!(formatted.contains("Line 43")); // 1-indexed
+        assert!(formatted.contains("Line 43:10-30")); // 1-indexed
         assert!(formatted.contains("utils.rs"));
     }
 
@@ -235,10 +4044,12 @@ mod tests {
     fn test_format_for_agent_with_rust() {
         let context = ContextInfo {
             file_path: Some("src/lib.rs".to_string()),
+            relative_path: None,
             start_line: 0,
             end_line: 5,
             start_column: 0,
             end_column: 0,
+            language: None,
         };
 
         let code = "fn hello() {\n    println!(\"Hello\");\n}";
@@ -249,6 +4060,197 @@ mod tests {
         assert!(formatted.contains(code));
     }
 
+    #[test]
+    fn test_context_info_builder() {
+        let context = ContextInfo::builder(4, 6)
+            .relative_path("src/lib.rs")
+            .build();
+        assert_eq!(
+            context,
+            ContextInfo {
+                file_path: None,
+                relative_path: Some("src/lib.rs".to_string()),
+                start_line: 4,
+                end_line: 6,
+                start_column: 0,
+                end_column: 0,
+                language: None,
+            }
+        );
+        assert_eq!(context.format(), "File: src/lib.rs • Lines 5-7");
+
+        let context = ContextInfo::builder(2, 2)
+            .file_path("project/src/main.rs")
+            .columns(4, 9)
+            .build();
+        assert_eq!(context.file_path.as_deref(), Some("project/src/main.rs"));
+        assert_eq!(context.relative_path, None);
+        assert_eq!((context.start_column, context.end_column), (4, 9));
+    }
+
+    #[test]
+    fn test_format_for_agent_styles() {
+        let context = ContextInfo::builder(5, 10).file_path("src/main.rs").build();
+        let code = "fn main() {}";
+
+        let fenced = context.format_for_agent_with_style(code, FormatStyle::FencedContext);
+        assert_eq!(fenced, context.format_for_agent(code));
+        assert_eq!(
+            fenced,
+            "```\nFile: src/main.rs • Lines 6-11\n```\n\n```rust\nfn main() {}\n```"
+        );
+
+        let header = context.format_for_agent_with_style(code, FormatStyle::MarkdownHeader);
+        assert_eq!(
+            header,
+            "**File:** src/main.rs • Lines 6-11\n\n```rust\nfn main() {}\n```"
+        );
+        assert_eq!(header.matches("```").count(), 2);
+    }
+
+    #[test]
+    fn test_format_for_agent_comment_header() {
+        let cases = [
+            (
+                "src/main.rs",
+                "fn main() {}",
+                "```rust\n// File: src/main.rs • Lines 6-11\nfn main() {}\n```",
+            ),
+            (
+                "app/main.py",
+                "def main(): pass",
+                "```python\n# File: app/main.py • Lines 6-11\ndef main(): pass\n```",
+            ),
+            (
+                "site/index.html",
+                "<p>Hi</p>",
+                "```html\n<!-- File: site/index.html • Lines 6-11 -->\n<p>Hi</p>\n```",
+            ),
+        ];
+        for (path, code, expected) in cases {
+            let context = ContextInfo::builder(5, 10).file_path(path).build();
+            assert_eq!(
+                context.format_for_agent_with_style(code, FormatStyle::CommentHeader),
+                expected
+            );
+        }
+
+        // Languages without a known comment syntax keep the context in its own fence
+        let context = ContextInfo::builder(5, 10).file_path("notes.txt").build();
+        assert_eq!(
+            context.format_for_agent_with_style("Hello", FormatStyle::CommentHeader),
+            context.format_for_agent_with_style("Hello", FormatStyle::FencedContext)
+        );
+    }
+
+    #[test]
+    fn test_format_for_agent_max_lines() {
+        let context = ContextInfo::builder(0, 999)
+            .file_path("src/main.rs")
+            .build();
+        let code = (1..=1000)
+            .map(|line| format!("let line_{line} = {line};"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let formatted =
+            context.format_for_agent_with_options(&code, FormatStyle::default(), Some(20));
+        assert!(formatted.starts_with("```\nFile: src/main.rs • Lines 1-1000\n```"));
+        let (_, code_block) = formatted.split_once("```rust\n").unwrap();
+        let lines = code_block
+            .strip_suffix("\n```")
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 21);
+        assert_eq!(lines[0], "let line_1 = 1;");
+        assert_eq!(lines[9], "let line_10 = 10;");
+        assert_eq!(lines[10], "… (980 lines omitted) …");
+        assert_eq!(lines[11], "let line_991 = 991;");
+        assert_eq!(lines[20], "let line_1000 = 1000;");
+
+        assert_eq!(
+            context.format_for_agent_with_options(&code, FormatStyle::default(), None),
+            context.format_for_agent(&code)
+        );
+        assert_eq!(
+            context.format_for_agent_with_options("let a = 1;", FormatStyle::default(), Some(20)),
+            context.format_for_agent("let a = 1;")
+        );
+    }
+
+    #[test]
+    fn test_local_reference() {
+        let context = ContextInfo::builder(5, 10)
+            .file_path("project/src/main.rs")
+            .relative_path("src/main.rs")
+            .build();
+        assert_eq!(
+            context.local_reference().as_deref(),
+            Some("src/main.rs#L6-L11")
+        );
+
+        let single_line = ContextInfo::builder(5, 5)
+            .file_path("project/src/main.rs")
+            .columns(4, 10)
+            .build();
+        assert_eq!(
+            single_line.local_reference().as_deref(),
+            Some("project/src/main.rs#L6")
+        );
+
+        assert_eq!(ContextInfo::builder(0, 3).build().local_reference(), None);
+    }
+
+    #[test]
+    fn test_context_payload() {
+        let context = ContextInfo::builder(5, 10)
+            .file_path("project/src/main.rs")
+            .relative_path("src/main.rs")
+            .build();
+        let payload = context.to_payload("fn main() {}");
+
+        let mut keys = payload
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, ["code", "end_line", "file", "language", "start_line"]);
+        assert_eq!(payload["file"], "src/main.rs");
+        assert_eq!(payload["start_line"], 6);
+        assert_eq!(payload["end_line"], 11);
+        assert_eq!(payload["language"], "rust");
+        assert_eq!(payload["code"], "fn main() {}");
+
+        let payload = ContextInfo::builder(0, 0).build().to_payload("");
+        assert!(payload["file"].is_null());
+        assert!(payload["language"].is_null());
+    }
+
+    #[test]
+    fn test_format_for_agent_escapes_fences() {
+        let context = ContextInfo {
+            file_path: Some("src/we`ird``.rs".to_string()),
+            relative_path: None,
+            start_line: 0,
+            end_line: 0,
+            start_column: 0,
+            end_column: 0,
+            language: None,
+        };
+
+        let formatted = context.format_for_agent("let a = 1;");
+        let (context_block, code_block) = formatted.split_once("\n\n").unwrap();
+        assert_eq!(context_block, "```\nFile: src/we'ird''.rs • Line 1\n```");
+        assert_eq!(code_block, "```rust\nlet a = 1;\n```");
+
+        let code = "/// ```\n/// assert!(true);\n/// ```\nfn documented() {}";
+        let formatted = context.format_for_agent(code);
+        assert!(formatted.ends_with(&format!("````rust\n{code}\n````")));
+    }
+
     #[test]
     fn test_infer_language() {
         let contexts = vec![
@@ -261,10 +4263,12 @@ mod tests {
         for (path, expected_lang) in contexts {
             let context = ContextInfo {
                 file_path: Some(path.to_string()),
+                relative_path: None,
                 start_line: 0,
                 end_line: 0,
                 start_column: 0,
                 end_column: 0,
+                language: None,
             };
             assert_eq!(context.infer_language(), expected_lang);
         }
@@ -0,0 +1,698 @@
+/// Quick Edit Input
+///
+/// This module provides the cmd-k prompt box opened by `OpenQuickEditForSelection`.
+/// It summarizes the selection and its location above a single-line prompt, and
+/// sends the prompt through the quick edit session it was opened for. The full prompt
+/// sent to the agent can be shown and edited before submitting.
+
+use agent_settings::AgentSettings;
+use anyhow::Result;
+use collections::{HashMap, VecDeque};
+use db::kvp::KEY_VALUE_STORE;
+use editor::{
+    Editor,
+    actions::{MoveDown, MoveUp},
+};
+use gpui::{
+    App, Context, DismissEvent, Entity, EventEmitter, FocusHandle, Focusable, Global, Render,
+    SharedString, WeakEntity, Window,
+};
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+use ui::prelude::*;
+use util::ResultExt as _;
+use workspace::{ModalView, Workspace, WorkspaceId};
+
+use crate::ToggleQuickEditFullPrompt;
+use crate::quick_edit::{
+    QuickEditState, active_agent_mode, report_quick_edit_submitted, selection_text_and_context,
+    show_quick_edit_error,
+};
+use crate::visual_indicators::SelectionInfo;
+
+/// Longest prompt kept in the history, in characters, so that huge pasted prompts
+/// aren't stored
+const PROMPT_HISTORY_MAX_CHARS: usize = 2000;
+
+/// Key prefix under which a workspace's prompt history is persisted, followed by the workspace id
+const PROMPT_HISTORY_KEY: &str = "quick_edit_prompt_history";
+
+/// Prompts submitted from quick edit inputs, oldest first.
+///
+/// Persisted per workspace as JSON, e.g. `{"prompts":["add logging"]}`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct PromptHistory {
+    prompts: VecDeque<String>,
+}
+
+impl PromptHistory {
+    /// Add `prompt` as the newest entry, moving it there if it was already in the
+    /// history, and keep at most `max_entries`
+    fn push(&mut self, mut prompt: String, max_entries: usize) {
+        truncate_prompt(&mut prompt);
+        self.prompts
+            .retain(|previous_prompt| *previous_prompt != prompt);
+        self.prompts.push_back(prompt);
+        self.trim(max_entries);
+    }
+
+    /// Drop the oldest entries beyond `max_entries`
+    fn trim(&mut self, max_entries: usize) {
+        let excess = self.prompts.len().saturating_sub(max_entries);
+        self.prompts.drain(..excess);
+    }
+
+    /// Read the history persisted for a workspace, or an empty one if there's none
+    fn load(workspace_id: WorkspaceId, max_entries: usize) -> Self {
+        KEY_VALUE_STORE
+            .read_kvp(&prompt_history_db_key(workspace_id))
+            .log_err()
+            .flatten()
+            .map(|value| Self::from_persisted(&value, max_entries))
+            .unwrap_or_default()
+    }
+
+    /// Persist the history for a workspace
+    async fn save(&self, workspace_id: WorkspaceId) -> Result<()> {
+        let value = serde_json::to_string(self)?;
+        KEY_VALUE_STORE
+            .write_kvp(prompt_history_db_key(workspace_id), value)
+            .await
+    }
+
+    /// Parse a persisted history, truncating prompts that are too long and keeping
+    /// the newest `max_entries`. A corrupt history is logged and reset.
+    fn from_persisted(value: &str, max_entries: usize) -> Self {
+        let mut history = match serde_json::from_str::<Self>(value) {
+            Ok(history) => history,
+            Err(error) => {
+                log::warn!("failed to parse persisted quick edit prompt history: {error}");
+                return Self::default();
+            }
+        };
+        for prompt in &mut history.prompts {
+            truncate_prompt(prompt);
+        }
+        history.trim(max_entries);
+        history
+    }
+}
+
+/// Cut `prompt` to `PROMPT_HISTORY_MAX_CHARS` characters
+fn truncate_prompt(prompt: &mut String) {
+    if let Some((byte_ix, _)) = prompt.char_indices().nth(PROMPT_HISTORY_MAX_CHARS) {
+        prompt.truncate(byte_ix);
+    }
+}
+
+fn prompt_history_db_key(workspace_id: WorkspaceId) -> String {
+    format!("{PROMPT_HISTORY_KEY}-{}", i64::from(workspace_id))
+}
+
+/// Prompt history of each workspace, loaded when first used. Workspaces without a
+/// database id share an unpersisted history.
+#[derive(Default)]
+struct QuickEditPromptHistories(HashMap<Option<WorkspaceId>, PromptHistory>);
+
+impl Global for QuickEditPromptHistories {}
+
+/// Get the prompt history of a workspace, loading it from the database on first use
+fn prompt_history(workspace_id: Option<WorkspaceId>, cx: &mut App) -> &mut PromptHistory {
+    let max_entries = AgentSettings::get_global(cx).quick_edit_prompt_history_size;
+    cx.default_global::<QuickEditPromptHistories>()
+        .0
+        .entry(workspace_id)
+        .or_insert_with(|| {
+            workspace_id.map_or_else(PromptHistory::default, |workspace_id| {
+                PromptHistory::load(workspace_id, max_entries)
+            })
+        })
+}
+
+/// Get the prompt most recently submitted in a workspace, if any
+pub(crate) fn last_prompt(workspace_id: Option<WorkspaceId>, cx: &mut App) -> Option<String> {
+    prompt_history(workspace_id, cx).prompts.back().cloned()
+}
+
+/// Modal prompt input for a quick edit of the selection.
+///
+/// Enter sends the typed prompt for the session's selection, Escape dismisses the
+/// input, and Up/Down recall previously submitted prompts. `ToggleQuickEditFullPrompt`
+/// shows the full prompt built from the typed one, whose edits are sent instead.
+pub struct QuickEditInput {
+    prompt_editor: Entity<Editor>,
+    workspace: WeakEntity<Workspace>,
+    /// The quick edit session the prompt is sent through
+    state: Entity<QuickEditState>,
+    /// Workspace whose prompt history is recalled and added to
+    workspace_id: Option<WorkspaceId>,
+    /// Size of the selection, e.g. "3 lines • 12 words • 80 chars • ~20 tokens"
+    selection_summary: Option<SharedString>,
+    /// File and lines of the selection
+    context_summary: Option<SharedString>,
+    /// Editor for the full prompt, while it's shown
+    full_prompt_editor: Option<Entity<Editor>>,
+    /// Index into the prompt history of the prompt being shown, if any
+    prompt_history_ix: Option<usize>,
+    /// What the user typed before recalling the history
+    pending_prompt: String,
+}
+
+impl QuickEditInput {
+    /// Open the input for the quick edit `state` of `editor`, prefilled with
+    /// `initial_prompt`, or close it when it's already open
+    pub(crate) fn toggle(
+        workspace: &mut Workspace,
+        editor: Entity<Editor>,
+        state: Entity<QuickEditState>,
+        initial_prompt: Option<String>,
+        window: &mut Window,
+        cx: &mut Context<Workspace>,
+    ) {
+        let workspace_entity = cx.entity().downgrade();
+        let workspace_id = workspace.database_id();
+        let range = state.read(cx).selection_range();
+        let selection = selection_text_and_context(editor.read(cx), range, cx);
+        let (selection_summary, context_summary) = match &selection {
+            Some((code, context)) => (
+                Some(SelectionInfo::from_text(code).format_display().into()),
+                Some(context.format().into()),
+            ),
+            None => (None, None),
+        };
+
+        workspace.toggle_modal(window, cx, move |window, cx| {
+            let prompt_editor = cx.new(|cx| {
+                let mut editor = Editor::single_line(window, cx);
+                editor.set_placeholder_text("Describe the edit…", window, cx);
+                if let Some(initial_prompt) = initial_prompt {
+                    editor.set_text(initial_prompt, window, cx);
+                }
+                editor
+            });
+            Self {
+                prompt_editor,
+                workspace: workspace_entity,
+                state,
+                workspace_id,
+                selection_summary,
+                context_summary,
+                full_prompt_editor: None,
+                prompt_history_ix: None,
+                pending_prompt: String::new(),
+            }
+        });
+    }
+
+    fn cancel(&mut self, _: &menu::Cancel, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(DismissEvent);
+    }
+
+    fn confirm(&mut self, _: &menu::Confirm, window: &mut Window, cx: &mut Context<Self>) {
+        let prompt = self.prompt_editor.read(cx).text(cx).trim().to_string();
+        // An edited full prompt replaces the one that would be built from the typed prompt
+        let full_prompt = self
+            .full_prompt_editor
+            .as_ref()
+            .map(|editor| editor.read(cx).text(cx).trim().to_string())
+            .filter(|full_prompt| !full_prompt.is_empty());
+        if prompt.is_empty() && full_prompt.is_none() {
+            return;
+        }
+
+        if !prompt.is_empty() {
+            let max_entries = AgentSettings::get_global(cx).quick_edit_prompt_history_size;
+            let history = prompt_history(self.workspace_id, cx);
+            history.push(prompt.clone(), max_entries);
+            if let Some(workspace_id) = self.workspace_id {
+                let history = history.clone();
+                cx.background_spawn(async move { history.save(workspace_id).await })
+                    .detach_and_log_err(cx);
+            }
+        }
+
+        cx.emit(DismissEvent);
+        let (Some(workspace), Some(editor)) =
+            (self.workspace.upgrade(), self.state.read(cx).editor())
+        else {
+            return;
+        };
+        let mode = active_agent_mode(workspace.read(cx), editor.read(cx), cx);
+        let result = self.state.update(cx, |state, cx| {
+            state.set_prompt(prompt);
+            state.submit(full_prompt, mode, window, cx)
+        });
+        match result {
+            Ok(()) => report_quick_edit_submitted(self.state.read(cx), cx),
+            Err(error) => workspace.update(cx, |workspace, cx| {
+                show_quick_edit_error(workspace, &error, cx)
+            }),
+        }
+    }
+
+    /// Build the full prompt for the typed prompt, as the agent would receive it
+    /// apart from attached files
+    fn build_full_prompt(&self, cx: &App) -> String {
+        let instruction = self.prompt_editor.read(cx).text(cx);
+        let instruction = instruction.trim();
+        self.state
+            .read(cx)
+            .build_prompt(instruction, &[], u32::MAX, cx)
+            .unwrap_or_else(|| instruction.to_string())
+    }
+
+    /// Show the full prompt in an editor, or hide it, discarding any edits to it
+    fn toggle_full_prompt(
+        &mut self,
+        _: &ToggleQuickEditFullPrompt,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.full_prompt_editor.take().is_none() {
+            let full_prompt = self.build_full_prompt(cx);
+            self.full_prompt_editor = Some(cx.new(|cx| {
+                let mut editor = Editor::auto_height(4, 16, window, cx);
+                editor.set_text(full_prompt, window, cx);
+                editor
+            }));
+        }
+        cx.notify();
+    }
+
+    fn move_up(&mut self, _: &MoveUp, window: &mut Window, cx: &mut Context<Self>) {
+        let history_len = prompt_history(self.workspace_id, cx).prompts.len();
+        let ix = match self.prompt_history_ix {
+            Some(0) => return,
+            Some(ix) => ix - 1,
+            None if history_len == 0 => return,
+            None => {
+                self.pending_prompt = self.prompt_editor.read(cx).text(cx);
+                history_len - 1
+            }
+        };
+        self.show_history_entry(Some(ix), window, cx);
+    }
+
+    fn move_down(&mut self, _: &MoveDown, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(ix) = self.prompt_history_ix else {
+            return;
+        };
+        let history_len = prompt_history(self.workspace_id, cx).prompts.len();
+        let next_ix = (ix + 1 < history_len).then_some(ix + 1);
+        self.show_history_entry(next_ix, window, cx);
+    }
+
+    /// Show the history entry at `ix` in the prompt editor, or the pending prompt for `None`
+    fn show_history_entry(
+        &mut self,
+        ix: Option<usize>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.prompt_history_ix = ix;
+        let prompt = match ix {
+            Some(ix) => prompt_history(self.workspace_id, cx)
+                .prompts
+                .get(ix)
+                .cloned()
+                .unwrap_or_default(),
+            None => self.pending_prompt.clone(),
+        };
+        self.prompt_editor.update(cx, |editor, cx| {
+            editor.set_text(prompt, window, cx);
+            editor.move_to_end(&Default::default(), window, cx);
+        });
+    }
+}
+
+impl Render for QuickEditInput {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        v_flex()
+            .key_context("QuickEditInput")
+            .w(rems(34.))
+            .elevation_2(cx)
+            .on_action(cx.listener(Self::cancel))
+            .on_action(cx.listener(Self::confirm))
+            .on_action(cx.listener(Self::move_up))
+            .on_action(cx.listener(Self::move_down))
+            .on_action(cx.listener(Self::toggle_full_prompt))
+            .child(
+                h_flex()
+                    .px_2()
+                    .py_1()
+                    .justify_between()
+                    .border_b_1()
+                    .border_color(cx.theme().colors().border_variant)
+                    .child(
+                        v_flex()
+                            .children(self.context_summary.clone().map(|context_summary| {
+                                Label::new(context_summary)
+                                    .size(LabelSize::Small)
+                                    .color(Color::Muted)
+                            }))
+                            .children(self.selection_summary.clone().map(|selection_summary| {
+                                Label::new(selection_summary)
+                                    .size(LabelSize::Small)
+                                    .color(Color::Muted)
+                            })),
+                    )
+                    .child(
+                        Button::new(
+                            "toggle-full-prompt",
+                            if self.full_prompt_editor.is_some() {
+                                "Hide Full Prompt"
+                            } else {
+                                "Show Full Prompt"
+                            },
+                        )
+                        .label_size(LabelSize::Small)
+                        .on_click(cx.listener(|this, _, window, cx| {
+                            this.toggle_full_prompt(&ToggleQuickEditFullPrompt, window, cx)
+                        })),
+                    ),
+            )
+            .child(div().px_2().py_1().child(self.prompt_editor.clone()))
+            .children(self.full_prompt_editor.clone().map(|full_prompt_editor| {
+                div()
+                    .px_2()
+                    .py_1()
+                    .border_t_1()
+                    .border_color(cx.theme().colors().border_variant)
+                    .child(full_prompt_editor)
+            }))
+    }
+}
+
+impl Focusable for QuickEditInput {
+    fn focus_handle(&self, cx: &App) -> FocusHandle {
+        self.prompt_editor.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for QuickEditInput {}
+impl ModalView for QuickEditInput {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use editor::SelectionEffects;
+    use gpui::TestAppContext;
+    use language::{
+        Diagnostic, DiagnosticEntry, DiagnosticSet, DiagnosticSeverity, LanguageServerId,
+        PointUtf16,
+    };
+    use multi_buffer::MultiBufferOffset;
+
+    use crate::agent_context_menu::handle_fix_diagnostics_here;
+    use crate::quick_edit::tests::{build_test_editor, sent_prompts, set_fake_model};
+    use crate::quick_edit::{format_agent_prompt, open_quick_edit_input, repeat_last_quick_edit};
+
+    #[gpui::test]
+    async fn test_submit_sends_prompt(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        let model = set_fake_model(&mut cx);
+
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            open_quick_edit_input(workspace, editor.clone(), 16..26, None, window, cx);
+        });
+        let input = workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.active_modal::<QuickEditInput>(cx)
+            })
+            .unwrap();
+        input.update(&mut cx, |input, _| {
+            assert_eq!(
+                input.context_summary.as_deref(),
+                Some("File: Untitled buffer • Line 2:4-14")
+            );
+            assert!(input.selection_summary.is_some());
+        });
+
+        // An empty prompt isn't submitted
+        input.update_in(&mut cx, |input, window, cx| {
+            input.confirm(&menu::Confirm, window, cx)
+        });
+        cx.run_until_parked();
+        assert!(sent_prompts(&model).is_empty());
+
+        input.update_in(&mut cx, |input, window, cx| {
+            input.prompt_editor.update(cx, |editor, cx| {
+                editor.set_text("  rename a to total  ", window, cx)
+            });
+            input.confirm(&menu::Confirm, window, cx);
+        });
+        cx.run_until_parked();
+        let expected_prompt = cx.update(|_, cx| {
+            let state = QuickEditState::for_editor(&editor, cx).unwrap();
+            state
+                .read(cx)
+                .build_prompt("rename a to total", &[], u32::MAX, cx)
+                .unwrap()
+        });
+        assert_eq!(sent_prompts(&model), [expected_prompt]);
+        assert!(
+            workspace
+                .update(&mut cx, |workspace, cx| workspace
+                    .active_modal::<QuickEditInput>(cx))
+                .is_none()
+        );
+
+        // The submitted prompt is recalled by the next input
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            open_quick_edit_input(workspace, editor.clone(), 16..26, None, window, cx);
+        });
+        let input = workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.active_modal::<QuickEditInput>(cx)
+            })
+            .unwrap();
+        let recalled = input.update_in(&mut cx, |input, window, cx| {
+            input.move_up(&MoveUp, window, cx);
+            input.prompt_editor.read(cx).text(cx)
+        });
+        assert_eq!(recalled, "rename a to total");
+    }
+
+    #[gpui::test]
+    async fn test_edited_full_prompt_is_sent(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n}\n", cx).await;
+        let model = set_fake_model(&mut cx);
+
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            open_quick_edit_input(workspace, editor.clone(), 16..26, None, window, cx);
+        });
+        let input = workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.active_modal::<QuickEditInput>(cx)
+            })
+            .unwrap();
+
+        // The full prompt shows the typed prompt along with the selection, its location
+        // and the code around it
+        let full_prompt = input.update_in(&mut cx, |input, window, cx| {
+            input.prompt_editor.update(cx, |editor, cx| {
+                editor.set_text("rename a to total", window, cx)
+            });
+            input.toggle_full_prompt(&ToggleQuickEditFullPrompt, window, cx);
+            input
+                .full_prompt_editor
+                .as_ref()
+                .map(|editor| editor.read(cx).text(cx))
+        });
+        let (code, context) = editor.update(&mut cx, |editor, cx| {
+            selection_text_and_context(editor, 16..26, cx).unwrap()
+        });
+        let full_prompt = full_prompt.unwrap();
+        assert!(full_prompt.starts_with(&format_agent_prompt(
+            "rename a to total",
+            &context,
+            &code
+        )));
+        assert!(full_prompt.contains("fn main() {"));
+
+        input.update_in(&mut cx, |input, window, cx| {
+            if let Some(full_prompt_editor) = &input.full_prompt_editor {
+                full_prompt_editor.update(cx, |editor, cx| {
+                    editor.set_text("Rename `a` to `total` and explain why.", window, cx)
+                });
+            }
+            input.confirm(&menu::Confirm, window, cx);
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            sent_prompts(&model),
+            ["Rename `a` to `total` and explain why."]
+        );
+
+        // Only the typed prompt is kept in the history
+        let workspace_id = input.read_with(&cx, |input, _| input.workspace_id);
+        let history = cx.update(|_, cx| prompts(prompt_history(workspace_id, cx)).join("\n"));
+        assert_eq!(history, "rename a to total");
+    }
+
+    #[gpui::test]
+    async fn test_repeat_last_quick_edit(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a = 1;\n    let b = 2;\n}\n", cx).await;
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            workspace.add_item_to_active_pane(Box::new(editor.clone()), None, true, window, cx);
+        });
+        let model = set_fake_model(&mut cx);
+
+        // Without a previous prompt there's nothing to repeat
+        let state = workspace.update_in(&mut cx, |workspace, window, cx| {
+            repeat_last_quick_edit(workspace, editor.clone(), 16..26, window, cx)
+        });
+        assert!(state.is_none());
+
+        let workspace_id = workspace.read_with(&cx, |workspace, _| workspace.database_id());
+        cx.update(|_, cx| {
+            let history = prompt_history(workspace_id, cx);
+            history.push("add logging".to_string(), 20);
+            history.push("rename a to total".to_string(), 20);
+        });
+        let state = workspace
+            .update_in(&mut cx, |workspace, window, cx| {
+                repeat_last_quick_edit(workspace, editor.clone(), 31..41, window, cx)
+            })
+            .unwrap();
+
+        let (code, context) = editor.update(&mut cx, |editor, cx| {
+            selection_text_and_context(editor, 31..41, cx).unwrap()
+        });
+        assert_eq!(code, "let b = 2;");
+        let expected_prompt = cx.update(|_, cx| {
+            let state = state.read(cx);
+            assert_eq!(state.prompt(), "rename a to total");
+            state
+                .build_prompt(state.prompt(), &[], u32::MAX, cx)
+                .unwrap()
+        });
+        assert!(expected_prompt.starts_with(&format_agent_prompt(
+            "rename a to total",
+            &context,
+            &code
+        )));
+        cx.run_until_parked();
+        assert_eq!(sent_prompts(&model), [expected_prompt]);
+    }
+
+    fn prompts(history: &PromptHistory) -> Vec<&str> {
+        history.prompts.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_prompt_history_push() {
+        let mut history = PromptHistory::default();
+        for prompt in ["a", "b", "c", "a"] {
+            history.push(prompt.to_string(), 3);
+        }
+        assert_eq!(prompts(&history), ["b", "c", "a"]);
+
+        history.push("d".to_string(), 2);
+        assert_eq!(prompts(&history), ["a", "d"]);
+
+        history.push("é".repeat(PROMPT_HISTORY_MAX_CHARS + 10), 2);
+        assert_eq!(
+            history.prompts.back().map(|prompt| prompt.chars().count()),
+            Some(PROMPT_HISTORY_MAX_CHARS)
+        );
+    }
+
+    #[test]
+    fn test_prompt_history_from_persisted() {
+        let long_prompt = "x".repeat(PROMPT_HISTORY_MAX_CHARS * 2);
+        let value = serde_json::json!({ "prompts": ["a", "b", long_prompt, "c"] }).to_string();
+
+        // Entries beyond the max count are trimmed, oldest first
+        let history = PromptHistory::from_persisted(&value, 2);
+        assert_eq!(
+            history.prompts.front().map(String::len),
+            Some(PROMPT_HISTORY_MAX_CHARS)
+        );
+        assert_eq!(history.prompts.back().map(String::as_str), Some("c"));
+        assert_eq!(history.prompts.len(), 2);
+
+        let history = PromptHistory::from_persisted(&value, 10);
+        assert_eq!(history.prompts.len(), 4);
+        assert_eq!(history.prompts[2].len(), PROMPT_HISTORY_MAX_CHARS);
+
+        // Corrupt histories are reset
+        assert_eq!(
+            PromptHistory::from_persisted("{\"prompts\": [1, 2", 10),
+            PromptHistory::default()
+        );
+        assert_eq!(
+            PromptHistory::from_persisted("[\"a\"]", 10),
+            PromptHistory::default()
+        );
+    }
+
+    #[gpui::test]
+    async fn test_fix_diagnostics_prefills_input(cx: &mut TestAppContext) {
+        let (workspace, editor, mut cx) =
+            build_test_editor("fn main() {\n    let a: u32 = \"one\";\n}\n", cx).await;
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            workspace.add_item_to_active_pane(Box::new(editor.clone()), None, true, window, cx);
+        });
+        let model = set_fake_model(&mut cx);
+        editor.update_in(&mut cx, |editor, window, cx| {
+            let buffer = editor.buffer().read(cx).as_singleton().unwrap();
+            buffer.update(cx, |buffer, cx| {
+                let diagnostics = DiagnosticSet::new(
+                    [DiagnosticEntry {
+                        range: PointUtf16::new(1, 17)..PointUtf16::new(1, 22),
+                        diagnostic: Diagnostic {
+                            severity: DiagnosticSeverity::ERROR,
+                            group_id: 1,
+                            is_primary: true,
+                            message: "mismatched types".into(),
+                            ..Default::default()
+                        },
+                    }],
+                    buffer,
+                );
+                buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+            });
+            editor.change_selections(SelectionEffects::no_scroll(), window, cx, |selections| {
+                selections.select_ranges([MultiBufferOffset(16)..MultiBufferOffset(35)]);
+            });
+        });
+
+        workspace.update_in(&mut cx, |workspace, window, cx| {
+            handle_fix_diagnostics_here(workspace, window, cx)
+        });
+        cx.run_until_parked();
+
+        // The instruction is left for the user to review instead of being sent
+        let input = workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.active_modal::<QuickEditInput>(cx)
+            })
+            .unwrap();
+        assert_eq!(
+            input.read_with(&cx, |input, cx| input.prompt_editor.read(cx).text(cx)),
+            "Fix these errors: line 2 (error): mismatched types"
+        );
+        assert!(sent_prompts(&model).is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_save_and_load_prompt_history() {
+        let workspace_id = WorkspaceId::default();
+        let mut history = PromptHistory::default();
+        for prompt in ["add logging", "rename a", "inline this"] {
+            history.push(prompt.to_string(), 20);
+        }
+        history.save(workspace_id).await.unwrap();
+
+        assert_eq!(PromptHistory::load(workspace_id, 20), history);
+        assert_eq!(
+            prompts(&PromptHistory::load(workspace_id, 2)),
+            ["rename a", "inline this"]
+        );
+    }
+}
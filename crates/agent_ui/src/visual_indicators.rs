@@ -6,18 +6,112 @@
 /// - Agent's proposed changes
 /// - Token count and context information
 
-use gpui::{HighlightStyle, Hsla};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::ops::Range;
+
+use agent_settings::AgentSettings;
+use gpui::{App, HighlightStyle, Hsla, Rgba, StrikethroughStyle, Task, UnderlineStyle, px};
+use settings::{Settings as _, VisualIndicatorSettingsContent};
+use ui::Color;
+
+pub use settings::HighlightPalette;
+
+/// Token usage percentage above which a warning is shown, unless configured otherwise
+pub const DEFAULT_WARN_THRESHOLD: f32 = 80.0;
+
+/// Token usage percentage above which the context is about to run out
+pub const CRITICAL_THRESHOLD: f32 = 95.0;
+
+/// Average characters per line above which a selection is taken for minified or generated code
+pub const DEFAULT_MINIFIED_CHARS_PER_LINE: usize = 300;
+
+/// Characters per token assumed by the fallback estimator when the model isn't known
+pub const DEFAULT_CHARS_PER_TOKEN: f32 = 4.0;
+
+/// Average characters per token by model family, matched against model ids
+const MODEL_CHARS_PER_TOKEN: &[(&str, f32)] = &[
+    ("claude", 3.5),
+    ("gpt", 4.0),
+    ("gemini", 4.0),
+    ("mistral", 3.5),
+    ("codestral", 3.5),
+];
+
+/// User configuration for agent highlights, resolved from the `agent.visual_indicators` setting
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisualIndicatorSettings {
+    pub palette: HighlightPalette,
+    /// Overrides the palette's background for code selected for the agent
+    pub selected_color: Option<Hsla>,
+    /// Overrides the palette's background for lines being edited by the agent
+    pub editing_color: Option<Hsla>,
+    /// Overrides the palette's background for changes proposed by the agent
+    pub proposed_color: Option<Hsla>,
+    /// Token usage percentage above which a warning is shown
+    pub warn_threshold: f32,
+}
+
+impl Default for VisualIndicatorSettings {
+    fn default() -> Self {
+        Self {
+            palette: HighlightPalette::default(),
+            selected_color: None,
+            editing_color: None,
+            proposed_color: None,
+            warn_threshold: DEFAULT_WARN_THRESHOLD,
+        }
+    }
+}
+
+impl VisualIndicatorSettings {
+    /// Get the current settings from the global agent settings
+    pub fn get(cx: &App) -> Self {
+        Self::from_content(&AgentSettings::get_global(cx).visual_indicators)
+    }
+
+    /// Resolve settings content, ignoring colors that fail to parse
+    pub fn from_content(content: &VisualIndicatorSettingsContent) -> Self {
+        Self {
+            palette: content.palette.unwrap_or_default(),
+            selected_color: parse_color("selected_color", content.selected_color.as_deref()),
+            editing_color: parse_color("editing_color", content.editing_color.as_deref()),
+            proposed_color: parse_color("proposed_color", content.proposed_color.as_deref()),
+            warn_threshold: content.warn_threshold.unwrap_or(DEFAULT_WARN_THRESHOLD),
+        }
+    }
+}
+
+fn parse_color(setting: &str, color: Option<&str>) -> Option<Hsla> {
+    let color = color?;
+    match Rgba::try_from(color) {
+        Ok(color) => Some(color.into()),
+        Err(error) => {
+            log::warn!("ignoring invalid agent.visual_indicators.{setting} {color:?}: {error}");
+            None
+        }
+    }
+}
 
 /// Style for code selected for agent
-pub fn selected_for_agent_style() -> HighlightStyle {
-    HighlightStyle {
-        color: None,
-        background_color: Some(Hsla {
-            h: 60.0,
+pub fn selected_for_agent_style(settings: &VisualIndicatorSettings) -> HighlightStyle {
+    let palette_color = match settings.palette {
+        HighlightPalette::Default => Hsla {
+            h: 60.0 / 360.0,
             s: 0.5,
             l: 0.7,
             a: 0.15,
-        }),
+        },
+        HighlightPalette::ColorblindSafe => Hsla {
+            h: 215.0 / 360.0,
+            s: 0.7,
+            l: 0.6,
+            a: 0.2,
+        },
+    };
+    HighlightStyle {
+        color: None,
+        background_color: Some(settings.selected_color.unwrap_or(palette_color)),
         font_weight: None,
         font_style: None,
         underline: None,
@@ -27,32 +121,124 @@ pub fn selected_for_agent_style() -> HighlightStyle {
 }
 
 /// Style for lines being edited by agent
-pub fn agent_editing_style() -> HighlightStyle {
+pub fn agent_editing_style(settings: &VisualIndicatorSettings) -> HighlightStyle {
+    let (palette_color, underline) = match settings.palette {
+        HighlightPalette::Default => (
+            Hsla {
+                h: 200.0 / 360.0,
+                s: 0.6,
+                l: 0.7,
+                a: 0.2,
+            },
+            None,
+        ),
+        HighlightPalette::ColorblindSafe => (
+            Hsla {
+                h: 30.0 / 360.0,
+                s: 0.9,
+                l: 0.75,
+                a: 0.2,
+            },
+            Some(UnderlineStyle {
+                thickness: px(1.),
+                color: None,
+                wavy: true,
+            }),
+        ),
+    };
+    HighlightStyle {
+        color: None,
+        background_color: Some(settings.editing_color.unwrap_or(palette_color)),
+        font_weight: None,
+        font_style: None,
+        underline,
+        strikethrough: None,
+        fade_out: None,
+    }
+}
+
+/// Style for proposed changes from agent
+pub fn proposed_changes_style(settings: &VisualIndicatorSettings) -> HighlightStyle {
+    let (palette_color, underline) = match settings.palette {
+        HighlightPalette::Default => (
+            Hsla {
+                h: 120.0 / 360.0,
+                s: 0.6,
+                l: 0.7,
+                a: 0.15,
+            },
+            None,
+        ),
+        HighlightPalette::ColorblindSafe => (
+            Hsla {
+                h: 280.0 / 360.0,
+                s: 0.5,
+                l: 0.45,
+                a: 0.2,
+            },
+            Some(UnderlineStyle {
+                thickness: px(1.),
+                color: None,
+                wavy: false,
+            }),
+        ),
+    };
+    HighlightStyle {
+        color: None,
+        background_color: Some(settings.proposed_color.unwrap_or(palette_color)),
+        font_weight: None,
+        font_style: None,
+        underline,
+        strikethrough: None,
+        fade_out: None,
+    }
+}
+
+/// Style for a proposed change the user has accepted
+pub fn accepted_change_style() -> HighlightStyle {
+    HighlightStyle {
+        color: None,
+        background_color: Some(Hsla {
+            h: 130.0 / 360.0,
+            s: 0.55,
+            l: 0.45,
+            a: 0.25,
+        }),
+        font_weight: None,
+        font_style: None,
+        underline: None,
+        strikethrough: None,
+        fade_out: None,
+    }
+}
+
+/// Style for a proposed change the user has rejected
+pub fn rejected_change_style() -> HighlightStyle {
     HighlightStyle {
         color: None,
         background_color: Some(Hsla {
-            h: 200.0,
+            h: 0.0,
             s: 0.6,
-            l: 0.7,
+            l: 0.55,
             a: 0.2,
         }),
         font_weight: None,
         font_style: None,
         underline: None,
         strikethrough: None,
-        fade_out: None,
+        fade_out: Some(0.3),
     }
 }
 
-/// Style for proposed changes from agent
-pub fn proposed_changes_style() -> HighlightStyle {
+/// Style for text inserted by an inline quick edit diff
+pub fn diff_added_style() -> HighlightStyle {
     HighlightStyle {
         color: None,
         background_color: Some(Hsla {
-            h: 120.0,
+            h: 142.0 / 360.0,
             s: 0.6,
-            l: 0.7,
-            a: 0.15,
+            l: 0.45,
+            a: 0.2,
         }),
         font_weight: None,
         font_style: None,
@@ -62,6 +248,186 @@ pub fn proposed_changes_style() -> HighlightStyle {
     }
 }
 
+/// Style for text deleted by an inline quick edit diff
+pub fn diff_removed_style() -> HighlightStyle {
+    HighlightStyle {
+        color: None,
+        background_color: Some(Hsla {
+            h: 0.0,
+            s: 0.7,
+            l: 0.55,
+            a: 0.2,
+        }),
+        font_weight: None,
+        font_style: None,
+        underline: None,
+        strikethrough: Some(StrikethroughStyle {
+            thickness: px(1.),
+            color: None,
+        }),
+        fade_out: None,
+    }
+}
+
+/// Minimum WCAG contrast ratio for body text, used to keep highlighted text legible
+pub const MIN_TEXT_CONTRAST_RATIO: f32 = 4.5;
+
+/// How much the overlay alpha is reduced per step while searching for legible contrast
+const CONTRAST_ALPHA_STEP: f32 = 0.01;
+
+/// WCAG contrast ratio between two opaque colors, from 1.0 up to 21.0
+pub fn contrast_ratio(a: Hsla, b: Hsla) -> f32 {
+    let a = relative_luminance(a);
+    let b = relative_luminance(b);
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// WCAG relative luminance of a color, ignoring alpha
+fn relative_luminance(color: Hsla) -> f32 {
+    let rgb = color.to_rgb();
+    let channel = |value: f32| {
+        if value <= 0.03928 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(rgb.r) + 0.7152 * channel(rgb.g) + 0.0722 * channel(rgb.b)
+}
+
+/// Reduce the background alpha of `style` until text drawn over it meets `min_ratio`.
+///
+/// The overlay is blended onto `background`, and compared against whichever of black or
+/// white text is more legible on that background.
+pub fn ensure_min_contrast(
+    style: HighlightStyle,
+    background: Hsla,
+    min_ratio: f32,
+) -> HighlightStyle {
+    let Some(overlay) = style.background_color else {
+        return style;
+    };
+    let background = background.alpha(1.0);
+    let white_contrast = contrast_ratio(gpui::white(), background);
+    let black_contrast = contrast_ratio(gpui::black(), background);
+    let foreground = if white_contrast >= black_contrast {
+        gpui::white()
+    } else {
+        gpui::black()
+    };
+
+    let mut alpha = overlay.a;
+    while alpha > 0.0
+        && contrast_ratio(foreground, background.blend(overlay.alpha(alpha))) < min_ratio
+    {
+        alpha = (alpha - CONTRAST_ALPHA_STEP).max(0.0);
+    }
+
+    HighlightStyle {
+        background_color: Some(overlay.alpha(alpha)),
+        ..style
+    }
+}
+
+/// Kind of gutter marker shown next to a line involved in an agent operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GutterIndicator {
+    /// Line is part of the selection sent to the agent
+    SelectedForAgent,
+    /// Line is currently being rewritten by the agent
+    BeingEdited,
+    /// Line contains a change proposed by the agent
+    Proposed,
+}
+
+impl GutterIndicator {
+    /// Get the highlight style used to paint this indicator
+    pub fn style(&self, settings: &VisualIndicatorSettings) -> HighlightStyle {
+        match self {
+            Self::SelectedForAgent => selected_for_agent_style(settings),
+            Self::BeingEdited => agent_editing_style(settings),
+            Self::Proposed => proposed_changes_style(settings),
+        }
+    }
+
+    /// Rank used to pick one indicator when several apply to the same line;
+    /// higher wins
+    pub fn priority(&self) -> u8 {
+        match self {
+            Self::SelectedForAgent => 0,
+            Self::Proposed => 1,
+            Self::BeingEdited => 2,
+        }
+    }
+}
+
+/// A contiguous run of rows sharing the same gutter indicator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GutterIndicatorSpan {
+    pub indicator: GutterIndicator,
+    /// Zero-based rows covered by this span (end-exclusive)
+    pub rows: Range<u32>,
+}
+
+/// Map a range of rows to the gutter spans for a single indicator
+pub fn gutter_spans_for_rows(
+    rows: Range<u32>,
+    indicator: GutterIndicator,
+) -> Vec<GutterIndicatorSpan> {
+    merge_gutter_rows(rows.map(|row| (row, indicator)))
+}
+
+/// Merge per-row indicators into contiguous spans of the same kind
+pub fn merge_gutter_rows(
+    rows: impl IntoIterator<Item = (u32, GutterIndicator)>,
+) -> Vec<GutterIndicatorSpan> {
+    let mut rows = rows.into_iter().collect::<Vec<_>>();
+    rows.sort_by_key(|(row, _)| *row);
+
+    let mut spans: Vec<GutterIndicatorSpan> = Vec::new();
+    for (row, indicator) in rows {
+        if let Some(last) = spans.last_mut()
+            && last.indicator == indicator
+            && last.rows.end >= row
+        {
+            last.rows.end = last.rows.end.max(row + 1);
+            continue;
+        }
+        spans.push(GutterIndicatorSpan {
+            indicator,
+            rows: row..row + 1,
+        });
+    }
+    spans
+}
+
+/// Resolve the indicators of each row to a single highlight style per row range.
+///
+/// A row may be listed with several indicators, e.g. when it is both selected
+/// for the agent and being edited. Only the highest-priority indicator of such
+/// a row is painted, since stacking translucent overlays muddies their colors.
+pub fn resolve_row_styles(
+    rows: impl IntoIterator<Item = (u32, GutterIndicator)>,
+    settings: &VisualIndicatorSettings,
+) -> Vec<(Range<u32>, HighlightStyle)> {
+    let mut dominant = BTreeMap::<u32, GutterIndicator>::new();
+    for (row, indicator) in rows {
+        dominant
+            .entry(row)
+            .and_modify(|current| {
+                if indicator.priority() > current.priority() {
+                    *current = indicator;
+                }
+            })
+            .or_insert(indicator);
+    }
+
+    merge_gutter_rows(dominant)
+        .into_iter()
+        .map(|span| (span.rows, span.indicator.style(settings)))
+        .collect()
+}
+
 /// Information about token usage
 #[derive(Clone, Debug, Default)]
 pub struct TokenInfo {
@@ -71,6 +437,28 @@ pub struct TokenInfo {
     pub total_tokens: u32,
     /// Model's context window size
     pub context_window: u32,
+    /// Where the tokens come from, when known
+    pub breakdown: Option<TokenBreakdown>,
+}
+
+/// Token usage split by what the tokens are spent on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenBreakdown {
+    /// Tokens in the system prompt
+    pub system: u32,
+    /// Tokens in the thread's previous messages
+    pub history: u32,
+    /// Tokens in the pending selection
+    pub selection: u32,
+    /// Tokens kept free for the model's response
+    pub response_reserve: u32,
+}
+
+impl TokenBreakdown {
+    /// Sum of every component
+    pub fn total(&self) -> u32 {
+        self.system + self.history + self.selection + self.response_reserve
+    }
 }
 
 impl TokenInfo {
@@ -83,25 +471,236 @@ impl TokenInfo {
         }
     }
 
+    /// Percentage of the context window used once the pending selection is added to the thread
+    pub fn projected_percentage_used(&self) -> f32 {
+        Self {
+            total_tokens: self.total_tokens.saturating_add(self.selection_tokens),
+            ..self.clone()
+        }
+        .percentage_used()
+    }
+
+    /// Copy of this info with a pending selection of `selection_tokens` about to be
+    /// added to the thread, to preview how the selection changes the headroom
+    pub fn with_pending_selection(&self, selection_tokens: u32) -> Self {
+        Self {
+            selection_tokens,
+            ..self.clone()
+        }
+    }
+
+    /// Count a new exchange of the thread: `input` tokens sent and `output` tokens generated.
+    ///
+    /// Both become part of the history for the next message, so the breakdown, when
+    /// known, grows with the total.
+    pub fn add_message_tokens(&mut self, input: u32, output: u32) {
+        let message_tokens = input.saturating_add(output);
+        self.total_tokens = self.total_tokens.saturating_add(message_tokens);
+        if let Some(breakdown) = &mut self.breakdown {
+            breakdown.history = breakdown.history.saturating_add(message_tokens);
+        }
+    }
+
+    /// Zero every count for a new thread, keeping the model's context window
+    pub fn reset(&mut self) {
+        *self = Self {
+            context_window: self.context_window,
+            ..Self::default()
+        };
+    }
+
     /// Estimate tokens from text (rough approximation)
     pub fn estimate_tokens_from_text(text: &str) -> u32 {
-        // Rough estimate: ~4 characters per token on average
-        (text.len() / 4).max(1) as u32
+        Self::estimate_tokens_from_text_with_ratio(text, DEFAULT_CHARS_PER_TOKEN)
+    }
+
+    /// Estimate tokens from text, assuming `chars_per_token` characters per token
+    /// outside of CJK text.
+    ///
+    /// A ratio that isn't positive falls back to `DEFAULT_CHARS_PER_TOKEN`.
+    pub fn estimate_tokens_from_text_with_ratio(text: &str, chars_per_token: f32) -> u32 {
+        let chars_per_token = if chars_per_token > 0.0 {
+            chars_per_token
+        } else {
+            DEFAULT_CHARS_PER_TOKEN
+        };
+        // CJK characters are usually a token each, whatever the ratio of other text
+        let (cjk_chars, other_chars) = text.chars().fold((0, 0), |(cjk, other), ch| {
+            if is_cjk(ch) {
+                (cjk + 1, other)
+            } else {
+                (cjk, other + 1)
+            }
+        });
+        let other_tokens = (other_chars as f32 / chars_per_token) as u32;
+        (cjk_chars + other_tokens).max(1)
+    }
+
+    /// Average characters per token for `model`, or `DEFAULT_CHARS_PER_TOKEN`
+    /// when its family isn't known
+    pub fn chars_per_token_for_model(model: &str) -> f32 {
+        let model = model.to_lowercase();
+        MODEL_CHARS_PER_TOKEN
+            .iter()
+            .find(|(family, _)| model.contains(family))
+            .map_or(DEFAULT_CHARS_PER_TOKEN, |(_, chars_per_token)| {
+                *chars_per_token
+            })
+    }
+
+    /// Count tokens in `text` with the tokenizer for `model`.
+    ///
+    /// Falls back to the rough estimate, at the model's ratio, when no tokenizer
+    /// is known for the model.
+    pub fn count_tokens(text: &str, model: &str) -> u32 {
+        tiktoken_rs::get_bpe_from_model(model)
+            .map(|bpe| bpe.encode_with_special_tokens(text).len() as u32)
+            .unwrap_or_else(|_| {
+                Self::estimate_tokens_from_text_with_ratio(
+                    text,
+                    Self::chars_per_token_for_model(model),
+                )
+            })
+    }
+
+    /// Count tokens like [`Self::count_tokens`], on the background executor.
+    ///
+    /// Use this for large selections, showing the rough estimate until it resolves.
+    pub fn count_tokens_async(text: String, model: String, cx: &App) -> Task<u32> {
+        cx.background_spawn(async move { Self::count_tokens(&text, &model) })
     }
 
     /// Format token info as display string
     pub fn format_display(&self) -> String {
-        let percentage = self.percentage_used();
-        let warning = if percentage > 80.0 {
+        self.format_display_with_warn_threshold(DEFAULT_WARN_THRESHOLD)
+    }
+
+    /// Format token info as display string, warning above `warn_threshold` percent usage.
+    ///
+    /// A pending selection is shown after the usage, and counts towards the warning.
+    pub fn format_display_with_warn_threshold(&self, warn_threshold: f32) -> String {
+        let pending = if self.selection_tokens > 0 {
+            format!(" (+{} pending)", self.selection_tokens)
+        } else {
+            String::new()
+        };
+        let warning = if self.projected_percentage_used() > warn_threshold {
             " ⚠️"
         } else {
             ""
         };
         format!(
-            "{}/{} tokens ({:.0}%)",
-            self.total_tokens, self.context_window, percentage
+            "{}/{} tokens ({:.0}%){pending}",
+            self.total_tokens,
+            self.context_window,
+            self.percentage_used()
         ) + warning
     }
+
+    /// Color for the token display, warning once usage, including the pending
+    /// selection, would go above `warn_threshold` percent
+    pub fn display_color(&self, warn_threshold: f32) -> Color {
+        if self.projected_percentage_used() > warn_threshold {
+            Color::Warning
+        } else {
+            Color::Muted
+        }
+    }
+
+    /// Advice on freeing up context once usage is above `warn_threshold` percent
+    pub fn suggest_action(&self, warn_threshold: f32) -> Option<String> {
+        let percentage = self.percentage_used();
+        if percentage > CRITICAL_THRESHOLD {
+            Some(format!(
+                "Context is {percentage:.0}% full — start a new thread or remove attached files before the next message."
+            ))
+        } else if percentage > warn_threshold {
+            Some(format!(
+                "Context is {percentage:.0}% full — consider starting a new thread or removing attached files."
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Format the token breakdown as display string, if there is one
+    pub fn format_breakdown(&self) -> Option<String> {
+        let breakdown = self.breakdown?;
+        Some(format!(
+            "System: {} • History: {} • Selection: {} • Response reserve: {}",
+            breakdown.system, breakdown.history, breakdown.selection, breakdown.response_reserve
+        ))
+    }
+
+    /// Format the token breakdown as a table with aligned component, token and percent
+    /// columns, for a monospace tooltip.
+    ///
+    /// Percentages are of the context window. Components without tokens are left
+    /// out, and the table is empty when there's no breakdown or every component is zero.
+    pub fn format_breakdown_table(&self) -> String {
+        let Some(breakdown) = self.breakdown else {
+            return String::new();
+        };
+        let rows = [
+            ("System", breakdown.system),
+            ("History", breakdown.history),
+            ("Selection", breakdown.selection),
+            ("Response reserve", breakdown.response_reserve),
+        ]
+        .into_iter()
+        .filter(|(_, tokens)| *tokens > 0)
+        .map(|(component, tokens)| {
+            let percent = if self.context_window == 0 {
+                0.0
+            } else {
+                tokens as f32 / self.context_window as f32 * 100.0
+            };
+            [
+                component.to_string(),
+                tokens.to_string(),
+                format!("{percent:.0}%"),
+            ]
+        })
+        .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return String::new();
+        }
+
+        let header = ["Component", "Tokens", "Percent"].map(String::from);
+        let mut widths = header.each_ref().map(|cell| cell.chars().count());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        std::iter::once(&header)
+            .chain(&rows)
+            .map(|[component, tokens, percent]| {
+                format!(
+                    "{component:<component_width$}  {tokens:>tokens_width$}  {percent:>percent_width$}",
+                    component_width = widths[0],
+                    tokens_width = widths[1],
+                    percent_width = widths[2],
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Whether `ch` belongs to a Chinese, Japanese or Korean script
+fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{3000}'..='\u{303F}' // CJK symbols and punctuation
+            | '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+            | '\u{3400}'..='\u{4DBF}' // CJK unified ideographs extension A
+            | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+            | '\u{AC00}'..='\u{D7AF}' // Hangul syllables
+            | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+            | '\u{FF00}'..='\u{FFEF}' // Halfwidth and fullwidth forms
+            | '\u{20000}'..='\u{2FA1F}' // CJK unified ideographs extensions B and later
+    )
 }
 
 /// Information about selected code for display
@@ -109,7 +708,10 @@ impl TokenInfo {
 pub struct SelectionInfo {
     pub char_count: usize,
     pub line_count: u32,
+    pub word_count: usize,
     pub token_estimate: u32,
+    /// Number of selections these counts were summed over
+    pub selection_count: usize,
 }
 
 impl SelectionInfo {
@@ -117,21 +719,102 @@ impl SelectionInfo {
     pub fn from_text(text: &str) -> Self {
         let char_count = text.len();
         let line_count = text.lines().count() as u32;
+        let word_count = text.split_whitespace().count();
         let token_estimate = TokenInfo::estimate_tokens_from_text(text);
 
         Self {
             char_count,
             line_count,
+            word_count,
             token_estimate,
+            selection_count: 1,
         }
     }
 
+    /// Sum the counts of several selections, e.g. one per cursor
+    pub fn merge(infos: &[SelectionInfo]) -> Self {
+        infos.iter().fold(
+            Self {
+                char_count: 0,
+                line_count: 0,
+                word_count: 0,
+                token_estimate: 0,
+                selection_count: 0,
+            },
+            |merged, info| Self {
+                char_count: merged.char_count + info.char_count,
+                line_count: merged.line_count + info.line_count,
+                word_count: merged.word_count + info.word_count,
+                token_estimate: merged.token_estimate + info.token_estimate,
+                selection_count: merged.selection_count + info.selection_count,
+            },
+        )
+    }
+
+    /// Average number of characters per line of the selection
+    pub fn chars_per_line(&self) -> usize {
+        self.char_count / self.line_count.max(1) as usize
+    }
+
+    /// Whether the selection looks like minified or generated code, which
+    /// costs far more tokens than its line count suggests
+    pub fn looks_minified(&self) -> bool {
+        self.looks_minified_with_threshold(DEFAULT_MINIFIED_CHARS_PER_LINE)
+    }
+
+    /// Like `looks_minified`, with a custom characters-per-line threshold
+    pub fn looks_minified_with_threshold(&self, max_chars_per_line: usize) -> bool {
+        self.chars_per_line() > max_chars_per_line
+    }
+
     /// Format selection info for display
     pub fn format_display(&self) -> String {
-        format!(
-            "{} lines • {} chars • ~{} tokens",
-            self.line_count, self.char_count, self.token_estimate
-        )
+        let display = format!(
+            "{} lines • {} words • {} chars • ~{} tokens",
+            self.line_count, self.word_count, self.char_count, self.token_estimate
+        );
+        if self.selection_count > 1 {
+            format!("{display} ({} selections)", self.selection_count)
+        } else {
+            display
+        }
+    }
+}
+
+/// Memoizes the `SelectionInfo` of the last text it was asked about, so that
+/// rendering the same selection repeatedly doesn't recount it
+#[derive(Debug, Default)]
+pub struct SelectionStatsCache {
+    /// Hash of the text `info` was computed from
+    text_hash: u64,
+    info: Option<SelectionInfo>,
+    /// How many times `info` was computed
+    #[cfg(test)]
+    compute_count: usize,
+}
+
+impl SelectionStatsCache {
+    /// Get the selection info of `text`, recomputing it only when `text` differs
+    /// from the previous call
+    pub fn get(&mut self, text: &str) -> &SelectionInfo {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let text_hash = hasher.finish();
+        if self.info.is_none() || self.text_hash != text_hash {
+            self.info = None;
+            self.text_hash = text_hash;
+            #[cfg(test)]
+            {
+                self.compute_count += 1;
+            }
+        }
+        self.info
+            .get_or_insert_with(|| SelectionInfo::from_text(text))
+    }
+
+    /// Forget the cached info, e.g. when the selection is cleared
+    pub fn clear(&mut self) {
+        self.info = None;
     }
 }
 
@@ -139,12 +822,20 @@ impl SelectionInfo {
 mod tests {
     use super::*;
 
+    fn colorblind_safe_settings() -> VisualIndicatorSettings {
+        VisualIndicatorSettings {
+            palette: HighlightPalette::ColorblindSafe,
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_token_percentage() {
         let info = TokenInfo {
             selection_tokens: 100,
             total_tokens: 5000,
             context_window: 10000,
+            breakdown: None,
         };
         assert_eq!(info.percentage_used(), 50.0);
     }
@@ -154,7 +845,56 @@ mod tests {
         let text = "fn hello() { println!(\"Hello, world!\"); }";
         let estimated = TokenInfo::estimate_tokens_from_text(text);
         assert!(estimated > 0);
-        assert!(estimated < text.len());
+        assert!((estimated as usize) < text.len());
+    }
+
+    #[test]
+    fn test_token_estimate_by_script() {
+        let ascii = "fn add(a: u32, b: u32) -> u32 { a + b }";
+        assert_eq!(
+            TokenInfo::estimate_tokens_from_text(ascii),
+            (ascii.chars().count() / 4) as u32
+        );
+
+        let cjk = "这是一个测试字符串";
+        assert_eq!(TokenInfo::estimate_tokens_from_text(cjk), 9);
+        assert_eq!(TokenInfo::estimate_tokens_from_text("こんにちは世界"), 7);
+
+        // 7 CJK characters plus 11 other characters
+        let mixed = "// 计算两个数的和\nlet sum";
+        let estimate = TokenInfo::estimate_tokens_from_text(mixed);
+        assert!((8..=12).contains(&estimate), "estimate was {estimate}");
+    }
+
+    #[test]
+    fn test_token_estimate_with_ratio() {
+        let text = "fn add(a: u32, b: u32) -> u32 { a + b }";
+        assert_eq!(text.len(), 39);
+        assert_eq!(TokenInfo::estimate_tokens_from_text(text), 9);
+        assert_eq!(
+            TokenInfo::estimate_tokens_from_text_with_ratio(text, DEFAULT_CHARS_PER_TOKEN),
+            9
+        );
+        assert_eq!(
+            TokenInfo::estimate_tokens_from_text_with_ratio(text, 3.0),
+            13
+        );
+        // Invalid ratios fall back to the default
+        assert_eq!(
+            TokenInfo::estimate_tokens_from_text_with_ratio(text, 0.0),
+            9
+        );
+        // CJK characters count a token each regardless of the ratio
+        assert_eq!(
+            TokenInfo::estimate_tokens_from_text_with_ratio("这是一个测试字符串", 3.0),
+            9
+        );
+
+        assert_eq!(TokenInfo::chars_per_token_for_model("claude-sonnet-4"), 3.5);
+        assert_eq!(
+            TokenInfo::chars_per_token_for_model("unknown-model"),
+            DEFAULT_CHARS_PER_TOKEN
+        );
     }
 
     #[test]
@@ -165,12 +905,23 @@ mod tests {
         assert_eq!(info.char_count, text.len());
     }
 
+    #[test]
+    fn test_selection_info_word_count() {
+        assert_eq!(SelectionInfo::from_text("").word_count, 0);
+        assert_eq!(SelectionInfo::from_text("   \n\t ").word_count, 0);
+        assert_eq!(SelectionInfo::from_text("  hello  ").word_count, 1);
+
+        let paragraph = "The quick  brown fox\n  jumps over\tthe\n\nlazy dog.  ";
+        assert_eq!(SelectionInfo::from_text(paragraph).word_count, 9);
+    }
+
     #[test]
     fn test_token_info_display() {
         let info = TokenInfo {
             selection_tokens: 100,
             total_tokens: 8000,
             context_window: 10000,
+            breakdown: None,
         };
         let display = info.format_display();
         assert!(display.contains("8000"));
@@ -178,27 +929,485 @@ mod tests {
         assert!(display.contains("80"));
     }
 
+    #[test]
+    fn test_pending_selection_display() {
+        let info = TokenInfo {
+            selection_tokens: 0,
+            total_tokens: 5000,
+            context_window: 10000,
+            breakdown: None,
+        };
+        assert_eq!(info.format_display(), "5000/10000 tokens (50%)");
+
+        let pending = info.with_pending_selection(2500);
+        assert_eq!(pending.selection_tokens, 2500);
+        assert_eq!(pending.total_tokens, 5000);
+        assert_eq!(pending.projected_percentage_used(), 75.0);
+        assert_eq!(
+            pending.format_display(),
+            "5000/10000 tokens (50%) (+2500 pending)"
+        );
+        assert_eq!(pending.display_color(DEFAULT_WARN_THRESHOLD), Color::Muted);
+    }
+
+    #[test]
+    fn test_pending_selection_projected_warning() {
+        let info = TokenInfo {
+            selection_tokens: 0,
+            total_tokens: 7000,
+            context_window: 10000,
+            breakdown: None,
+        };
+        assert!(!info.format_display().contains("⚠️"));
+        assert_eq!(info.display_color(DEFAULT_WARN_THRESHOLD), Color::Muted);
+
+        // Current usage stays below the threshold, but adding the selection would cross it
+        let pending = info.with_pending_selection(1500);
+        assert!(pending.percentage_used() < DEFAULT_WARN_THRESHOLD);
+        assert_eq!(
+            pending.format_display(),
+            "7000/10000 tokens (70%) (+1500 pending) ⚠️"
+        );
+        assert_eq!(
+            pending.display_color(DEFAULT_WARN_THRESHOLD),
+            Color::Warning
+        );
+        assert_eq!(pending.display_color(90.0), Color::Muted);
+    }
+
     #[test]
     fn test_token_warning() {
         let info = TokenInfo {
             selection_tokens: 0,
             total_tokens: 9000,
             context_window: 10000,
+            breakdown: None,
         };
         let display = info.format_display();
         assert!(display.contains("⚠️"));
     }
 
+    #[test]
+    fn test_colorblind_safe_backgrounds_differ_in_hue_and_lightness() {
+        let backgrounds = [
+            selected_for_agent_style(&colorblind_safe_settings()),
+            agent_editing_style(&colorblind_safe_settings()),
+            proposed_changes_style(&colorblind_safe_settings()),
+        ]
+        .map(|style| style.background_color.unwrap());
+
+        for (ix, a) in backgrounds.iter().enumerate() {
+            for b in &backgrounds[ix + 1..] {
+                assert_ne!(a.h, b.h);
+                assert!((a.l - b.l).abs() >= 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_colorblind_safe_states_have_distinct_underlines() {
+        let editing = agent_editing_style(&colorblind_safe_settings());
+        let proposed = proposed_changes_style(&colorblind_safe_settings());
+        assert!(editing.underline.unwrap().wavy);
+        assert!(!proposed.underline.unwrap().wavy);
+        assert!(
+            selected_for_agent_style(&colorblind_safe_settings())
+                .underline
+                .is_none()
+        );
+        assert!(
+            proposed_changes_style(&VisualIndicatorSettings::default())
+                .underline
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_ensure_min_contrast_on_dark_background() {
+        let background = gpui::hsla(0.0, 0.0, 0.1, 1.0);
+        let bright_overlay = HighlightStyle {
+            background_color: Some(gpui::hsla(60.0 / 360.0, 0.9, 0.9, 0.9)),
+            ..Default::default()
+        };
+
+        let adjusted = ensure_min_contrast(bright_overlay, background, MIN_TEXT_CONTRAST_RATIO);
+        let overlay = adjusted.background_color.unwrap();
+        assert!(overlay.a < 0.9);
+        assert!(
+            contrast_ratio(gpui::white(), background.blend(overlay)) >= MIN_TEXT_CONTRAST_RATIO
+        );
+
+        // The default styles are already subtle enough on a dark background
+        let style = agent_editing_style(&VisualIndicatorSettings::default());
+        assert_eq!(
+            ensure_min_contrast(style, background, MIN_TEXT_CONTRAST_RATIO),
+            style
+        );
+    }
+
+    #[test]
+    fn test_ensure_min_contrast_on_light_background() {
+        let background = gpui::hsla(0.0, 0.0, 0.98, 1.0);
+        let dark_overlay = HighlightStyle {
+            background_color: Some(gpui::hsla(280.0 / 360.0, 0.5, 0.2, 0.8)),
+            ..Default::default()
+        };
+
+        let adjusted = ensure_min_contrast(dark_overlay, background, MIN_TEXT_CONTRAST_RATIO);
+        let overlay = adjusted.background_color.unwrap();
+        assert!(overlay.a < 0.8);
+        assert!(
+            contrast_ratio(gpui::black(), background.blend(overlay)) >= MIN_TEXT_CONTRAST_RATIO
+        );
+    }
+
+    #[test]
+    fn test_visual_indicator_settings_partial_override() {
+        let content: VisualIndicatorSettingsContent = serde_json::from_value(serde_json::json!({
+            "editing_color": "#ff8800",
+            "proposed_color": "not a color",
+            "warn_threshold": 90.0
+        }))
+        .unwrap();
+        let settings = VisualIndicatorSettings::from_content(&content);
+        let defaults = VisualIndicatorSettings::default();
+
+        assert_eq!(settings.palette, HighlightPalette::Default);
+        assert_eq!(settings.warn_threshold, 90.0);
+        assert_eq!(
+            agent_editing_style(&settings).background_color,
+            Some(Rgba::try_from("#ff8800").unwrap().into())
+        );
+        // Unset and invalid colors fall back to the defaults
+        assert_eq!(
+            selected_for_agent_style(&settings),
+            selected_for_agent_style(&defaults)
+        );
+        assert_eq!(
+            proposed_changes_style(&settings),
+            proposed_changes_style(&defaults)
+        );
+    }
+
+    #[test]
+    fn test_token_suggest_action() {
+        let info_at = |total_tokens| TokenInfo {
+            selection_tokens: 0,
+            total_tokens,
+            context_window: 10000,
+            breakdown: None,
+        };
+
+        assert_eq!(info_at(5000).suggest_action(DEFAULT_WARN_THRESHOLD), None);
+        assert_eq!(info_at(8000).suggest_action(DEFAULT_WARN_THRESHOLD), None);
+        assert_eq!(
+            info_at(8500)
+                .suggest_action(DEFAULT_WARN_THRESHOLD)
+                .as_deref(),
+            Some(
+                "Context is 85% full — consider starting a new thread or removing attached files."
+            )
+        );
+        assert_eq!(
+            info_at(9700)
+                .suggest_action(DEFAULT_WARN_THRESHOLD)
+                .as_deref(),
+            Some(
+                "Context is 97% full — start a new thread or remove attached files before the next message."
+            )
+        );
+
+        // The warning follows the configured threshold
+        assert!(info_at(6000).suggest_action(50.0).is_some());
+        assert_eq!(info_at(8500).suggest_action(90.0), None);
+    }
+
+    #[test]
+    fn test_token_breakdown() {
+        let breakdown = TokenBreakdown {
+            system: 1200,
+            history: 3000,
+            selection: 800,
+            response_reserve: 4000,
+        };
+        let info = TokenInfo {
+            selection_tokens: 800,
+            total_tokens: breakdown.total(),
+            context_window: 20000,
+            breakdown: Some(breakdown),
+        };
+        assert_eq!(breakdown.total(), 9000);
+        assert_eq!(info.percentage_used(), 45.0);
+        assert_eq!(
+            info.format_breakdown().as_deref(),
+            Some("System: 1200 • History: 3000 • Selection: 800 • Response reserve: 4000")
+        );
+
+        let info = TokenInfo {
+            breakdown: None,
+            ..info
+        };
+        assert_eq!(info.format_breakdown(), None);
+    }
+
+    #[test]
+    fn test_format_breakdown_table() {
+        let info = TokenInfo {
+            selection_tokens: 300,
+            total_tokens: 1500,
+            context_window: 100000,
+            breakdown: Some(TokenBreakdown {
+                system: 1200,
+                history: 0,
+                selection: 300,
+                response_reserve: 4096,
+            }),
+        };
+        let table = info.format_breakdown_table();
+        assert_eq!(
+            table,
+            [
+                "Component         Tokens  Percent",
+                "System              1200       1%",
+                "Selection            300       0%",
+                "Response reserve    4096       4%",
+            ]
+            .join("\n")
+        );
+        assert!(!table.contains("History"));
+        let line_width = table.lines().next().unwrap().len();
+        assert!(table.lines().all(|line| line.len() == line_width));
+
+        let empty_breakdown = TokenInfo {
+            breakdown: Some(TokenBreakdown::default()),
+            ..info.clone()
+        };
+        assert_eq!(empty_breakdown.format_breakdown_table(), "");
+        let no_breakdown = TokenInfo {
+            breakdown: None,
+            ..info
+        };
+        assert_eq!(no_breakdown.format_breakdown_table(), "");
+    }
+
+    #[test]
+    fn test_token_info_add_message_tokens() {
+        let mut info = TokenInfo {
+            selection_tokens: 0,
+            total_tokens: 1000,
+            context_window: 20000,
+            breakdown: Some(TokenBreakdown {
+                system: 1000,
+                ..TokenBreakdown::default()
+            }),
+        };
+        info.add_message_tokens(500, 1500);
+        info.add_message_tokens(300, 700);
+        assert_eq!(info.total_tokens, 4000);
+        assert_eq!(info.context_window, 20000);
+        let breakdown = info.breakdown.unwrap();
+        assert_eq!(breakdown.history, 3000);
+        assert_eq!(breakdown.total(), info.total_tokens);
+
+        info.selection_tokens = 200;
+        info.reset();
+        assert_eq!(info.total_tokens, 0);
+        assert_eq!(info.selection_tokens, 0);
+        assert_eq!(info.breakdown, None);
+        assert_eq!(info.context_window, 20000);
+
+        // Without a breakdown only the total grows
+        info.add_message_tokens(10, 20);
+        assert_eq!(info.total_tokens, 30);
+        assert_eq!(info.breakdown, None);
+    }
+
+    #[test]
+    fn test_token_warning_threshold() {
+        let info = TokenInfo {
+            selection_tokens: 0,
+            total_tokens: 8500,
+            context_window: 10000,
+            breakdown: None,
+        };
+        assert!(info.format_display().contains("⚠️"));
+        assert!(!info.format_display_with_warn_threshold(90.0).contains("⚠️"));
+    }
+
+    #[test]
+    fn test_gutter_spans_merge_multi_line_range() {
+        let spans = gutter_spans_for_rows(3..8, GutterIndicator::BeingEdited);
+        assert_eq!(
+            spans,
+            vec![GutterIndicatorSpan {
+                indicator: GutterIndicator::BeingEdited,
+                rows: 3..8,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_styles() {
+        assert!(diff_removed_style().strikethrough.is_some());
+        assert!(diff_added_style().strikethrough.is_none());
+
+        let added = diff_added_style().background_color.unwrap();
+        let removed = diff_removed_style().background_color.unwrap();
+        assert_ne!(added.h, removed.h);
+    }
+
+    #[test]
+    fn test_accepted_and_rejected_styles_differ() {
+        let accepted = accepted_change_style().background_color.unwrap();
+        let rejected = rejected_change_style().background_color.unwrap();
+        assert_ne!(accepted.h, rejected.h);
+        assert!(rejected_change_style().fade_out.is_some());
+    }
+
+    #[test]
+    fn test_gutter_spans_split_on_kind_and_gap() {
+        let spans = merge_gutter_rows([
+            (2, GutterIndicator::SelectedForAgent),
+            (0, GutterIndicator::SelectedForAgent),
+            (1, GutterIndicator::SelectedForAgent),
+            (3, GutterIndicator::Proposed),
+            (4, GutterIndicator::Proposed),
+            (6, GutterIndicator::Proposed),
+        ]);
+        assert_eq!(
+            spans,
+            vec![
+                GutterIndicatorSpan {
+                    indicator: GutterIndicator::SelectedForAgent,
+                    rows: 0..3,
+                },
+                GutterIndicatorSpan {
+                    indicator: GutterIndicator::Proposed,
+                    rows: 3..5,
+                },
+                GutterIndicatorSpan {
+                    indicator: GutterIndicator::Proposed,
+                    rows: 6..7,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_row_styles_picks_highest_priority() {
+        let settings = VisualIndicatorSettings::default();
+        let selected = GutterIndicator::SelectedForAgent.style(&settings);
+        let editing = GutterIndicator::BeingEdited.style(&settings);
+        let proposed = GutterIndicator::Proposed.style(&settings);
+
+        let styles = resolve_row_styles(
+            [
+                (0, GutterIndicator::SelectedForAgent),
+                (1, GutterIndicator::SelectedForAgent),
+                (1, GutterIndicator::BeingEdited),
+                (2, GutterIndicator::BeingEdited),
+                (2, GutterIndicator::SelectedForAgent),
+                (3, GutterIndicator::Proposed),
+                (3, GutterIndicator::SelectedForAgent),
+                (4, GutterIndicator::Proposed),
+                (4, GutterIndicator::BeingEdited),
+                (4, GutterIndicator::SelectedForAgent),
+            ],
+            &settings,
+        );
+        assert_eq!(
+            styles,
+            vec![
+                (0..1, selected),
+                (1..3, editing),
+                (3..4, proposed),
+                (4..5, editing),
+            ]
+        );
+    }
+
     #[test]
     fn test_selection_info_display() {
         let info = SelectionInfo {
             char_count: 150,
             line_count: 5,
+            word_count: 24,
             token_estimate: 40,
+            selection_count: 1,
         };
         let display = info.format_display();
         assert!(display.contains("5 lines"));
+        assert!(display.contains("24 words"));
         assert!(display.contains("150 chars"));
         assert!(display.contains("40 tokens"));
+        assert!(!display.contains("selections"));
+    }
+
+    #[test]
+    fn test_selection_stats_cache() {
+        let mut cache = SelectionStatsCache::default();
+        assert_eq!(cache.get("let a = 1;").word_count, 4);
+        assert_eq!(cache.get("let a = 1;").word_count, 4);
+        assert_eq!(cache.compute_count, 1);
+
+        assert_eq!(cache.get("let a = 1;\nlet b = 2;").line_count, 2);
+        assert_eq!(cache.compute_count, 2);
+        cache.get("let a = 1;\nlet b = 2;");
+        assert_eq!(cache.compute_count, 2);
+
+        cache.clear();
+        cache.get("let a = 1;\nlet b = 2;");
+        assert_eq!(cache.compute_count, 3);
+    }
+
+    #[test]
+    fn test_selection_info_merge() {
+        let infos = ["fn a() {}", "let b = 1;\nlet c = 2;", "x"].map(SelectionInfo::from_text);
+        let merged = SelectionInfo::merge(&infos);
+
+        assert_eq!(merged.selection_count, 3);
+        assert_eq!(merged.char_count, 9 + 21 + 1);
+        assert_eq!(merged.line_count, 1 + 2 + 1);
+        assert_eq!(merged.word_count, 3 + 8 + 1);
+        assert_eq!(
+            merged.token_estimate,
+            infos.iter().map(|info| info.token_estimate).sum::<u32>()
+        );
+        assert_eq!(
+            merged.format_display(),
+            "4 lines • 12 words • 31 chars • ~8 tokens (3 selections)"
+        );
+    }
+
+    #[test]
+    fn test_selection_looks_minified() {
+        let code = "fn main() {\n    let total = items.iter().map(|item| item.price).sum::<u32>();\n    println!(\"{total}\");\n}\n";
+        assert!(!SelectionInfo::from_text(code).looks_minified());
+        assert!(!SelectionInfo::from_text("").looks_minified());
+
+        let minified = "function a(b,c){return b+c};".repeat(40);
+        let info = SelectionInfo::from_text(&minified);
+        assert_eq!(info.line_count, 1);
+        assert!(info.looks_minified());
+        assert!(!info.looks_minified_with_threshold(minified.len()));
+        assert!(SelectionInfo::from_text(code).looks_minified_with_threshold(20));
+    }
+
+    #[gpui::test]
+    async fn test_count_tokens_async_matches_sync(cx: &mut gpui::TestAppContext) {
+        let text = "fn main() {\n    println!(\"Hello, world!\");\n}\n";
+
+        let count = cx
+            .update(|cx| TokenInfo::count_tokens_async(text.to_string(), "gpt-4o".to_string(), cx))
+            .await;
+        assert_eq!(count, TokenInfo::count_tokens(text, "gpt-4o"));
+        assert!(count > 0);
+
+        let count = cx
+            .update(|cx| {
+                TokenInfo::count_tokens_async(text.to_string(), "unknown-model".to_string(), cx)
+            })
+            .await;
+        assert_eq!(count, TokenInfo::estimate_tokens_from_text(text));
     }
 }
@@ -409,6 +409,32 @@ pub fn set_diagnostic_renderer(renderer: impl DiagnosticRenderer + 'static, cx:
     cx.set_global(GlobalDiagnosticRenderer(Arc::new(renderer)));
 }
 
+/// Contributes entries to the agent section of the editor's context menu
+pub trait AgentContextMenuRenderer {
+    fn render_entries(
+        &self,
+        menu: ui::ContextMenu,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> ui::ContextMenu;
+}
+
+pub(crate) struct GlobalAgentContextMenuRenderer(pub Arc<dyn AgentContextMenuRenderer>);
+
+impl GlobalAgentContextMenuRenderer {
+    pub(crate) fn global(cx: &App) -> Option<Arc<dyn AgentContextMenuRenderer>> {
+        cx.try_global::<Self>().map(|g| g.0.clone())
+    }
+}
+
+impl gpui::Global for GlobalAgentContextMenuRenderer {}
+pub fn set_agent_context_menu_renderer(
+    renderer: impl AgentContextMenuRenderer + 'static,
+    cx: &mut App,
+) {
+    cx.set_global(GlobalAgentContextMenuRenderer(Arc::new(renderer)));
+}
+
 pub struct SearchWithinRange;
 
 trait InvalidationRegion {
@@ -1,8 +1,8 @@
 use crate::{
     Copy, CopyAndTrim, CopyPermalinkToLine, Cut, DisplayPoint, DisplaySnapshot, Editor,
-    EvaluateSelectedText, FindAllReferences, GoToDeclaration, GoToDefinition, GoToImplementation,
-    GoToTypeDefinition, Paste, Rename, RevealInFileManager, RunToCursor, SelectMode,
-    SelectionEffects, SelectionExt, ToDisplayPoint, ToggleCodeActions,
+    EvaluateSelectedText, FindAllReferences, GlobalAgentContextMenuRenderer, GoToDeclaration,
+    GoToDefinition, GoToImplementation, GoToTypeDefinition, Paste, Rename, RevealInFileManager,
+    RunToCursor, SelectMode, SelectionEffects, SelectionExt, ToDisplayPoint, ToggleCodeActions,
     actions::{Format, FormatSelections},
     selections_collection::SelectionsCollection,
 };
@@ -13,7 +13,11 @@ use settings::Settings;
 use std::ops::Range;
 use text::PointUtf16;
 use workspace::OpenInTerminal;
-use zed_actions::agent::AddSelectionToThread;
+use zed_actions::agent::{
+    AddSelectionToThread, AskAgentAboutThis, ExplainSelection, FixDiagnosticsHere,
+    GenerateDocstring, GenerateFromTemplate, GenerateTestsForSelection, QuickEditWithAgent,
+    SummarizeSelection,
+};
 
 #[derive(Debug)]
 pub enum MenuPosition {
@@ -217,8 +221,17 @@ pub fn deploy_context_menu(
         let evaluate_selection = window.is_action_available(&EvaluateSelectedText, cx);
         let run_to_cursor = window.is_action_available(&RunToCursor, cx);
         let disable_ai = DisableAiSettings::get_global(cx).disable_ai;
+        let ask_agent = window.is_action_available(&AskAgentAboutThis, cx);
+        let explain_selection = window.is_action_available(&ExplainSelection, cx);
+        let summarize_selection = window.is_action_available(&SummarizeSelection, cx);
+        let quick_edit_with_agent = window.is_action_available(&QuickEditWithAgent, cx);
+        let generate_from_template = window.is_action_available(&GenerateFromTemplate, cx);
+        let fix_diagnostics_here = window.is_action_available(&FixDiagnosticsHere, cx);
+        let generate_tests = window.is_action_available(&GenerateTestsForSelection, cx);
+        let generate_docstring = window.is_action_available(&GenerateDocstring, cx);
+        let agent_context_menu_renderer = GlobalAgentContextMenuRenderer::global(cx);
 
-        ui::ContextMenu::build(window, cx, |menu, _window, _cx| {
+        ui::ContextMenu::build(window, cx, |menu, window, cx| {
             let builder = menu
                 .on_blur_subscription(Subscription::new(|| {}))
                 .when(run_to_cursor, |builder| {
@@ -252,6 +265,53 @@ pub fn deploy_context_menu(
                 .when(!disable_ai && has_selections, |this| {
                     this.action("Add to Agent Thread", Box::new(AddSelectionToThread))
                 })
+                .when(!disable_ai, |this| {
+                    this.action_disabled_when(
+                        !ask_agent,
+                        "Ask Agent About This",
+                        Box::new(AskAgentAboutThis),
+                    )
+                    .action_disabled_when(
+                        !explain_selection,
+                        "Explain This",
+                        Box::new(ExplainSelection),
+                    )
+                    .action_disabled_when(
+                        !summarize_selection,
+                        "Summarize This",
+                        Box::new(SummarizeSelection),
+                    )
+                    .action_disabled_when(
+                        !quick_edit_with_agent,
+                        "Quick Edit With Agent",
+                        Box::new(QuickEditWithAgent),
+                    )
+                    .action_disabled_when(
+                        !generate_from_template,
+                        "Generate From Template",
+                        Box::new(GenerateFromTemplate),
+                    )
+                    .action_disabled_when(
+                        !generate_tests,
+                        "Generate Tests",
+                        Box::new(GenerateTestsForSelection),
+                    )
+                    .action_disabled_when(
+                        !generate_docstring,
+                        "Generate Doc Comment",
+                        Box::new(GenerateDocstring),
+                    )
+                    .action_disabled_when(
+                        !fix_diagnostics_here,
+                        "Fix Diagnostics Here",
+                        Box::new(FixDiagnosticsHere),
+                    )
+                });
+            let builder = match agent_context_menu_renderer {
+                Some(renderer) if !disable_ai => renderer.render_entries(builder, window, cx),
+                _ => builder,
+            };
+            let builder = builder
                 .separator()
                 .action("Cut", Box::new(Cut))
                 .action("Copy", Box::new(Copy))
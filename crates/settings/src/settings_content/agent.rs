@@ -106,6 +106,48 @@ pub struct AgentSettingsContent {
     ///
     /// Default: 4
     pub message_editor_min_lines: Option<usize>,
+    /// Number of tokens kept free for the response when checking whether a quick edit
+    /// selection fits in the model's context window.
+    ///
+    /// Default: 4096
+    pub quick_edit_response_reserve: Option<u32>,
+    /// How many times a quick edit whose result can't be applied is retried with
+    /// expanded context before giving up.
+    ///
+    /// Default: 2
+    pub quick_edit_max_retries: Option<u32>,
+    /// How many submitted quick edit prompts are remembered per workspace, for recall
+    /// with the up and down arrows.
+    ///
+    /// Default: 20
+    pub quick_edit_prompt_history_size: Option<usize>,
+    /// How many lines of surrounding code are sent above and below a quick edit's
+    /// selection. Fewer are sent when they wouldn't fit the token budget.
+    ///
+    /// Default: 10
+    pub quick_edit_context_lines: Option<u32>,
+    /// Globs of files while editing which the agent must ask before running commands,
+    /// even in Write mode.
+    ///
+    /// Default: secrets, lockfiles and CI configuration, such as `**/.env` and `**/Cargo.lock`
+    pub sensitive_path_patterns: Option<Vec<String>>,
+    /// Custom templates offered by "Generate From Template", in addition to the built-in ones.
+    /// A template with the same name as a built-in template replaces it.
+    ///
+    /// Default: []
+    pub code_templates: Option<Vec<CodeTemplateContent>>,
+    /// Colors of the highlights shown around code the agent is working on.
+    pub visual_indicators: Option<VisualIndicatorSettingsContent>,
+    /// Mode the agent starts in when opened for a file whose language has no entry
+    /// in `language_default_modes`. One of "write", "ask", "plan", "quick-edit" or "manual".
+    ///
+    /// Default: "write"
+    pub default_mode: Option<String>,
+    /// Mode the agent starts in when opened for a file, keyed by language name.
+    /// Language names are matched case-insensitively.
+    ///
+    /// Default: {}
+    pub language_default_modes: Option<HashMap<String, String>>,
 }
 
 impl AgentSettingsContent {
@@ -165,6 +207,76 @@ impl AgentSettingsContent {
     }
 }
 
+#[with_fallible_options]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema, MergeFrom)]
+pub struct VisualIndicatorSettingsContent {
+    /// Palette the highlight colors are taken from.
+    ///
+    /// Default: "default"
+    pub palette: Option<HighlightPalette>,
+    /// Background of code selected for the agent, as a hex color like "#3b82f633".
+    /// Falls back to the palette's color when unset or invalid.
+    pub selected_color: Option<String>,
+    /// Background of lines the agent is editing, as a hex color.
+    /// Falls back to the palette's color when unset or invalid.
+    pub editing_color: Option<String>,
+    /// Background of changes the agent proposes, as a hex color.
+    /// Falls back to the palette's color when unset or invalid.
+    pub proposed_color: Option<String>,
+    /// Percentage of the context window above which token usage shows a warning.
+    ///
+    /// Default: 80
+    pub warn_threshold: Option<f32>,
+}
+
+#[with_fallible_options]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema, MergeFrom)]
+pub struct CodeTemplateContent {
+    /// Name shown in the template picker.
+    pub name: String,
+    /// Description shown below the name in the template picker.
+    #[serde(default)]
+    pub description: String,
+    /// Instruction sent to the model along with the selected code.
+    pub prompt_body: String,
+    /// Language the template is offered for, such as "Rust".
+    /// Offered for every language when unset.
+    pub target_language: Option<String>,
+    /// Where the generated code goes relative to the selection.
+    ///
+    /// Default: "replace_selection"
+    pub insertion: Option<CodeTemplateInsertion>,
+}
+
+#[derive(
+    Copy, Clone, Default, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, MergeFrom,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeTemplateInsertion {
+    /// Replace the selected code
+    #[default]
+    ReplaceSelection,
+    /// Insert on new lines above the selection's first line
+    InsertAbove,
+    /// Insert on new lines below the selection's last line
+    InsertBelow,
+    /// Insert at the end of the file
+    AppendToFile,
+}
+
+#[derive(
+    Copy, Clone, Default, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, MergeFrom,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightPalette {
+    /// Yellow, blue and green overlays
+    #[default]
+    Default,
+    /// Blue, orange and purple overlays with distinct lightness, plus underlines,
+    /// so that states can be told apart without relying on hue
+    ColorblindSafe,
+}
+
 #[with_fallible_options]
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema, MergeFrom)]
 pub struct AgentProfileContent {
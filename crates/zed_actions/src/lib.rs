@@ -346,6 +346,22 @@ pub mod agent {
             AddSelectionToThread,
             /// Resets the agent panel zoom levels (agent UI and buffer font sizes).
             ResetAgentZoom,
+            /// Asks the agent about the selected code.
+            AskAgentAboutThis,
+            /// Opens quick edit with the agent for the selected code.
+            QuickEditWithAgent,
+            /// Generates code for the selection from a template.
+            GenerateFromTemplate,
+            /// Opens quick edit with the agent to fix the diagnostics in the selected code.
+            FixDiagnosticsHere,
+            /// Asks the agent to explain the selected code without allowing edits.
+            ExplainSelection,
+            /// Asks the agent for a brief summary of the selected code, or of the whole file.
+            SummarizeSelection,
+            /// Opens quick edit with the agent to generate tests for the selected code.
+            GenerateTestsForSelection,
+            /// Opens quick edit with the agent to insert a doc comment above the selected symbol.
+            GenerateDocstring,
         ]
     );
 }